@@ -34,6 +34,9 @@ impl From<ModelEvent> for HubEvent<EntityRef> {
 }
 
 /// From ConvEvt
+///   hub: convHub
+/// topic:  "conv_work_done"             (ConvEvent.name(), e.g., "conv_msg_chunk" for the streamed content)
+/// detail: the event data               (e.g., {conv_id: 1, msg_id: 12, delta: "Hello"})
 impl From<ConvEvent> for HubEvent<Value> {
 	fn from(conv_evt: ConvEvent) -> Self {
 		let topic = conv_evt.name().to_string();
@@ -20,6 +20,9 @@ use lib_workers::dsource_worker::DSourceWorker;
 
 // endregion: --- Modules
 
+/// The env var of the embed model of the drive parts vectors (e.g., `ollama::nomic-embed-text`).
+const ENV_EMBED_MODEL: &str = "FC_EMBED_MODEL";
+
 const TRACE_FILTER: &str = r#"
 app_desktop=debug,
 lib_ais=debug,
@@ -35,16 +38,20 @@ pub async fn run() -> Result<()> {
 	lib_utils::trace::init_trace();
 
 	// -- Setup ModelManager
-	let mm = ModelManager::new().await?;
+	// Note: The agent chains get validated on all of the agent updates.
+	let mm = ModelManager::new().await?.with_agent_chain_check(lib_ais::agent_chain_check());
 
 	// -- Init the AI Manager
-	let aim = lib_ais::AiManager::default();
+	let mut aim = lib_ais::AiManager::default();
+	if let Some(embed_model) = std::env::var(ENV_EMBED_MODEL).ok().filter(|m| !m.trim().is_empty()) {
+		aim = aim.with_embed_model(embed_model);
+	}
 
 	// -- Init the Dbs
 	init_main_db_all(&mm).await?;
 
 	// -- Init the workers
-	DSourceWorker::start(mm.clone())?;
+	DSourceWorker::start(mm.clone(), aim.clone())?;
 	ConvWorker::start(mm.clone(), aim.clone())?;
 
 	// -- Setup RPC States
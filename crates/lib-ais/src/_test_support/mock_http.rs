@@ -0,0 +1,221 @@
+//! Minimal local HTTP server used to test the AI clients without hitting any remote provider.
+//!
+//! Each route replays a canned body (JSON or recorded SSE). Every connection is closed after
+//! the response, which is enough for the reqwest based clients.
+
+use crate::_test_support::Result;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// region:    --- Types
+
+#[derive(Debug, Clone)]
+pub struct MockRoute {
+	pub method: &'static str,
+	pub path: &'static str,
+	pub status: u16,
+	pub content_type: &'static str,
+	pub body: String,
+	/// Wait before responding (e.g., to simulate a long generation).
+	pub delay: Option<Duration>,
+	/// Only respond to this number of requests, then the next matching route responds
+	/// (e.g., a failing response, then a successful one).
+	pub max_hits: Option<usize>,
+}
+
+impl MockRoute {
+	pub fn json(method: &'static str, path: &'static str, body: impl Into<String>) -> Self {
+		Self {
+			method,
+			path,
+			status: 200,
+			content_type: "application/json",
+			body: body.into(),
+			delay: None,
+			max_hits: None,
+		}
+	}
+
+	pub fn sse(method: &'static str, path: &'static str, body: impl Into<String>) -> Self {
+		Self {
+			method,
+			path,
+			status: 200,
+			content_type: "text/event-stream",
+			body: body.into(),
+			delay: None,
+			max_hits: None,
+		}
+	}
+
+	pub fn with_status(mut self, status: u16) -> Self {
+		self.status = status;
+		self
+	}
+
+	pub fn with_delay(mut self, delay: Duration) -> Self {
+		self.delay = Some(delay);
+		self
+	}
+
+	pub fn with_max_hits(mut self, max_hits: usize) -> Self {
+		self.max_hits = Some(max_hits);
+		self
+	}
+}
+
+/// A request received by the mock server (for assertions).
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+	pub method: String,
+	pub path: String,
+	pub headers: Vec<(String, String)>,
+	pub body: String,
+}
+
+impl MockRequest {
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+
+	pub fn body_json(&self) -> Result<serde_json::Value> {
+		Ok(serde_json::from_str(&self.body)?)
+	}
+}
+
+// endregion: --- Types
+
+// region:    --- MockHttpServer
+
+pub struct MockHttpServer {
+	addr: SocketAddr,
+	requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockHttpServer {
+	pub async fn start(routes: Vec<MockRoute>) -> Result<Self> {
+		let listener = TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+		let requests: Arc<Mutex<Vec<MockRequest>>> = Default::default();
+		let route_hits: Arc<Mutex<Vec<usize>>> = Arc::new(Mutex::new(vec![0; routes.len()]));
+		let routes = Arc::new(routes);
+
+		let requests_ = requests.clone();
+		tokio::spawn(async move {
+			while let Ok((stream, _)) = listener.accept().await {
+				let routes = routes.clone();
+				let route_hits = route_hits.clone();
+				let requests = requests_.clone();
+				tokio::spawn(async move {
+					let _ = handle_conn(stream, &routes, &route_hits, &requests).await;
+				});
+			}
+		});
+
+		Ok(Self { addr, requests })
+	}
+
+	/// e.g., `http://127.0.0.1:PORT`
+	pub fn base_url(&self) -> String {
+		format!("http://{}", self.addr)
+	}
+
+	pub fn requests(&self) -> Vec<MockRequest> {
+		self.requests.lock().map(|r| r.clone()).unwrap_or_default()
+	}
+}
+
+async fn handle_conn(
+	mut stream: TcpStream,
+	routes: &[MockRoute],
+	route_hits: &Mutex<Vec<usize>>,
+	requests: &Mutex<Vec<MockRequest>>,
+) -> std::io::Result<()> {
+	// -- Read the head
+	let mut buf: Vec<u8> = Vec::new();
+	let mut chunk = [0u8; 4096];
+	let head_end = loop {
+		let n = stream.read(&mut chunk).await?;
+		if n == 0 {
+			return Ok(());
+		}
+		buf.extend_from_slice(&chunk[..n]);
+		if let Some(idx) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+			break idx + 4;
+		}
+	};
+
+	let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+	let mut lines = head.lines();
+	let mut first = lines.next().unwrap_or_default().split_whitespace();
+	let method = first.next().unwrap_or_default().to_string();
+	let path = first.next().unwrap_or_default();
+	let path = path.split('?').next().unwrap_or_default().to_string();
+	let headers: Vec<(String, String)> = lines
+		.filter_map(|l| l.split_once(':'))
+		.map(|(n, v)| (n.trim().to_string(), v.trim().to_string()))
+		.collect();
+
+	// -- Read the body
+	let content_length = headers
+		.iter()
+		.find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+		.and_then(|(_, v)| v.parse::<usize>().ok())
+		.unwrap_or(0);
+	let mut body = buf[head_end..].to_vec();
+	while body.len() < content_length {
+		let n = stream.read(&mut chunk).await?;
+		if n == 0 {
+			break;
+		}
+		body.extend_from_slice(&chunk[..n]);
+	}
+	let body = String::from_utf8_lossy(&body).to_string();
+
+	// -- Route
+	let route = match route_hits.lock() {
+		Ok(mut route_hits) => {
+			let idx = routes.iter().enumerate().position(|(idx, r)| {
+				r.method == method && r.path == path && r.max_hits.map_or(true, |max| route_hits[idx] < max)
+			});
+			if let Some(idx) = idx {
+				route_hits[idx] += 1;
+			}
+			idx.map(|idx| &routes[idx])
+		}
+		Err(_) => None,
+	};
+	if let Ok(mut requests) = requests.lock() {
+		requests.push(MockRequest {
+			method,
+			path,
+			headers,
+			body,
+		});
+	}
+
+	if let Some(delay) = route.and_then(|r| r.delay) {
+		tokio::time::sleep(delay).await;
+	}
+
+	let (status, content_type, body) = match route {
+		Some(route) => (route.status, route.content_type, route.body.as_str()),
+		None => (404, "text/plain", "not found"),
+	};
+	let res = format!(
+		"HTTP/1.1 {status} MOCK\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+		body.len()
+	);
+	stream.write_all(res.as_bytes()).await?;
+	stream.shutdown().await?;
+
+	Ok(())
+}
+
+// endregion: --- MockHttpServer
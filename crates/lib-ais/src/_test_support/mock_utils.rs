@@ -28,6 +28,7 @@ pub fn mock_echo_agent_c(name: impl Into<String>, inst: impl Into<String>) -> Ag
 		out_format: Some(OutFormat::Text),
 		kind: None,
 		provider: None,
+		logic_tool: None,
 	}
 }
 
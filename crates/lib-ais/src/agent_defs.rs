@@ -44,5 +44,6 @@ interface ToolCall {
 		out_format: Some(OutFormat::Json),
 		kind: None,
 		provider: None,
+		logic_tool: None,
 	}
 }
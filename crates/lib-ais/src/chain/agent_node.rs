@@ -1,25 +1,58 @@
-use crate::chain::{CondNode, InputContent};
+use crate::chain::{ChainVars, CondNode, InputContent};
+use crate::Error;
 use core::fmt;
 use serde::de::{MapAccess, Visitor};
 use serde::{Deserialize, Deserializer};
 
 #[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 pub struct AgentNode {
 	#[serde(default)]
 	pub agent: AgentRef,
+	/// Bind the node input to this chain variable.
 	pub name_input: Option<String>,
+	/// Bind the node output to this chain variable.
 	pub name_output: Option<String>,
+	/// The node input, with the `${name}` chain variables (e.g., `"${original_input}"`).
+	/// Default to the previous output.
+	pub input: Option<String>,
 	pub when: Option<CondNode>,
 }
 
 impl AgentNode {
-	#[allow(unused)] // For early development.
+	/// Returns true if the node has no `when`, or if its `when` matches the input.
 	pub fn should_activate(&self, input_content: &InputContent) -> bool {
 		match self.when.as_ref() {
 			Some(when_activation) => when_activation.matches_input(input_content),
 			None => true,
 		}
 	}
+
+	/// Returns the node input from its `input` template, or None if the node has no `input`.
+	/// Fails if a referenced chain variable is not bound.
+	pub fn resolve_input(&self, vars: &ChainVars) -> crate::Result<Option<String>> {
+		let Some(tmpl) = self.input.as_deref() else {
+			return Ok(None);
+		};
+
+		let mut res = String::new();
+		let mut rest = tmpl;
+		while let Some(start) = rest.find("${") {
+			let Some(len) = rest[start..].find('}') else {
+				break;
+			};
+			let name = rest[start + 2..start + len].trim();
+			let val = vars
+				.get(name)
+				.ok_or_else(|| Error::ChainVarNotFound { name: name.to_string() })?;
+			res.push_str(&rest[..start]);
+			res.push_str(val);
+			rest = &rest[start + len + 1..];
+		}
+		res.push_str(rest);
+
+		Ok(Some(res))
+	}
 }
 
 #[derive(Debug, Default)]
@@ -31,6 +64,11 @@ pub enum AgentRef {
 	Id(i64),
 }
 
+impl AgentRef {
+	/// The keys of the agent ref object (only one of them per ref).
+	pub const KEYS: &'static [&'static str] = &["uid", "name", "id"];
+}
+
 // region:    --- AgentRef Deserializer
 
 struct AgentRefVisitor;
@@ -57,29 +95,14 @@ impl<'de> Visitor<'de> for AgentRefVisitor {
 	where
 		V: MapAccess<'de>,
 	{
-		let mut agent_ref = AgentRef::Same; // default
-		while let Some(key) = map.next_key::<String>()? {
-			match key.as_str() {
-				"uid" => {
-					let uid: String = map.next_value()?;
-					agent_ref = AgentRef::Uid(uid);
-					break; // Assuming only one of these keys is present
-				}
-				"id" => {
-					let id: i64 = map.next_value()?;
-					agent_ref = AgentRef::Id(id);
-					break; // Assuming only one of these keys is present
-				}
-				"name" => {
-					let name: String = map.next_value()?;
-					agent_ref = AgentRef::Name(name);
-					break; // Assuming only one of these keys is present
-				}
-				_ => {
-					let _: serde::de::IgnoredAny = map.next_value()?; // Ignore any unknown fields
-				}
-			}
-		}
+		// Note: Assuming only one of these keys is present (an unknown key is an error).
+		let agent_ref = match map.next_key::<String>()?.as_deref() {
+			Some("uid") => AgentRef::Uid(map.next_value()?),
+			Some("id") => AgentRef::Id(map.next_value()?),
+			Some("name") => AgentRef::Name(map.next_value()?),
+			Some(key) => return Err(serde::de::Error::unknown_field(key, AgentRef::KEYS)),
+			None => AgentRef::Same, // default
+		};
 		Ok(agent_ref)
 	}
 }
@@ -140,6 +163,28 @@ mod tests {
 		Ok(())
 	}
 
+	#[test]
+	fn test_agent_node_resolve_input() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_node: AgentNode = serde_json::from_str(r#"{"input": "Question: ${original_input} (${lang})"}"#)?;
+		let fx_vars = ChainVars::from([
+			("original_input".to_string(), "Hello".to_string()),
+			("lang".to_string(), "fr".to_string()),
+		]);
+
+		// -- Exec
+		let input = fx_node.resolve_input(&fx_vars)?;
+
+		// -- Check
+		assert_eq!(input.as_deref(), Some("Question: Hello (fr)"));
+		assert!(
+			fx_node.resolve_input(&ChainVars::new()).is_err(),
+			"unbound var should fail"
+		);
+
+		Ok(())
+	}
+
 	#[test]
 	fn test_agent_node_deserialize_with_agent_self() -> Result<()> {
 		// -- Fixtures
@@ -1,15 +1,30 @@
-use crate::chain::{ChainNode, CondNode};
+use crate::chain::{ChainNode, CondNode, InputContent};
 use serde::Deserialize;
 use std::slice::Iter;
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct BranchNode {
 	pub branch: Vec<BranchArm>,
+	#[serde(default, rename = "match")]
+	pub match_mode: BranchMatch,
+}
+
+/// How the branch arms get selected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BranchMatch {
+	/// Run the first matching arm (or the `else` arm if none match).
+	#[default]
+	First,
+	/// Run each matching arm, in order (or the `else` arm if none match).
+	/// Note: Each arm cond is evaluated when reached, so against the output of the previous arm that ran.
+	All,
 }
 
 // impl iter for branch_node
 impl BranchNode {
-	pub fn iter(&self) -> Iter<BranchArm> {
+	pub fn iter(&self) -> Iter<'_, BranchArm> {
 		self.branch.iter()
 	}
 }
@@ -27,15 +42,54 @@ impl BranchNode {
 	pub fn get_arm(&self, idx: usize) -> Option<&BranchArm> {
 		self.branch.get(idx)
 	}
+
+	/// Returns the idx of the arm to enter the branch with,
+	/// the first matching arm, or the `else` arm if none match.
+	pub fn first_arm_idx(&self, input: &InputContent) -> Option<usize> {
+		self.iter()
+			.position(|arm| arm.matches_input(input))
+			.or_else(|| self.iter().position(|arm| arm.is_else))
+	}
+
+	/// Returns the idx of the next matching arm from `from_idx` (inclusive), when in `"match": "all"` mode.
+	/// Note: The `else` arm is never a next arm (it only runs when no arm matched).
+	pub fn next_all_arm_idx(&self, from_idx: usize, input: &InputContent) -> Option<usize> {
+		if self.match_mode != BranchMatch::All {
+			return None;
+		}
+		self.branch
+			.get(from_idx..)?
+			.iter()
+			.position(|arm| arm.matches_input(input))
+			.map(|idx| from_idx + idx)
+	}
+
+	/// Returns true if there is an arm (not `else`) from `from_idx` (inclusive) to be evaluated in `"match": "all"` mode.
+	pub(super) fn has_all_arm_from(&self, from_idx: usize) -> bool {
+		self.match_mode == BranchMatch::All
+			&& self.branch.get(from_idx..).is_some_and(|arms| arms.iter().any(|a| !a.is_else))
+	}
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct BranchArm {
+	/// Note: An arm without `cond` (or without `cond.input`) never matches (unless `else`).
+	#[serde(default)]
 	pub cond: CondNode,
+	/// The fallback arm, taken when no other arm matches.
+	#[serde(default, rename = "else")]
+	pub is_else: bool,
 	#[serde(default)] // this will make null/absent to do empty vec
 	pub nodes: Vec<ChainNode>,
 }
 
+impl BranchArm {
+	pub fn matches_input(&self, input: &InputContent) -> bool {
+		!self.is_else && self.cond.matches_input(input)
+	}
+}
+
 // region:    --- Tests
 
 #[cfg(test)]
@@ -44,8 +98,57 @@ mod tests {
 	type Result<T> = core::result::Result<T, Error>; // For tests.
 
 	use super::*;
+	use crate::_test_support::mock_name_from_agent_node;
+	use crate::chain::{Chain, ChainCursor};
 	use lib_utils::o_wrap;
-	use serde_json::from_str;
+	use lib_utils::x_vec::XStringVec;
+	use serde_json::{from_str, json};
+
+	/// The chain with a branch of the given match mode (`"first"` or `"all"`).
+	fn fx_chain(match_mode: &str) -> String {
+		format!(
+			r#"
+{{
+	"nodes": [
+		{{ "agent": {{ "name": "A" }} }},
+		{{
+			"match": "{match_mode}",
+			"branch": [
+				{{
+					"cond": {{ "input": {{ "json_matches": {{ "pointer": "/category", "value": 1 }} }} }},
+					"nodes": [{{ "agent": {{ "name": "B" }} }}]
+				}},
+				{{
+					"cond": {{ "input": {{ "is_json": true }} }},
+					"nodes": [{{ "agent": {{ "name": "C" }} }}, {{ "agent": {{ "name": "C2" }} }}]
+				}},
+				{{
+					"else": true,
+					"nodes": [{{ "agent": {{ "name": "D" }} }}]
+				}}
+			]
+		}},
+		{{ "agent": {{ "name": "E" }} }}
+	]
+}}
+		"#
+		)
+	}
+
+	/// Returns the names of the agents walked through by the cursor for an input.
+	fn walk_agent_names(chain: &Chain, input: &InputContent) -> Result<Vec<String>> {
+		let mut names = Vec::new();
+		let mut cursor = ChainCursor::default();
+		while let Some(next_cursor) = chain.next_agent_cursor(&cursor, input) {
+			let agent_node = chain.get_agent_node(&next_cursor).ok_or("Should have agent node")?;
+			names.push(mock_name_from_agent_node(agent_node));
+			cursor = next_cursor;
+			if names.len() > 10 {
+				return Err("Seems to be an infinite loop.".into());
+			}
+		}
+		Ok(names)
+	}
 
 	#[test]
 	fn test_branch_node_ok() -> Result<()> {
@@ -83,7 +186,67 @@ mod tests {
 			o_wrap!(first_branch.cond.input?.json_matches?.pop()).ok_or("Should have one json_matches")?;
 
 		assert_eq!(json_matches.pointer, "/category");
-		assert_eq!(json_matches.value, 1);
+		assert_eq!(json_matches.value, Some(json!(1)));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_branch_node_match_first_cursor() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_chain: Chain = from_str(&fx_chain("first"))?;
+
+		// -- Exec & Check
+		// both arm 0 and 1 match, only the first one runs
+		let names = walk_agent_names(&fx_chain, &InputContent::new(r#"{"category": 1}"#))?;
+		assert_eq!(names.x_strs(), ["A", "B", "E"]);
+		let names = walk_agent_names(&fx_chain, &InputContent::new(r#"{"category": 2}"#))?;
+		assert_eq!(names.x_strs(), ["A", "C", "C2", "E"]);
+		// no arm match, the else arm runs
+		let names = walk_agent_names(&fx_chain, &InputContent::new("Some text"))?;
+		assert_eq!(names.x_strs(), ["A", "D", "E"]);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_branch_node_match_all_cursor() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_chain: Chain = from_str(&fx_chain("all"))?;
+
+		// -- Exec & Check
+		// each matching arm runs in order, but not the else arm
+		let names = walk_agent_names(&fx_chain, &InputContent::new(r#"{"category": 1}"#))?;
+		assert_eq!(names.x_strs(), ["A", "B", "C", "C2", "E"]);
+		let names = walk_agent_names(&fx_chain, &InputContent::new(r#"{"category": 2}"#))?;
+		assert_eq!(names.x_strs(), ["A", "C", "C2", "E"]);
+		// no arm match, the else arm runs
+		let names = walk_agent_names(&fx_chain, &InputContent::new("Some text"))?;
+		assert_eq!(names.x_strs(), ["A", "D", "E"]);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_branch_node_empty_arm_and_next_branch_cursor() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_chain: Chain = from_str(
+			r#"
+{
+	"nodes": [
+		{ "branch": [{ "cond": { "input": { "is_json": false } }, "nodes": [] }] },
+		{ "branch": [{ "else": true, "nodes": [{ "agent": { "name": "B" } }] }] },
+		{ "agent": { "name": "C" } }
+	]
+}
+			"#,
+		)?;
+
+		// -- Exec
+		let names = walk_agent_names(&fx_chain, &InputContent::new("Some text"))?;
+
+		// -- Check
+		assert_eq!(names.x_strs(), ["B", "C"]);
 
 		Ok(())
 	}
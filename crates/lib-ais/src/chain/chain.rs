@@ -1,7 +1,10 @@
-use crate::chain::{AgentNode, BranchArm, ChainNode, InputContent};
+use crate::chain::{AgentNode, AgentRef, BranchArm, ChainNode, InputContent, LoopNode, MapNode, TransformNode};
 use crate::{Error, Result};
 use lib_core::model::agent::Agent;
+use lib_utils::hbs;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 
 // region:    --- Chain CallStack/Cursor
 
@@ -43,10 +46,39 @@ impl ChainCallStack {
 	}
 }
 
+/// The chain variables, bound by the `name_input` / `name_output` of the agent nodes.
+pub type ChainVars = BTreeMap<String, String>;
+
+/// Render the handlebars template with the `input` and the chain variables (e.g., `{{original_input}}`).
+/// Note: The variable values are parsed like the input (json or text), and `input` takes precedence.
+pub fn render_chain_tmpl(tmpl: &str, input: InputContent, vars: &ChainVars) -> hbs::Result<String> {
+	let mut data = serde_json::Map::new();
+	for (name, val) in vars.iter() {
+		data.insert(name.to_string(), InputContent::new(val).into_value());
+	}
+	data.insert("input".to_string(), input.into_value());
+
+	hbs::render(tmpl, Value::Object(data))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StackItem {
 	pub agent_uid: String,
 	pub cursor: ChainCursor,
+
+	/// The variables bound so far in this chain (scoped to this item, not seen by the nested agent chains).
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub vars: ChainVars,
+
+	/// The input of the node at the cursor (or of the chain when at start),
+	/// when not the previous output (e.g., agent node `"input": "${original_input}"`).
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub input: Option<String>,
+
+	/// The idxs of the map nodes down to the sub-chain of this item (each in the chain of the previous one),
+	/// empty for the agent chain itself (see `Chain::scope_chain`).
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub scope: Vec<Vec<usize>>,
 }
 
 impl StackItem {
@@ -54,24 +86,59 @@ impl StackItem {
 		Self {
 			agent_uid: uid.into(),
 			cursor: ChainCursor::default(),
+			vars: ChainVars::new(),
+			input: None,
+			scope: Vec::new(),
 		}
 	}
 	pub fn new(uuid: impl Into<String>, cursor: ChainCursor) -> Self {
 		Self {
 			agent_uid: uuid.into(),
 			cursor,
+			vars: ChainVars::new(),
+			input: None,
+			scope: Vec::new(),
 		}
 	}
+
+	pub fn with_vars(mut self, vars: ChainVars) -> Self {
+		self.vars = vars;
+		self
+	}
+
+	pub fn with_input(mut self, input: Option<String>) -> Self {
+		self.input = input;
+		self
+	}
+
+	pub fn with_scope(mut self, scope: Vec<Vec<usize>>) -> Self {
+		self.scope = scope;
+		self
+	}
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ChainCursor {
 	pub idxs: Vec<usize>,
+
+	/// The loop nodes the cursor is in (outer first).
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub loops: Vec<LoopState>,
+}
+
+/// The iteration state of a loop node the cursor is in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopState {
+	/// The loop node idxs.
+	pub idxs: Vec<usize>,
+	/// The current iteration (1 based).
+	pub iter: usize,
 }
 
 // endregion: --- Chain Stack/Cursor
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Chain {
 	pub nodes: Vec<ChainNode>,
 }
@@ -123,13 +190,52 @@ impl Chain {
 		}
 	}
 
-	/// Get the ChainCursor for the next AgentNode
+	/// Get the pointed MapNode for a given cursor (None if no map node at this cursor).
+	pub fn get_map_node(&self, cursor: &ChainCursor) -> Option<&MapNode> {
+		match self.get_el(&cursor.idxs)? {
+			ChainEl::Node(ChainNode::Map(map_node)) => Some(map_node),
+			_ => None,
+		}
+	}
+
+	/// Get the pointed TransformNode for a given cursor (None if no transform node at this cursor).
+	pub fn get_transform_node(&self, cursor: &ChainCursor) -> Option<&TransformNode> {
+		match self.get_el(&cursor.idxs)? {
+			ChainEl::Node(ChainNode::Transform(transform_node)) => Some(transform_node),
+			_ => None,
+		}
+	}
+
+	/// Returns the `name_output` of the agent, map, or transform node at the cursor.
+	pub fn get_name_output(&self, cursor: &ChainCursor) -> Option<&str> {
+		match self.get_el(&cursor.idxs)? {
+			ChainEl::Node(ChainNode::Agent(agent_node)) => agent_node.name_output.as_deref(),
+			ChainEl::Node(ChainNode::Map(map_node)) => map_node.name_output.as_deref(),
+			ChainEl::Node(ChainNode::Transform(transform_node)) => transform_node.name_output.as_deref(),
+			_ => None,
+		}
+	}
+
+	/// Returns the sub-chain of the map nodes `scope` (see `StackItem::scope`), or self if empty.
+	pub fn scope_chain(&self, scope: &[Vec<usize>]) -> Result<&Chain> {
+		let mut chain = self;
+		for idxs in scope {
+			chain = match chain.get_el(idxs) {
+				Some(ChainEl::Node(ChainNode::Map(map_node))) => &map_node.map,
+				_ => return Err(Error::ChainMapNotFound { idxs: idxs.clone() }),
+			};
+		}
+		Ok(chain)
+	}
+
+	/// Get the ChainCursor for the next AgentNode (or MapNode, or TransformNode)
 	/// - Only turn a ChainCursor if there is a next AgentNode for the given input
 	/// - Will evaluation the input content when getting into a branch node.
 	pub fn next_agent_cursor(&self, cursor: &ChainCursor, input: &InputContent) -> Option<ChainCursor> {
 		// -- Extract vals from cursor
 		// returns early if no idxs
 		let mut idxs = cursor.idxs.clone();
+		let mut loops = cursor.loops.clone();
 
 		let mut query_next = true;
 
@@ -142,27 +248,72 @@ impl Chain {
 				idxs
 			};
 
-			let el = self.get_el(&idxs)?;
+			// Note: Past the end of the nodes (e.g., empty arm), so the next node gets queried.
+			let Some(el) = self.get_el(&idxs) else {
+				continue;
+			};
 
 			match el {
-				// if the pointed el is an agent,
-				ChainEl::Node(ChainNode::Agent(_agent_node)) => break,
+				// if the pointed el is an agent to activate, this is the next one
+				ChainEl::Node(ChainNode::Agent(agent_node)) if agent_node.should_activate(input) => break,
+				// otherwise (`when` not matching), the agent gets skipped
+				ChainEl::Node(ChainNode::Agent(_)) => (),
+
+				// a map node runs as one step (its sub-chain is run per item, see `StackItem::scope`)
+				ChainEl::Node(ChainNode::Map(_)) => break,
+
+				// a transform node runs as one step too (without any model call)
+				ChainEl::Node(ChainNode::Transform(_)) => break,
+
+				// if a loop, either we enter it, or (back at the end of an iteration, see `next_node_idxs`)
+				// we go for another iteration (if not done), otherwise the next query goes after the loop
+				ChainEl::Node(ChainNode::Loop(loop_node)) => {
+					let cur_loop = loops.last_mut().filter(|l| l.idxs == idxs);
+					let enter = match cur_loop {
+						None => {
+							loops.push(LoopState {
+								idxs: idxs.clone(),
+								iter: 1,
+							});
+							true
+						}
+						Some(cur_loop) if !loop_node.is_done(cur_loop.iter, input) => {
+							cur_loop.iter += 1;
+							true
+						}
+						Some(_) => {
+							loops.pop();
+							false
+						}
+					};
+					// Note: An empty loop is entered, but ends right away (as its end is queried next).
+					if enter {
+						idxs.push(0);
+						query_next = false;
+					}
+				}
 
+				// if a branch, we go into the arm to run (if none, the next query goes after the branch)
 				ChainEl::Node(ChainNode::Branch(branch_node)) => {
-					// FIXME: Needs to evaluate input and cond of arm
-					for (arm_idx, arm) in branch_node.iter().enumerate() {
-						if arm.cond.matches_input(input) {
-							idxs.push(arm_idx); // we go into the arm idx
-							idxs.push(0); // point to the first eventual item (if none, it will exit the branch)
-							query_next = false;
-						}
+					if let Some(arm_idx) = branch_node.first_arm_idx(input) {
+						idxs.push(arm_idx); // we go into the arm idx
+						idxs.push(0); // point to the first eventual item (if none, it will exit the branch)
+						query_next = false;
 					}
 				}
 
-				// Note: Should not reach this point, and the next_node_idxs will not drill down into branch arms
+				// Note: Only reached in the `"match": "all"` mode, after an arm ran (see `next_node_idxs`),
+				//       so we go into the next matching arm, if any.
 				ChainEl::Arm(_) => {
-					println!("->> ChainEl::Arm SHOULD NOT BE REACHED");
-					return None;
+					let from_arm_idx = idxs.pop()?; // back to the branch
+					let Some(ChainEl::Node(ChainNode::Branch(branch_node))) = self.get_el(&idxs) else {
+						return None;
+					};
+					if let Some(arm_idx) = branch_node.next_all_arm_idx(from_arm_idx, input) {
+						idxs.push(arm_idx);
+						idxs.push(0);
+						query_next = false;
+					}
 				}
 
 				// Note: Should not reach this point, as this is past the starting point.
@@ -171,7 +322,39 @@ impl Chain {
 			}
 		}
 
-		Some(ChainCursor { idxs })
+		Some(ChainCursor { idxs, loops })
+	}
+
+	/// Returns all the agent refs of the chain agent nodes (including the ones in the branch arms, maps, and loops).
+	pub fn agent_refs(&self) -> Vec<&AgentRef> {
+		let mut refs = Vec::new();
+		let mut todo: Vec<&ChainNode> = self.nodes.iter().collect();
+		while let Some(node) = todo.pop() {
+			match node {
+				ChainNode::Agent(agent_node) => refs.push(&agent_node.agent),
+				ChainNode::Branch(branch_node) => todo.extend(branch_node.iter().flat_map(|arm| arm.nodes.iter())),
+				ChainNode::Map(map_node) => todo.extend(map_node.map.nodes.iter()),
+				ChainNode::Loop(loop_node) => todo.extend(loop_node.nodes.iter()),
+				ChainNode::Transform(_) => (),
+			}
+		}
+		refs
+	}
+
+	/// Returns the branch arms the cursor is in, as `(branch_idxs, arm_idx)` (outer first).
+	/// Note: In the cursor idxs, the idx after the branch node idxs is the arm idx.
+	pub fn cursor_arms(&self, cursor: &ChainCursor) -> Vec<(Vec<usize>, usize)> {
+		let idxs = &cursor.idxs;
+		(1..idxs.len())
+			.filter(|&i| matches!(self.get_el(&idxs[..i]), Some(ChainEl::Node(ChainNode::Branch(_)))))
+			.map(|i| (idxs[..i].to_vec(), idxs[i]))
+			.collect()
+	}
+
+	/// Returns true if there is any node (agent or branch) after the cursor.
+	/// Note: Structural only, the branch arm conditions are not evaluated.
+	pub fn has_next_node(&self, cursor: &ChainCursor) -> bool {
+		self.next_node_idxs(&cursor.idxs).is_some()
 	}
 
 	/// Get the next node idxs for a given `idxs`
@@ -207,11 +390,29 @@ impl Chain {
 			// so that the next iteration of this loop can look at the next item.
 			// Note: This will eventually revert to empty idxs, which will return None.
 			let Some(el) = self.get_el(&idxs) else {
+				// past the end of the loop nodes, the caller evaluates the loop (see `next_agent_cursor`)
+				if let Some((_, loop_idxs)) = idxs.split_last() {
+					if matches!(self.get_el(loop_idxs), Some(ChainEl::Node(ChainNode::Loop(_)))) {
+						idxs.pop();
+						return Some(idxs);
+					}
+				}
+
 				pop_and_inc(&mut idxs);
 
-				// if this is a Agent node, then, returns it (otherwise match below will go to next)
-				if let Some(ChainEl::Node(ChainNode::Agent(_))) = self.get_el(&idxs) {
-					return Some(idxs);
+				match self.get_el(&idxs) {
+					// if this is a node (agent or branch), then, returns it
+					Some(ChainEl::Node(_)) => return Some(idxs),
+					// if this is the next arm, we ran past the end of an arm
+					Some(ChainEl::Arm(_)) => {
+						// in `"match": "all"` mode, the caller evaluates the next arms
+						if self.has_all_arm_at(&idxs) {
+							return Some(idxs);
+						}
+						idxs.pop(); // back to branch, so that the next iteration goes after it
+					}
+					// otherwise, the next iteration will go to the parent next
+					Some(ChainEl::Chain(_)) | None => (),
 				}
 				continue;
 			};
@@ -243,6 +444,18 @@ impl Chain {
 		} // loop
 	}
 
+	/// Returns true if the `idxs` point to a `"match": "all"` branch arm,
+	/// with an arm (not `else`) to be evaluated from there.
+	fn has_all_arm_at(&self, idxs: &[usize]) -> bool {
+		let Some((arm_idx, branch_idxs)) = idxs.split_last() else {
+			return false;
+		};
+		match self.get_el(branch_idxs) {
+			Some(ChainEl::Node(ChainNode::Branch(branch_node))) => branch_node.has_all_arm_from(*arm_idx),
+			_ => false,
+		}
+	}
+
 	/// A simple step through idxs for each el.
 	/// Will get down branch arm.
 	/// NOT USED YET - might not be needed
@@ -276,11 +489,14 @@ impl Chain {
 			};
 
 			match el {
-				// In both Agent Node or Arm, we increment the last index.
+				// In Agent Node, Map, Loop, or Transform Node (not walked down), or Arm, we increment the last index.
 				// - If some element, then we can return the new idxs.
 				// - If none, the next iteration will take care of performing the pop_and_inc,
 				//   so that the subsequent iteration can check the next parent element.
-				ChainEl::Node(ChainNode::Agent(_)) | ChainEl::Arm(_) => {
+				ChainEl::Node(
+					ChainNode::Agent(_) | ChainNode::Map(_) | ChainNode::Loop(_) | ChainNode::Transform(_),
+				)
+				| ChainEl::Arm(_) => {
 					inc_last(&mut idxs);
 					// if we have a match for the next agent, then, we can return early
 					if self.get_el(&idxs).is_some() {
@@ -334,7 +550,9 @@ impl<'a> ChainEl<'a> {
 	fn get_el(&self, idx: usize) -> Option<ChainEl<'a>> {
 		match self {
 			ChainEl::Chain(chain) => chain.nodes.get(idx).map(ChainEl::Node),
-			ChainEl::Node(ChainNode::Agent(_)) => None,
+			// Note: The map nodes are walked as their own chain (see `Chain::scope_chain`).
+			ChainEl::Node(ChainNode::Agent(_) | ChainNode::Map(_) | ChainNode::Transform(_)) => None,
+			ChainEl::Node(ChainNode::Loop(loop_node)) => loop_node.nodes.get(idx).map(ChainEl::Node),
 			ChainEl::Node(ChainNode::Branch(branch)) => branch.branch.get(idx).map(ChainEl::Arm),
 			ChainEl::Arm(arm) => arm.nodes.get(idx).map(ChainEl::Node),
 		}
@@ -404,10 +622,8 @@ mod tests {
 						}
 					},
 					"nodes": [{
-						"agent": { 
-							"uid": "Generic Agent", 
-							"input": "${original_input}"
-						}
+						"agent": { "uid": "Generic Agent" },
+						"input": "${original_input}"
 					}]					
 				}			
 			]
@@ -496,6 +712,40 @@ mod tests {
 
 		Ok(())
 	}
+
+	#[test]
+	fn test_chain_next_agent_node_when() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_chain: Chain = from_str(
+			r#"
+{
+	"nodes": [
+		{ "agent": {"name": "A"} },
+		{ "agent": {"name": "B"}, "when": { "input": { "json_matches": { "pointer": "/score", "lt": 0.5 } } } },
+		{ "agent": {"name": "C"} }
+	]
+}
+			"#,
+		)?;
+
+		// -- Exec & Check
+		for (input, fx_names) in [
+			(r#"{"score": 0.2}"#, &["A", "B", "C"][..]),
+			(r#"{"score": 0.8}"#, &["A", "C"][..]),
+		] {
+			let input = InputContent::new(input);
+			let mut names = Vec::new();
+			let mut cursor = ChainCursor::default();
+			while let Some(next_cursor) = fx_chain.next_agent_cursor(&cursor, &input) {
+				let agent = fx_chain.get_agent_node(&next_cursor).ok_or("Should have agent node")?;
+				names.push(mock_name_from_agent_node(agent));
+				cursor = next_cursor;
+			}
+			assert_eq!(names.x_strs(), fx_names);
+		}
+
+		Ok(())
+	}
 }
 
 // endregion: --- Tests
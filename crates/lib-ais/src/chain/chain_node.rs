@@ -1,4 +1,4 @@
-use crate::chain::{AgentNode, BranchNode};
+use crate::chain::{AgentNode, BranchNode, LoopNode, MapNode, TransformNode};
 use core::fmt;
 use derive_more::From;
 use serde::de::{self, MapAccess, Visitor};
@@ -9,6 +9,9 @@ use serde_json::{from_value, Value};
 pub enum ChainNode {
 	Agent(AgentNode),
 	Branch(BranchNode),
+	Map(MapNode),
+	Loop(LoopNode),
+	Transform(TransformNode),
 }
 
 // region:    --- Deserializer
@@ -24,7 +27,8 @@ impl<'de> Deserialize<'de> for ChainNode {
 			type Value = ChainNode;
 
 			fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-				formatter.write_str("an object with either an 'agent' or a 'branch' key")
+				formatter
+					.write_str("an object with either an 'agent', a 'branch', a 'map', a 'loop', or a 'transform' key")
 			}
 
 			fn visit_map<V>(self, mut map: V) -> Result<ChainNode, V::Error>
@@ -36,8 +40,16 @@ impl<'de> Deserialize<'de> for ChainNode {
 					ChainNode::Agent(from_value(value).map_err(de::Error::custom)?)
 				} else if value.get("branch").is_some() {
 					ChainNode::Branch(from_value(value).map_err(de::Error::custom)?)
+				} else if value.get("map").is_some() {
+					ChainNode::Map(from_value(value).map_err(de::Error::custom)?)
+				} else if value.get("loop").is_some() {
+					ChainNode::Loop(from_value(value).map_err(de::Error::custom)?)
+				} else if value.get("transform").is_some() {
+					ChainNode::Transform(from_value(value).map_err(de::Error::custom)?)
 				} else {
-					return Err(de::Error::custom("Not a value for chain node (no agent or branch)"));
+					return Err(de::Error::custom(
+						"Not a value for chain node (no agent, branch, map, loop, or transform)",
+					));
 				};
 
 				Ok(flow_node)
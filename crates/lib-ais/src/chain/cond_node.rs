@@ -1,62 +1,264 @@
 use crate::chain::InputContent;
-use serde::Deserialize;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
 use serde_json::Value;
-use serde_with::{serde_as, OneOrMany};
+use serde_with::{serde_as, DisplayFromStr, OneOrMany};
 
-#[derive(Debug, Deserialize)]
+/// The condition of a branch arm or agent node (`when`).
+/// - All the set parts (`input`, `all`, `any`, `not`) need to match.
+/// - A cond without any part never matches.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct CondNode {
-	pub input: Option<CondInput>,
+	pub input: Option<Box<CondInput>>,
+
+	/// Matches if all the conds match.
+	pub all: Option<Vec<CondNode>>,
+	/// Matches if any of the conds match.
+	pub any: Option<Vec<CondNode>>,
+	/// Matches if the cond does not match.
+	pub not: Option<Box<CondNode>>,
 }
 
 #[serde_as]
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct CondInput {
 	pub is_json: Option<bool>,
+
+	/// The input text (or json string) contains this text.
+	pub contains: Option<String>,
+	/// The input text (or json string) matches this regex.
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[serde(default)]
+	pub regex: Option<Regex>,
+
 	#[serde_as(deserialize_as = "Option<OneOrMany<_>>")]
 	pub json_matches: Option<Vec<JsonMatch>>,
 }
 
+/// The predicates on the json input value at `pointer`, all the set predicates need to match.
+/// e.g., `{"pointer": "/score", "gte": 0.8}` or `{"pointer": "/label", "in": ["bug", "issue"]}`
+#[serde_as]
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct JsonMatch {
 	pub pointer: String,
-	pub value: Value,
+
+	/// Equal to this value.
+	/// Note: Some(Null) for `"value": null`, None when absent.
+	#[serde(default, deserialize_with = "deserialize_some")]
+	pub value: Option<Value>,
+
+	/// The pointed value exists (`true`) or not (`false`).
+	/// Note: When not set, the pointed value must exist.
+	pub exists: Option<bool>,
+
+	/// Equal to one of these values.
+	#[serde(rename = "in")]
+	pub in_values: Option<Vec<Value>>,
+
+	// -- Number comparisons
+	pub gt: Option<f64>,
+	pub gte: Option<f64>,
+	pub lt: Option<f64>,
+	pub lte: Option<f64>,
+
+	// -- Text predicates (the pointed value must be a string)
+	pub contains: Option<String>,
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[serde(default)]
+	pub regex: Option<Regex>,
+}
+
+fn deserialize_some<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	Value::deserialize(deserializer).map(Some)
 }
 
 // region:    --- Node Activation Match
 
 impl CondNode {
 	pub fn matches_input(&self, content: &InputContent) -> bool {
+		let mut has_part = false;
+
 		if let Some(when_input) = self.input.as_ref() {
-			// -- Check if the is_json flag match
-			if let Some(is_json) = when_input.is_json {
-				if is_json != content.is_json() {
-					return false;
-				}
-			}
-			// -- Check the JsonMatch
-			if let Some(json_matches) = when_input.json_matches.as_ref() {
-				// if content not json, then, return false
-				let InputContent::Json(input_value) = content else {
-					return false;
-				};
-
-				// for each json match
-				for json_match in json_matches {
-					// if no value in input_value, then false
-					let Some(pointed_value) = input_value.pointer(&json_match.pointer) else {
-						return false;
-					};
-
-					if pointed_value != &json_match.value {
-						return false;
-					}
-				}
-			}
-			true
-		} else {
-			false
+			has_part = true;
+			if !when_input.matches_input(content) {
+				return false;
+			}
+		}
+		if let Some(conds) = self.all.as_ref() {
+			has_part = true;
+			if !conds.iter().all(|c| c.matches_input(content)) {
+				return false;
+			}
 		}
+		if let Some(conds) = self.any.as_ref() {
+			has_part = true;
+			if !conds.iter().any(|c| c.matches_input(content)) {
+				return false;
+			}
+		}
+		if let Some(cond) = self.not.as_ref() {
+			has_part = true;
+			if cond.matches_input(content) {
+				return false;
+			}
+		}
+
+		has_part
 	}
 }
 
+impl CondInput {
+	fn matches_input(&self, content: &InputContent) -> bool {
+		// -- Check if the is_json flag match
+		if let Some(is_json) = self.is_json {
+			if is_json != content.is_json() {
+				return false;
+			}
+		}
+
+		// -- Check the text predicates
+		if self.contains.is_some() || self.regex.is_some() {
+			let text = content.to_string();
+			if !text_matches(&text, self.contains.as_deref(), self.regex.as_ref()) {
+				return false;
+			}
+		}
+
+		// -- Check the JsonMatch
+		if let Some(json_matches) = self.json_matches.as_ref() {
+			// if content not json, then, return false
+			let InputContent::Json(input_value) = content else {
+				return false;
+			};
+
+			if !json_matches.iter().all(|json_match| json_match.matches_value(input_value)) {
+				return false;
+			}
+		}
+
+		true
+	}
+}
+
+impl JsonMatch {
+	fn matches_value(&self, input_value: &Value) -> bool {
+		// -- Check the existence
+		let pointed_value = match (self.exists, input_value.pointer(&self.pointer)) {
+			(Some(false), None) => return true,
+			(Some(false), Some(_)) | (_, None) => return false,
+			(_, Some(pointed_value)) => pointed_value,
+		};
+
+		// -- Check the equalities
+		if self.value.as_ref().is_some_and(|value| pointed_value != value) {
+			return false;
+		}
+		if self.in_values.as_ref().is_some_and(|values| !values.contains(pointed_value)) {
+			return false;
+		}
+
+		// -- Check the number comparisons
+		let cmps = [self.gt, self.gte, self.lt, self.lte];
+		if cmps.iter().any(Option::is_some) {
+			let Some(num) = pointed_value.as_f64() else {
+				return false;
+			};
+			let is_match = self.gt.map_or(true, |v| num > v)
+				&& self.gte.map_or(true, |v| num >= v)
+				&& self.lt.map_or(true, |v| num < v)
+				&& self.lte.map_or(true, |v| num <= v);
+			if !is_match {
+				return false;
+			}
+		}
+
+		// -- Check the text predicates
+		if self.contains.is_some() || self.regex.is_some() {
+			let Some(text) = pointed_value.as_str() else {
+				return false;
+			};
+			if !text_matches(text, self.contains.as_deref(), self.regex.as_ref()) {
+				return false;
+			}
+		}
+
+		true
+	}
+}
+
+fn text_matches(text: &str, contains: Option<&str>, regex: Option<&Regex>) -> bool {
+	contains.map_or(true, |c| text.contains(c)) && regex.map_or(true, |re| re.is_match(text))
+}
+
 // endregion: --- Node Activation Match
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use serde_json::from_str;
+
+	#[test]
+	fn test_cond_node_json_predicates() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_cond: CondNode = from_str(
+			r#"
+{
+	"input": {
+		"json_matches": [
+			{ "pointer": "/score", "gte": 0.8, "lt": 1 },
+			{ "pointer": "/label", "in": ["bug", "issue"] },
+			{ "pointer": "/detail", "exists": false }
+		]
+	}
+}
+			"#,
+		)?;
+
+		// -- Exec & Check
+		assert!(fx_cond.matches_input(&InputContent::new(r#"{"score": 0.9, "label": "bug"}"#)));
+		assert!(!fx_cond.matches_input(&InputContent::new(r#"{"score": 0.5, "label": "bug"}"#)));
+		assert!(!fx_cond.matches_input(&InputContent::new(r#"{"score": 0.9, "label": "feature"}"#)));
+		assert!(!fx_cond.matches_input(&InputContent::new(r#"{"score": 0.9, "label": "bug", "detail": "x"}"#)));
+		assert!(!fx_cond.matches_input(&InputContent::new("Some text")));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_cond_node_text_and_combinators() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_cond: CondNode = from_str(
+			r#"
+{
+	"any": [
+		{ "input": { "contains": "urgent" } },
+		{ "input": { "regex": "(?i)^error\\b" } }
+	],
+	"not": { "input": { "is_json": true } }
+}
+			"#,
+		)?;
+
+		// -- Exec & Check
+		assert!(fx_cond.matches_input(&InputContent::new("This is urgent")));
+		assert!(fx_cond.matches_input(&InputContent::new("ERROR: boom")));
+		assert!(!fx_cond.matches_input(&InputContent::new("All good")));
+		assert!(!fx_cond.matches_input(&InputContent::new(r#""urgent""#)));
+		assert!(!CondNode::default().matches_input(&InputContent::new("All good")));
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
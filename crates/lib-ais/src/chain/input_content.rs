@@ -53,6 +53,14 @@ impl InputContent {
 	pub fn is_json(&self) -> bool {
 		matches!(self, InputContent::Json(_))
 	}
+
+	/// Returns the JSON value of the content (a JSON string for a text).
+	pub fn into_value(self) -> Value {
+		match self {
+			InputContent::Text(text) => Value::String(text),
+			InputContent::Json(value) => value,
+		}
+	}
 }
 
 // endregion: --- InputContent
@@ -0,0 +1,108 @@
+use crate::chain::{ChainNode, CondNode, InputContent};
+use serde::Deserialize;
+
+/// The default max number of iterations of a loop node.
+pub const LOOP_DEFAULT_MAX: usize = 3;
+
+/// Repeats the `loop` nodes until the `until` cond matches the latest output,
+/// or the `max` number of iterations is reached.
+/// Note: The iterations are tracked in the `ChainCursor::loops`, so they survive across the steps.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoopNode {
+	#[serde(rename = "loop")]
+	pub nodes: Vec<ChainNode>,
+
+	/// Evaluated on the output of each iteration (no `until` means `max` iterations).
+	pub until: Option<CondNode>,
+	/// The max number of iterations (default to `LOOP_DEFAULT_MAX`).
+	pub max: Option<usize>,
+}
+
+impl LoopNode {
+	pub fn max(&self) -> usize {
+		self.max.filter(|m| *m > 0).unwrap_or(LOOP_DEFAULT_MAX)
+	}
+
+	/// Returns true if the loop is done after the `iter` iteration (1 based), with this iteration output.
+	pub fn is_done(&self, iter: usize, output: &InputContent) -> bool {
+		iter >= self.max() || self.until.as_ref().is_some_and(|until| until.matches_input(output))
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::_test_support::mock_name_from_agent_node;
+	use crate::chain::{Chain, ChainCursor};
+	use lib_utils::x_vec::XStringVec;
+	use serde_json::from_str;
+
+	const CHAIN: &str = r#"
+{
+	"nodes": [
+		{ "agent": { "name": "Writer" } },
+		{
+			"loop": [{ "agent": { "name": "Critic" } }, { "agent": { "name": "Refiner" } }],
+			"until": { "input": { "json_matches": { "pointer": "/done", "value": true } } },
+			"max": 3
+		},
+		{ "agent": { "name": "Final" } }
+	]
+}
+	"#;
+
+	/// Returns the names of the agents walked through by the cursor, for a constant input.
+	fn walk_agent_names(chain: &Chain, input: &InputContent) -> Result<Vec<String>> {
+		let mut names = Vec::new();
+		let mut cursor = ChainCursor::default();
+		while let Some(next_cursor) = chain.next_agent_cursor(&cursor, input) {
+			let agent_node = chain.get_agent_node(&next_cursor).ok_or("Should have agent node")?;
+			names.push(mock_name_from_agent_node(agent_node));
+			// Note: Through json, as the cursor gets persisted in the stack step.
+			cursor = serde_json::from_value(serde_json::to_value(next_cursor)?)?;
+			if names.len() > 20 {
+				return Err("Seems to be an infinite loop.".into());
+			}
+		}
+		Ok(names)
+	}
+
+	#[test]
+	fn test_loop_node_cursor_until_max() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_chain: Chain = from_str(CHAIN)?;
+
+		// -- Exec
+		let names = walk_agent_names(&fx_chain, &InputContent::new(r#"{"done": false}"#))?;
+
+		// -- Check
+		assert_eq!(
+			names.x_strs(),
+			["Writer", "Critic", "Refiner", "Critic", "Refiner", "Critic", "Refiner", "Final"]
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_loop_node_cursor_until_match() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_chain: Chain = from_str(CHAIN)?;
+
+		// -- Exec
+		let names = walk_agent_names(&fx_chain, &InputContent::new(r#"{"done": true}"#))?;
+
+		// -- Check
+		assert_eq!(names.x_strs(), ["Writer", "Critic", "Refiner", "Final"]);
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,129 @@
+use crate::chain::{Chain, ChainNode, ChainVars, InputContent};
+use crate::{Error, Result};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+/// The default number of items run at the same time.
+pub const MAP_DEFAULT_CONCURRENCY: usize = 4;
+
+/// Runs the `map` nodes (its sub-chain) once per item of a JSON array,
+/// and gathers the outputs into a JSON array (in the items order), as the next input.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapNode {
+	#[serde(deserialize_with = "deserialize_map_chain")]
+	pub map: Chain,
+
+	/// The chain variable holding the items (default to the node input).
+	pub from: Option<String>,
+	/// The JSON pointer to the items array in the `from` value (e.g., `/files`), default to the whole value.
+	pub pointer: Option<String>,
+	/// The max number of items run at the same time (default to `MAP_DEFAULT_CONCURRENCY`).
+	pub concurrency: Option<usize>,
+
+	/// Bind the gathered outputs (JSON array) to this chain variable.
+	pub name_output: Option<String>,
+}
+
+fn deserialize_map_chain<'de, D>(deserializer: D) -> core::result::Result<Chain, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let nodes = Vec::<ChainNode>::deserialize(deserializer)?;
+	Ok(Chain { nodes })
+}
+
+impl MapNode {
+	pub fn concurrency(&self) -> usize {
+		self.concurrency.filter(|c| *c > 0).unwrap_or(MAP_DEFAULT_CONCURRENCY)
+	}
+
+	/// Returns the items to map, from the `from` chain variable (or the input) at `pointer`.
+	pub fn resolve_items(&self, input: &InputContent, vars: &ChainVars) -> Result<Vec<Value>> {
+		let source = match self.from.as_ref() {
+			Some(name) => {
+				let val = vars
+					.get(name)
+					.ok_or_else(|| Error::ChainVarNotFound { name: name.to_string() })?;
+				InputContent::new(val)
+			}
+			None => input.clone(),
+		};
+
+		let InputContent::Json(value) = source else {
+			return Err(Error::ChainMapItemsNotArray {
+				from: self.items_desc(),
+			});
+		};
+		let value = match self.pointer.as_deref() {
+			Some(pointer) => value.pointer(pointer).cloned(),
+			None => Some(value),
+		};
+
+		match value {
+			Some(Value::Array(items)) => Ok(items),
+			_ => Err(Error::ChainMapItemsNotArray {
+				from: self.items_desc(),
+			}),
+		}
+	}
+
+	fn items_desc(&self) -> String {
+		format!(
+			"{}{}",
+			self.from.as_deref().unwrap_or("input"),
+			self.pointer.as_deref().unwrap_or("")
+		)
+	}
+}
+
+/// Returns the input string of a map item (the text for a JSON string, the JSON otherwise).
+pub fn map_item_to_input(item: Value) -> String {
+	match item {
+		Value::String(text) => text,
+		other => other.to_string(),
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use serde_json::{from_str, json};
+
+	#[test]
+	fn test_map_node_resolve_items() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_node: MapNode = from_str(
+			r#"
+{
+	"map": [{ "agent": { "name": "Summarizer" } }],
+	"pointer": "/files",
+	"concurrency": 2
+}
+			"#,
+		)?;
+		let fx_input = InputContent::new(r#"{"files": ["a.md", {"name": "b.md"}]}"#);
+
+		// -- Exec
+		let items = fx_node.resolve_items(&fx_input, &ChainVars::new())?;
+
+		// -- Check
+		assert_eq!(fx_node.map.nodes.len(), 1);
+		assert_eq!(fx_node.concurrency(), 2);
+		assert_eq!(items, vec![json!("a.md"), json!({"name": "b.md"})]);
+		let inputs: Vec<String> = items.into_iter().map(map_item_to_input).collect();
+		assert_eq!(inputs, ["a.md", r#"{"name":"b.md"}"#]);
+		assert!(fx_node
+			.resolve_items(&InputContent::new("not json"), &ChainVars::new())
+			.is_err());
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
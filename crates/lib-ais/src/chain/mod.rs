@@ -7,7 +7,11 @@ mod chain;
 mod chain_node;
 mod cond_node;
 mod input_content;
+mod loop_node;
+mod map_node;
 mod resolvers;
+mod transform_node;
+mod validator;
 
 // -- Flatten
 pub use agent_node::*;
@@ -16,6 +20,10 @@ pub use chain::*;
 pub use chain_node::*;
 pub use cond_node::*;
 pub use input_content::*;
+pub use loop_node::*;
+pub use map_node::*;
 pub use resolvers::*;
+pub use transform_node::*;
+pub use validator::*;
 
 // endregion: --- Modules
@@ -0,0 +1,172 @@
+use crate::chain::{map_item_to_input, render_chain_tmpl, ChainVars, InputContent};
+use crate::{Error, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use serde_with::{serde_as, OneOrMany};
+
+/// Reshapes the input into the next input, without any model call (runs as a step of the chain agent).
+/// Note: The transforms are applied in order, each on the output of the previous one.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransformNode {
+	#[serde_as(as = "OneOrMany<_>")]
+	pub transform: Vec<Transform>,
+
+	/// Bind the transform output to this chain variable.
+	pub name_output: Option<String>,
+}
+
+/// A transform of the input (e.g., `{"pointer": "/answer"}`, `{"join": ", "}`, or `"first"`).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+	/// The value at the JSON pointer of the (JSON) input (a JSON string value becomes text).
+	Pointer(String),
+	/// The handlebars template, rendered with `input` and the chain variables (like the agent `prompt_tmpl`).
+	Tmpl(String),
+	/// The items of the (JSON array) input joined into text with this separator.
+	Join(String),
+	/// The first item of the (JSON array) input.
+	First,
+	/// The last item of the (JSON array) input.
+	Last,
+	/// The number of items (array), entries (object), or chars (text) of the input.
+	Len,
+	/// The non-empty trimmed lines of the (text) input, as a JSON array.
+	Lines,
+	/// The trimmed (text) input.
+	Trim,
+}
+
+impl TransformNode {
+	/// Apply the transforms to the input, and returns the next input.
+	pub fn apply(&self, input: &str, vars: &ChainVars) -> Result<String> {
+		let mut output = input.to_string();
+		for transform in self.transform.iter() {
+			output = transform.apply(InputContent::new(output), vars)?;
+		}
+		Ok(output)
+	}
+}
+
+impl Transform {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Transform::Pointer(_) => "pointer",
+			Transform::Tmpl(_) => "tmpl",
+			Transform::Join(_) => "join",
+			Transform::First => "first",
+			Transform::Last => "last",
+			Transform::Len => "len",
+			Transform::Lines => "lines",
+			Transform::Trim => "trim",
+		}
+	}
+
+	fn apply(&self, input: InputContent, vars: &ChainVars) -> Result<String> {
+		let output = match (self, input) {
+			(Transform::Pointer(pointer), InputContent::Json(value)) => {
+				let value = value
+					.pointer(pointer)
+					.cloned()
+					.ok_or_else(|| self.fail(format!("No value at pointer '{pointer}'")))?;
+				map_item_to_input(value)
+			}
+
+			(Transform::Tmpl(tmpl), input) => {
+				render_chain_tmpl(tmpl, input, vars).map_err(|err| self.fail(err.to_string()))?
+			}
+
+			(Transform::Join(sep), InputContent::Json(Value::Array(items))) => {
+				items.into_iter().map(map_item_to_input).collect::<Vec<_>>().join(sep)
+			}
+
+			(Transform::First, InputContent::Json(Value::Array(items))) => {
+				items.into_iter().next().map(map_item_to_input).unwrap_or_default()
+			}
+			(Transform::Last, InputContent::Json(Value::Array(items))) => {
+				items.into_iter().last().map(map_item_to_input).unwrap_or_default()
+			}
+
+			(Transform::Len, InputContent::Json(Value::Array(items))) => items.len().to_string(),
+			(Transform::Len, InputContent::Json(Value::Object(obj))) => obj.len().to_string(),
+			(Transform::Len, InputContent::Text(text)) => text.chars().count().to_string(),
+
+			(Transform::Lines, InputContent::Text(text)) => {
+				let lines: Vec<&str> = text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+				Value::from(lines).to_string()
+			}
+			(Transform::Trim, InputContent::Text(text)) => text.trim().to_string(),
+
+			(Transform::Pointer(_), _) => return Err(self.fail("Input is not JSON")),
+			(Transform::Join(_) | Transform::First | Transform::Last, _) => {
+				return Err(self.fail("Input is not a JSON array"))
+			}
+			(Transform::Len, _) => return Err(self.fail("Input is not a JSON array, object, or text")),
+			(Transform::Lines | Transform::Trim, _) => return Err(self.fail("Input is not text")),
+		};
+
+		Ok(output)
+	}
+
+	fn fail(&self, cause: impl Into<String>) -> Error {
+		Error::ChainTransformFail {
+			transform: self.name(),
+			cause: cause.into(),
+		}
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use serde_json::from_str;
+
+	#[test]
+	fn test_transform_node_apply_ok() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_input = r#"{"answer": {"files": ["a.md", "b.md"]}, "title": "Files"}"#;
+		let fx_vars = ChainVars::from([("user".to_string(), "Jen".to_string())]);
+		let fx_cases = [
+			(r#"{"pointer": "/title"}"#, "Files"),
+			(r#"[{"pointer": "/answer/files"}, {"join": ", "}]"#, "a.md, b.md"),
+			(r#"[{"pointer": "/answer/files"}, "last"]"#, "b.md"),
+			(r#"[{"pointer": "/answer/files"}, "len"]"#, "2"),
+			(r#"{"tmpl": "{{input.title}} for {{user}}"}"#, "Files for Jen"),
+			(r#"[{"tmpl": " a\n\n b "}, "lines"]"#, r#"["a","b"]"#),
+		];
+
+		// -- Exec & Check
+		for (transform, expected) in fx_cases {
+			let node: TransformNode = from_str(&format!(r#"{{"transform": {transform}}}"#))?;
+			assert_eq!(node.apply(fx_input, &fx_vars)?, expected, "transform: {transform}");
+		}
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_transform_node_apply_fail() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_node: TransformNode = from_str(r#"{"transform": [{"pointer": "/files"}, "first"]}"#)?;
+
+		// -- Exec
+		let res = fx_node.apply(r#"{"files": "a.md"}"#, &ChainVars::new());
+
+		// -- Check
+		assert!(
+			matches!(res, Err(crate::Error::ChainTransformFail { transform: "first", .. })),
+			"Should fail on first, but was {res:?}"
+		);
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,760 @@
+//! Static validation of the agent chain json, to be done before the chain gets saved.
+//!
+//! The problems are returned as `ChainDiagnostic`, with the JSON pointer of the faulty value.
+//!
+//! Note: The known keys of each object are the fields of its chain serde type (see `serde_fields`),
+//!       as the chain types deny the unknown fields.
+
+use crate::chain::{
+	AgentChain, AgentNode, AgentRef, BranchArm, BranchNode, Chain, CondInput, CondNode, JsonMatch, LoopNode, MapNode,
+	Transform, TransformNode,
+};
+use crate::{Error, Result};
+use lib_core::model::agent::{Agent, AgentBmc, AgentChainCheck, AgentError};
+use lib_core::model::{self, Id, ModelManager};
+use regex::Regex;
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+// region:    --- Types
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainDiagnostic {
+	/// The JSON pointer of the faulty value in the chain json (e.g., `/nodes/1/branch/0`).
+	pub path: String,
+	pub kind: ChainDiagnosticKind,
+	pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainDiagnosticKind {
+	InvalidJson,
+	UnknownKey,
+	InvalidValue,
+	AgentNotFound,
+	EmptyArm,
+	UnreachableArm,
+	AgentCycle,
+}
+
+impl ChainDiagnostic {
+	fn new(path: impl Into<String>, kind: ChainDiagnosticKind, message: impl Into<String>) -> Self {
+		Self {
+			path: path.into(),
+			kind,
+			message: message.into(),
+		}
+	}
+}
+
+// endregion: --- Types
+
+// region:    --- Public Fns
+
+/// Validate the chain json of an agent (`agent_id` is None for a new agent).
+/// Returns the diagnostics, empty if the chain is valid.
+pub async fn validate_agent_chain(
+	mm: &ModelManager,
+	agent_id: Option<Id>,
+	chain: &str,
+) -> Result<Vec<ChainDiagnostic>> {
+	// Note: Empty chain means default chain (see `Chain::new`).
+	if chain.trim().is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let value: Value = match serde_json::from_str(chain) {
+		Ok(value) => value,
+		Err(err) => {
+			return Ok(vec![ChainDiagnostic::new(
+				"",
+				ChainDiagnosticKind::InvalidJson,
+				err.to_string(),
+			)])
+		}
+	};
+
+	// -- Check the structure
+	let mut walker = ChainWalker::default();
+	walker.check_chain(&value);
+	let ChainWalker { mut diags, agent_refs } = walker;
+
+	// Note: Failsafe, in case the walker misses something the chain parser does not accept.
+	if diags.is_empty() {
+		if let Err(err) = serde_json::from_value::<Chain>(value) {
+			diags.push(ChainDiagnostic::new(
+				"",
+				ChainDiagnosticKind::InvalidValue,
+				err.to_string(),
+			));
+		}
+	}
+
+	// -- Check the agent refs and cycles
+	for (path, agent_ref) in agent_refs {
+		match resolve_agent_ref(mm, &agent_ref).await? {
+			Some(agent) => {
+				if Some(agent.id) == agent_id {
+					continue;
+				}
+				if let Some(cycle) = find_agent_cycle(mm, agent_id, agent).await? {
+					diags.push(ChainDiagnostic::new(
+						path,
+						ChainDiagnosticKind::AgentCycle,
+						format!("Agent cycle: {}", cycle.join(" -> ")),
+					));
+				}
+			}
+			None => diags.push(ChainDiagnostic::new(
+				path,
+				ChainDiagnosticKind::AgentNotFound,
+				format!("No agent found for {agent_ref:?}"),
+			)),
+		}
+	}
+
+	Ok(diags)
+}
+
+/// Same as `validate_agent_chain`, but fails with `Error::ChainInvalid` when there are diagnostics.
+pub async fn check_agent_chain(mm: &ModelManager, agent_id: Option<Id>, chain: &str) -> Result<()> {
+	let diagnostics = validate_agent_chain(mm, agent_id, chain).await?;
+	if diagnostics.is_empty() {
+		Ok(())
+	} else {
+		Err(Error::ChainInvalid { diagnostics })
+	}
+}
+
+/// The `ModelManager` agent chain check (see `ModelManager::with_agent_chain_check`),
+/// so that all of the `AgentBmc::update` with a chain get validated.
+pub fn agent_chain_check() -> AgentChainCheck {
+	Arc::new(|mm, agent_id, chain| {
+		Box::pin(async move {
+			match check_agent_chain(&mm, Some(agent_id), &chain).await {
+				Ok(()) => Ok(()),
+				Err(Error::ChainInvalid { diagnostics }) => Err(AgentError::ChainInvalid {
+					agent_id,
+					diagnostics: serde_json::to_value(diagnostics).unwrap_or_default(),
+				}
+				.into()),
+				Err(Error::Model(err)) => Err(err),
+				Err(err) => Err(model::Error::Custom(err.to_string())),
+			}
+		})
+	})
+}
+
+// endregion: --- Public Fns
+
+// region:    --- ChainWalker
+
+/// Walks the chain json value, collecting the structural diagnostics and the agent refs (with their paths).
+#[derive(Default)]
+struct ChainWalker {
+	diags: Vec<ChainDiagnostic>,
+	agent_refs: Vec<(String, AgentRef)>,
+}
+
+impl ChainWalker {
+	fn check_chain(&mut self, value: &Value) {
+		let Some(obj) = self.as_object(value, "") else {
+			return;
+		};
+		self.check_keys(obj, "", serde_fields::<Chain>());
+
+		match obj.get("nodes") {
+			Some(nodes) => self.check_nodes(nodes, "/nodes"),
+			None => self.invalid("", "Chain has no 'nodes'"),
+		}
+	}
+
+	fn check_nodes(&mut self, value: &Value, path: &str) {
+		let Some(nodes) = value.as_array() else {
+			return self.invalid(path, "Should be an array of nodes");
+		};
+		for (idx, node) in nodes.iter().enumerate() {
+			self.check_node(node, &format!("{path}/{idx}"));
+		}
+	}
+
+	fn check_node(&mut self, value: &Value, path: &str) {
+		let Some(obj) = self.as_object(value, path) else {
+			return;
+		};
+
+		if let Some(branch) = obj.get("branch") {
+			self.check_keys(obj, path, serde_fields::<BranchNode>());
+			let match_all = match obj.get("match") {
+				None => false,
+				Some(Value::String(mode)) if mode == "first" => false,
+				Some(Value::String(mode)) if mode == "all" => true,
+				Some(_) => {
+					self.invalid(&format!("{path}/match"), "Should be 'first' or 'all'");
+					false
+				}
+			};
+			self.check_branch(branch, &format!("{path}/branch"), match_all);
+		} else if let Some(agent) = obj.get("agent") {
+			self.check_keys(obj, path, serde_fields::<AgentNode>());
+			self.check_agent_ref(agent, &format!("{path}/agent"));
+			for key in ["name_input", "name_output", "input"] {
+				if obj.get(key).is_some_and(|v| !v.is_string()) {
+					self.invalid(&format!("{path}/{}", ptr_escape(key)), "Should be a string");
+				}
+			}
+			if let Some(when) = obj.get("when") {
+				self.check_cond(when, &format!("{path}/when"));
+			}
+		} else if let Some(map) = obj.get("map") {
+			self.check_keys(obj, path, serde_fields::<MapNode>());
+			self.check_map(obj, map, path);
+		} else if let Some(nodes) = obj.get("loop") {
+			self.check_keys(obj, path, serde_fields::<LoopNode>());
+			match nodes.as_array() {
+				Some(items) if items.is_empty() => self.invalid(&format!("{path}/loop"), "Loop has no nodes"),
+				_ => self.check_nodes(nodes, &format!("{path}/loop")),
+			}
+			if let Some(until) = obj.get("until") {
+				self.check_cond(until, &format!("{path}/until"));
+			}
+			if obj.get("max").is_some_and(|v| !v.as_u64().is_some_and(|m| m > 0)) {
+				self.invalid(&format!("{path}/max"), "Should be a positive integer");
+			}
+		} else if let Some(transform) = obj.get("transform") {
+			self.check_keys(obj, path, serde_fields::<TransformNode>());
+			self.check_transform(transform, &format!("{path}/transform"));
+			if obj.get("name_output").is_some_and(|v| !v.is_string()) {
+				self.invalid(&format!("{path}/name_output"), "Should be a string");
+			}
+		} else {
+			self.invalid(
+				path,
+				"Node should have an 'agent', a 'branch', a 'map', a 'loop', or a 'transform'",
+			);
+		}
+	}
+
+	/// Checks the transform (one or an array), e.g., `"first"` or `{"pointer": "/answer"}`.
+	fn check_transform(&mut self, value: &Value, path: &str) {
+		let transforms: Vec<(String, &Value)> = match value {
+			Value::Array(items) if items.is_empty() => return self.invalid(path, "Transform has no transforms"),
+			Value::Array(items) => items.iter().enumerate().map(|(idx, t)| (format!("{path}/{idx}"), t)).collect(),
+			item => vec![(path.to_string(), item)],
+		};
+		for (path, transform) in transforms {
+			match Transform::deserialize(transform) {
+				Ok(Transform::Pointer(pointer)) if !(pointer.is_empty() || pointer.starts_with('/')) => self.invalid(
+					&format!("{path}/pointer"),
+					"Should be a JSON pointer string (e.g., '/answer')",
+				),
+				Ok(_) => (),
+				Err(err) => self.invalid(&path, format!("Invalid transform. Cause: {err}")),
+			}
+		}
+	}
+
+	fn check_map(&mut self, obj: &Map<String, Value>, map: &Value, path: &str) {
+		match map.as_array() {
+			Some(nodes) if nodes.is_empty() => self.invalid(&format!("{path}/map"), "Map has no nodes"),
+			Some(_) => self.check_nodes(map, &format!("{path}/map")),
+			None => self.invalid(&format!("{path}/map"), "Should be an array of nodes"),
+		}
+		for key in ["from", "name_output"] {
+			if obj.get(key).is_some_and(|v| !v.is_string()) {
+				self.invalid(&format!("{path}/{}", ptr_escape(key)), "Should be a string");
+			}
+		}
+		match obj.get("pointer").map(|p| p.as_str()) {
+			Some(Some(pointer)) if pointer.is_empty() || pointer.starts_with('/') => (),
+			Some(_) => self.invalid(
+				&format!("{path}/pointer"),
+				"Should be a JSON pointer string (e.g., '/files')",
+			),
+			None => (),
+		}
+		if obj.get("concurrency").is_some_and(|v| !v.as_u64().is_some_and(|c| c > 0)) {
+			self.invalid(&format!("{path}/concurrency"), "Should be a positive integer");
+		}
+	}
+
+	fn check_agent_ref(&mut self, value: &Value, path: &str) {
+		if value.as_str() == Some("self") {
+			return;
+		}
+		let Some(obj) = value.as_object() else {
+			return self.invalid(path, "Agent should be 'self' or an object with 'uid', 'name', or 'id'");
+		};
+		self.check_keys(obj, path, AgentRef::KEYS);
+
+		let agent_ref = match (obj.get("uid"), obj.get("name"), obj.get("id")) {
+			(Some(Value::String(uid)), None, None) => AgentRef::Uid(uid.to_string()),
+			(None, Some(Value::String(name)), None) => AgentRef::Name(name.to_string()),
+			(None, None, Some(id)) => match id.as_i64() {
+				Some(id) => AgentRef::Id(id),
+				None => return self.invalid(&format!("{path}/id"), "Should be an integer"),
+			},
+			_ => {
+				return self.invalid(
+					path,
+					"Agent should have one string 'uid' or 'name', or one integer 'id'",
+				)
+			}
+		};
+		self.agent_refs.push((path.to_string(), agent_ref));
+	}
+
+	fn check_branch(&mut self, value: &Value, path: &str, match_all: bool) {
+		let Some(arms) = value.as_array() else {
+			return self.invalid(path, "Should be an array of arms");
+		};
+
+		let mut prev_conds: Vec<&Value> = Vec::new();
+		let mut has_else = false;
+		for (idx, arm) in arms.iter().enumerate() {
+			let arm_path = format!("{path}/{idx}");
+			let Some(obj) = self.as_object(arm, &arm_path) else {
+				continue;
+			};
+			self.check_keys(obj, &arm_path, serde_fields::<BranchArm>());
+
+			// -- Check the else arm
+			let is_else = match obj.get("else") {
+				None => false,
+				Some(Value::Bool(is_else)) => *is_else,
+				Some(_) => {
+					self.invalid(&format!("{arm_path}/else"), "Should be a boolean");
+					false
+				}
+			};
+			if is_else {
+				if has_else {
+					self.push(
+						&arm_path,
+						ChainDiagnosticKind::UnreachableArm,
+						"Branch already has an 'else' arm",
+					);
+				}
+				if obj.get("cond").is_some() {
+					self.invalid(&format!("{arm_path}/cond"), "The 'else' arm should not have a 'cond'");
+				}
+				has_else = true;
+			}
+
+			// -- Check the cond
+			match obj.get("cond") {
+				Some(cond) if !is_else => {
+					let cond_path = format!("{arm_path}/cond");
+					self.check_cond(cond, &cond_path);
+					if !serde_fields::<CondNode>().iter().any(|key| cond.get(key).is_some()) {
+						self.push(
+							&cond_path,
+							ChainDiagnosticKind::UnreachableArm,
+							"Arm cond has no 'input', 'all', 'any', nor 'not', so it never matches",
+						);
+					}
+					// Note: In the `all` mode, the same cond can match again (evaluated on the previous arm output).
+					else if !match_all && prev_conds.contains(&cond) {
+						self.push(
+							&cond_path,
+							ChainDiagnosticKind::UnreachableArm,
+							"Arm cond is the same as a previous arm cond",
+						);
+					}
+					prev_conds.push(cond);
+				}
+				Some(_) => (),
+				None if is_else => (),
+				None => self.invalid(&arm_path, "Arm has no 'cond' (nor 'else': true)"),
+			}
+
+			// -- Check the nodes
+			match obj.get("nodes") {
+				Some(Value::Array(nodes)) if nodes.is_empty() => {
+					self.push(&arm_path, ChainDiagnosticKind::EmptyArm, "Arm has no nodes")
+				}
+				Some(nodes) => self.check_nodes(nodes, &format!("{arm_path}/nodes")),
+				None => self.push(&arm_path, ChainDiagnosticKind::EmptyArm, "Arm has no nodes"),
+			}
+		}
+	}
+
+	fn check_cond(&mut self, value: &Value, path: &str) {
+		let Some(obj) = self.as_object(value, path) else {
+			return;
+		};
+		self.check_keys(obj, path, serde_fields::<CondNode>());
+
+		// -- Check the combinators
+		for key in ["all", "any"] {
+			let Some(conds) = obj.get(key) else {
+				continue;
+			};
+			let conds_path = format!("{path}/{key}");
+			match conds.as_array() {
+				Some(conds) => {
+					for (idx, cond) in conds.iter().enumerate() {
+						self.check_cond(cond, &format!("{conds_path}/{idx}"));
+					}
+				}
+				None => self.invalid(&conds_path, "Should be an array of conds"),
+			}
+		}
+		if let Some(cond) = obj.get("not") {
+			self.check_cond(cond, &format!("{path}/not"));
+		}
+
+		// -- Check the input
+		let Some(input) = obj.get("input") else {
+			return;
+		};
+		let input_path = format!("{path}/input");
+		let Some(input_obj) = self.as_object(input, &input_path) else {
+			return;
+		};
+		self.check_keys(input_obj, &input_path, serde_fields::<CondInput>());
+
+		if input_obj.get("is_json").is_some_and(|v| !v.is_boolean()) {
+			self.invalid(&format!("{input_path}/is_json"), "Should be a boolean");
+		}
+		self.check_text_predicates(input_obj, &input_path);
+
+		// Note: `json_matches` can be one or many.
+		let matches_path = format!("{input_path}/json_matches");
+		let json_matches: Vec<(String, &Value)> = match input_obj.get("json_matches") {
+			Some(Value::Array(items)) => items
+				.iter()
+				.enumerate()
+				.map(|(idx, item)| (format!("{matches_path}/{idx}"), item))
+				.collect(),
+			Some(item) => vec![(matches_path, item)],
+			None => Vec::new(),
+		};
+		for (match_path, json_match) in json_matches {
+			let Some(match_obj) = self.as_object(json_match, &match_path) else {
+				continue;
+			};
+			self.check_keys(match_obj, &match_path, serde_fields::<JsonMatch>());
+			match match_obj.get("pointer").map(|p| p.as_str()) {
+				Some(Some(pointer)) if pointer.is_empty() || pointer.starts_with('/') => (),
+				Some(_) => self.invalid(
+					&format!("{match_path}/pointer"),
+					"Should be a JSON pointer string (e.g., '/category')",
+				),
+				None => self.invalid(&match_path, "Json match has no 'pointer'"),
+			}
+			if match_obj.get("exists").is_some_and(|v| !v.is_boolean()) {
+				self.invalid(&format!("{match_path}/exists"), "Should be a boolean");
+			}
+			if match_obj.get("in").is_some_and(|v| !v.is_array()) {
+				self.invalid(&format!("{match_path}/in"), "Should be an array of values");
+			}
+			for key in ["gt", "gte", "lt", "lte"] {
+				if match_obj.get(key).is_some_and(|v| !v.is_number()) {
+					self.invalid(&format!("{match_path}/{key}"), "Should be a number");
+				}
+			}
+			self.check_text_predicates(match_obj, &match_path);
+		}
+	}
+
+	/// Checks the `contains` and `regex` predicates of a cond input or json match.
+	fn check_text_predicates(&mut self, obj: &Map<String, Value>, path: &str) {
+		if obj.get("contains").is_some_and(|v| !v.is_string()) {
+			self.invalid(&format!("{path}/contains"), "Should be a string");
+		}
+		match obj.get("regex").map(|v| v.as_str()) {
+			Some(Some(regex)) => {
+				if let Err(err) = Regex::new(regex) {
+					self.invalid(&format!("{path}/regex"), format!("Invalid regex. Cause: {err}"));
+				}
+			}
+			Some(None) => self.invalid(&format!("{path}/regex"), "Should be a regex string"),
+			None => (),
+		}
+	}
+
+	// -- Support
+
+	fn as_object<'v>(&mut self, value: &'v Value, path: &str) -> Option<&'v Map<String, Value>> {
+		let obj = value.as_object();
+		if obj.is_none() {
+			self.invalid(path, "Should be an object");
+		}
+		obj
+	}
+
+	fn check_keys(&mut self, obj: &Map<String, Value>, path: &str, keys: &[&str]) {
+		for key in obj.keys().filter(|k| !keys.contains(&k.as_str())) {
+			self.push(
+				&format!("{path}/{}", ptr_escape(key)),
+				ChainDiagnosticKind::UnknownKey,
+				format!("Unknown key '{key}' (expected one of {keys:?})"),
+			);
+		}
+	}
+
+	fn invalid(&mut self, path: &str, message: impl Into<String>) {
+		self.push(path, ChainDiagnosticKind::InvalidValue, message);
+	}
+
+	fn push(&mut self, path: &str, kind: ChainDiagnosticKind, message: impl Into<String>) {
+		self.diags.push(ChainDiagnostic::new(path, kind, message));
+	}
+}
+
+/// Escape a key for a JSON pointer segment (RFC 6901).
+fn ptr_escape(key: &str) -> String {
+	key.replace('~', "~0").replace('/', "~1")
+}
+
+// endregion: --- ChainWalker
+
+// region:    --- Serde Fields
+
+/// Returns the field names of the serde struct `T` (with its serde renames), empty if `T` is not a struct.
+fn serde_fields<T: DeserializeOwned>() -> &'static [&'static str] {
+	let mut fields: &'static [&'static str] = &[];
+	// Note: The probe always fails, once it got the fields.
+	let _ = T::deserialize(FieldsProbe(&mut fields));
+	fields
+}
+
+/// The deserializer which only records the fields of the struct being deserialized.
+struct FieldsProbe<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldsProbe<'_> {
+	type Error = de::value::Error;
+
+	fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> core::result::Result<V::Value, Self::Error> {
+		Err(de::Error::custom("FieldsProbe only supports structs"))
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		fields: &'static [&'static str],
+		_visitor: V,
+	) -> core::result::Result<V::Value, Self::Error> {
+		*self.0 = fields;
+		Err(de::Error::custom("FieldsProbe got the fields"))
+	}
+
+	serde::forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+		bytes byte_buf option unit unit_struct newtype_struct seq tuple
+		tuple_struct map enum identifier ignored_any
+	}
+}
+
+// endregion: --- Serde Fields
+
+// region:    --- Agent Refs
+
+/// Returns the agent for the ref, None if not found.
+/// Note: `AgentRef::Same` is not resolved (None), as it is always valid.
+async fn resolve_agent_ref(mm: &ModelManager, agent_ref: &AgentRef) -> Result<Option<Agent>> {
+	let res = match agent_ref {
+		AgentRef::Same => return Ok(None),
+		AgentRef::Uid(uid) => AgentBmc::get_by_uid(mm, uid).await,
+		AgentRef::Id(id) => AgentBmc::get(mm, (*id).into()).await,
+		AgentRef::Name(name) => return Ok(AgentBmc::first_by_name(mm, name).await?),
+	};
+
+	match res {
+		Ok(agent) => Ok(Some(agent)),
+		Err(model::Error::EntityNotFound { .. } | model::Error::EntityByUidNotFound { .. }) => Ok(None),
+		Err(err) => Err(err.into()),
+	}
+}
+
+/// Returns the agent names of a cycle reachable from the `start` agent (through the agent chains), if any.
+/// - A cycle back to the `root_agent_id` (the agent being validated) is a cycle.
+/// - The agent referencing itself is not a cycle (same as `"agent": "self"`).
+/// - The agents with an invalid chain are skipped (they get their own diagnostics when saved).
+async fn find_agent_cycle(mm: &ModelManager, root_agent_id: Option<Id>, start: Agent) -> Result<Option<Vec<String>>> {
+	let mut expanded: HashSet<i64> = HashSet::new();
+	// (agent, path of agents to it)
+	let mut todo: Vec<(Agent, Vec<Agent>)> = vec![(start, Vec::new())];
+
+	while let Some((agent, path)) = todo.pop() {
+		let is_cycle = Some(agent.id) == root_agent_id || path.iter().any(|a| a.id == agent.id);
+		if is_cycle {
+			let mut names: Vec<String> = vec!["(this agent)".to_string()];
+			names.extend(path.iter().map(|a| a.name.to_string()));
+			names.push(agent.name.to_string());
+			return Ok(Some(names));
+		}
+		if !expanded.insert(agent.id.as_i64()) {
+			continue;
+		}
+
+		let Ok(chain) = agent.get_chain() else {
+			continue;
+		};
+		for agent_ref in chain.agent_refs() {
+			let Some(ref_agent) = resolve_agent_ref(mm, agent_ref).await? else {
+				continue;
+			};
+			if ref_agent.id == agent.id {
+				continue;
+			}
+			let mut ref_path = path.clone();
+			ref_path.push(agent.clone());
+			todo.push((ref_agent, ref_path));
+		}
+	}
+
+	Ok(None)
+}
+
+// endregion: --- Agent Refs
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::_test_support::seed_mock_echo_agents;
+	use lib_core::model::agent::AgentForUpdate;
+
+	#[tokio::test]
+	async fn test_validator_diagnostics() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		seed_mock_echo_agents(&mm, &[("Agent Known", "")]).await?;
+		let fx_chain = r#"
+{
+	"nodes": [
+		{ "agent": "self", "name_input": "original_input" },
+		{
+			"branch": [
+				{
+					"cond": { "input": { "is_json": true } },
+					"flow": [{ "agent": { "name": "Agent Known" } }]
+				},
+				{
+					"cond": { "input": { "is_json": true } },
+					"nodes": [{ "agent": { "name": "Agent Unknown" } }]
+				}
+			]
+		},
+		{ "map": [{ "agent": "self" }], "pointer": "/files", "concurrency": 0 },
+		{ "loop": [{ "agent": "self" }], "until": { "input": { "regex": "(" } }, "max": 5 },
+		{ "transform": [{ "pointer": "files" }, "upper", { "join": ", " }] }
+	]
+}
+		"#;
+
+		// -- Exec
+		let diags = validate_agent_chain(&mm, None, fx_chain).await?;
+
+		// -- Check
+		let diags: Vec<(&str, ChainDiagnosticKind)> = diags.iter().map(|d| (d.path.as_str(), d.kind)).collect();
+		assert_eq!(
+			diags,
+			[
+				("/nodes/1/branch/0/flow", ChainDiagnosticKind::UnknownKey),
+				("/nodes/1/branch/0", ChainDiagnosticKind::EmptyArm),
+				("/nodes/1/branch/1/cond", ChainDiagnosticKind::UnreachableArm),
+				("/nodes/2/concurrency", ChainDiagnosticKind::InvalidValue),
+				("/nodes/3/until/input/regex", ChainDiagnosticKind::InvalidValue),
+				("/nodes/4/transform/0/pointer", ChainDiagnosticKind::InvalidValue),
+				("/nodes/4/transform/1", ChainDiagnosticKind::InvalidValue),
+				("/nodes/1/branch/1/nodes/0/agent", ChainDiagnosticKind::AgentNotFound),
+			]
+		);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_validator_agent_cycle() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let agents = seed_mock_echo_agents(&mm, &[("Agent A", ""), ("Agent B", "")]).await?;
+		let (agent_a, agent_b) = (&agents[0], &agents[1]);
+		let fx_chain_b = r#"{"nodes": [{"agent": {"name": "Agent A"}}]}"#;
+		AgentBmc::update(
+			&mm,
+			agent_b.id,
+			AgentForUpdate {
+				chain: Some(fx_chain_b.to_string()),
+				..Default::default()
+			},
+		)
+		.await?;
+		let fx_chain_a = r#"{"nodes": [{"agent": "self"}, {"agent": {"name": "Agent B"}}]}"#;
+
+		// -- Exec
+		let diags = validate_agent_chain(&mm, Some(agent_a.id), fx_chain_a).await?;
+
+		// -- Check
+		assert_eq!(diags.len(), 1);
+		assert_eq!(diags[0].kind, ChainDiagnosticKind::AgentCycle);
+		assert_eq!(diags[0].path, "/nodes/1/agent");
+		// Note: When the cycle is already saved, the other agent gets it as well.
+		AgentBmc::update(
+			&mm,
+			agent_a.id,
+			AgentForUpdate {
+				chain: Some(fx_chain_a.to_string()),
+				..Default::default()
+			},
+		)
+		.await?;
+		assert!(check_agent_chain(&mm, Some(agent_b.id), fx_chain_b).await.is_err());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_validator_agent_chain_check() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?.with_agent_chain_check(agent_chain_check());
+		let agents = seed_mock_echo_agents(&mm, &[("Agent A", "")]).await?;
+		let agent_id = agents[0].id;
+		let fx_chain_ok = r#"{"nodes": [{"agent": "self"}]}"#;
+		let fx_chain_invalid = r#"{"nodes": [{"agent": "self", "flow": []}]}"#;
+
+		// -- Exec
+		let res_invalid = AgentBmc::update(
+			&mm,
+			agent_id,
+			AgentForUpdate {
+				chain: Some(fx_chain_invalid.to_string()),
+				..Default::default()
+			},
+		)
+		.await;
+		AgentBmc::update(
+			&mm,
+			agent_id,
+			AgentForUpdate {
+				chain: Some(fx_chain_ok.to_string()),
+				..Default::default()
+			},
+		)
+		.await?;
+
+		// -- Check
+		let Err(model::Error::Agent(AgentError::ChainInvalid { diagnostics, .. })) = res_invalid else {
+			return Err(format!("Should be a ChainInvalid error, but was {res_invalid:?}").into());
+		};
+		assert_eq!(diagnostics[0]["path"], "/nodes/0/flow");
+		assert_eq!(diagnostics[0]["kind"], "unknown_key");
+		let agent = AgentBmc::get(&mm, agent_id).await?;
+		assert_eq!(agent.chain.as_deref(), Some(fx_chain_ok));
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
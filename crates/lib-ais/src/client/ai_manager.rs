@@ -1,58 +1,448 @@
 // region:    --- Modules
 
-use crate::client::{AiClient, FcClient, OllamaClient};
-use crate::{ClientKind, OpenaiClient, Result};
-use lib_utils::x_vec::XStringVec;
+use crate::client::{AiClient, AnthropicClient, FcClient, OllamaClient};
+use crate::runner::RunLimiter;
+use crate::{ClientKind, Error, LogicTool, LogicToolRegistry, OpenaiClient, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
 
 // endregion: --- Modules
 
+/// Default time to live of the per provider model list cache.
+const MODELS_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Separator for the `provider::model` model name notation (e.g., `openai::gpt-4o`).
+pub(crate) const PROVIDER_SEP: &str = "::";
+
+/// The order in which the providers are looked up when the model does not specify one.
+const PROVIDERS_LOOKUP_ORDER: &[ClientKind] =
+	&[ClientKind::Fc, ClientKind::Ollama, ClientKind::Openai, ClientKind::Anthropic];
+
+/// The resolved provider and provider model name (without the eventual `provider::` prefix).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelTarget {
+	pub kind: ClientKind,
+	pub model: String,
+}
+
+/// Formats as `provider::model` (e.g., `ollama::mixtral`)
+impl core::fmt::Display for ModelTarget {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		write!(f, "{}{PROVIDER_SEP}{}", self.kind.as_str(), self.model)
+	}
+}
+
 #[cfg_attr(feature = "with-rpc", derive(rpc_router::RpcResource))]
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct AiManager {
 	ollama_client: OllamaClient,
 	openai_client: OpenaiClient,
+	anthropic_client: AnthropicClient,
 	fc_client: FcClient,
+
+	/// The tools of the `AgentKind::Logic` agents.
+	logic_tools: LogicToolRegistry,
+
+	/// The model of the drive parts vectors (no vectors when None).
+	embed_model: Option<String>,
+
+	/// The per provider and per model concurrency limits of the model runs (shared by the clones).
+	run_limiter: Arc<RunLimiter>,
+
+	models_ttl: Duration,
+	models_cache: Arc<Mutex<HashMap<ClientKind, CachedModels>>>,
+}
+
+struct CachedModels {
+	time: Instant,
+	models: Vec<String>,
+}
+
+impl Default for AiManager {
+	fn default() -> Self {
+		Self {
+			ollama_client: OllamaClient::default(),
+			openai_client: OpenaiClient::default(),
+			anthropic_client: AnthropicClient::default(),
+			fc_client: FcClient::default(),
+			logic_tools: LogicToolRegistry::default(),
+			embed_model: None,
+			run_limiter: Default::default(),
+			models_ttl: MODELS_CACHE_TTL,
+			models_cache: Default::default(),
+		}
+	}
+}
+
+/// Builder methods
+impl AiManager {
+	pub fn with_openai_client(mut self, openai_client: OpenaiClient) -> Self {
+		self.openai_client = openai_client;
+		self
+	}
+
+	pub fn with_anthropic_client(mut self, anthropic_client: AnthropicClient) -> Self {
+		self.anthropic_client = anthropic_client;
+		self
+	}
+
+	pub fn with_models_ttl(mut self, models_ttl: Duration) -> Self {
+		self.models_ttl = models_ttl;
+		self
+	}
+
+	pub fn with_embed_model(mut self, embed_model: impl Into<String>) -> Self {
+		self.embed_model = Some(embed_model.into());
+		self
+	}
+
+	pub fn with_run_limiter(mut self, run_limiter: RunLimiter) -> Self {
+		self.run_limiter = Arc::new(run_limiter);
+		self
+	}
+
+	/// Register a logic tool, in addition to the built-in ones (replacing the one with the same name).
+	pub fn with_logic_tool(mut self, tool: impl LogicTool + 'static) -> Self {
+		self.logic_tools.register(tool);
+		self
+	}
 }
 
 /// Public methods
 impl AiManager {
-	pub async fn get_client_for_model(&self, model_name: &str) -> Result<Box<dyn AiClient + Send>> {
-		let client_type = self.get_provider_for_model(model_name).await?;
+	pub fn logic_tools(&self) -> &LogicToolRegistry {
+		&self.logic_tools
+	}
 
-		self.get_client(client_type)
+	/// The limiter to acquire the run permits from, before running a model.
+	pub fn run_limiter(&self) -> &RunLimiter {
+		&self.run_limiter
 	}
 
+	/// The model of the drive parts vectors (None by default, see `with_embed_model`).
+	pub fn embed_model(&self) -> Option<&str> {
+		self.embed_model.as_deref()
+	}
+
+	/// Embed the `inputs` with a model (resolved like `get_client_for_model`).
+	///
+	/// Returns `Error::AiEmbedCountMismatch` if the client does not return one vector per input.
+	pub async fn embed(&self, model_name: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>> {
+		let (client, target) = self.get_client_for_model(None, model_name).await?;
+		let inputs_count = inputs.len();
+		let vecs = client.embed(&target.model, inputs).await?;
+
+		if vecs.len() != inputs_count {
+			return Err(Error::AiEmbedCountMismatch {
+				model: target.to_string(),
+				inputs: inputs_count,
+				vecs: vecs.len(),
+			});
+		}
+
+		Ok(vecs)
+	}
+
+	/// Resolve the provider and the client for a model name.
+	///
+	/// - `provider` is the eventual explicit provider (e.g., `Agent.provider`).
+	/// - `model_name` can be in the `provider::model` form, which takes precedence over `provider`.
+	///
+	/// Returns the client with the `ModelTarget` (which has the model name to be used with this client).
+	pub async fn get_client_for_model(
+		&self,
+		provider: Option<&str>,
+		model_name: &str,
+	) -> Result<(Box<dyn AiClient + Send>, ModelTarget)> {
+		let target = self.resolve_model(provider, model_name).await?;
+		let client = self.get_client(target.kind)?;
+
+		Ok((client, target))
+	}
+
+	/// Resolve the `ModelTarget` (provider kind and model name) for a model name.
+	///
+	/// When there is no explicit provider, the cached model lists of each provider are looked up,
+	/// and `Error::AiModelNotFound` is returned if none has it.
+	pub async fn resolve_model(&self, provider: Option<&str>, model_name: &str) -> Result<ModelTarget> {
+		// -- If explicit provider (prefix or param), we trust it
+		let (provider, model) = match model_name.split_once(PROVIDER_SEP) {
+			Some((provider, model)) => (Some(provider), model),
+			None => (provider.filter(|p| !p.trim().is_empty()), model_name),
+		};
+
+		if let Some(provider) = provider {
+			let kind =
+				ClientKind::from_provider(provider).ok_or_else(|| Error::AiProviderUnknown(provider.to_string()))?;
+			return Ok(ModelTarget {
+				kind,
+				model: model.to_string(),
+			});
+		}
+
+		// -- Otherwise, lookup the providers model lists
+		for kind in PROVIDERS_LOOKUP_ORDER {
+			let models = match self.list_models_cached(*kind).await {
+				Ok(models) => models,
+				Err(err) => {
+					warn!(
+						"AiManager - cannot list models for provider '{}'. Cause: {err}",
+						kind.as_str()
+					);
+					continue;
+				}
+			};
+			if let Some(model) = find_model(&models, model) {
+				return Ok(ModelTarget {
+					kind: *kind,
+					model: model.to_string(),
+				});
+			}
+		}
+
+		Err(Error::AiModelNotFound {
+			model: model_name.to_string(),
+		})
+	}
+
+	/// Returns the models of all of the providers.
+	/// Note: The providers which cannot list their models (e.g., not reachable) are skipped (with a warning).
 	pub async fn list_all_models(&self) -> Result<Vec<String>> {
 		let mut models = Vec::new();
 
-		models.extend(self.ollama_client.list_models().await?);
-		models.extend(self.openai_client.list_models().await?);
-		models.extend(self.fc_client.list_models().await?);
+		for kind in PROVIDERS_LOOKUP_ORDER {
+			match self.list_models_cached(*kind).await {
+				Ok(kind_models) => models.extend(kind_models),
+				Err(err) => warn!(
+					"AiManager - cannot list models for provider '{}'. Cause: {err}",
+					kind.as_str()
+				),
+			}
+		}
 
 		Ok(models)
 	}
-}
 
-/// Private methods
-impl AiManager {
-	fn get_client(&self, kind: ClientKind) -> Result<Box<dyn AiClient + Send>> {
+	pub fn get_client(&self, kind: ClientKind) -> Result<Box<dyn AiClient + Send>> {
 		match kind {
 			ClientKind::Ollama => Ok(Box::new(self.ollama_client.clone())),
 			ClientKind::Openai => Ok(Box::new(self.openai_client.clone())),
+			ClientKind::Anthropic => Ok(Box::new(self.anthropic_client.clone())),
 			ClientKind::Fc => Ok(Box::new(self.fc_client.clone())),
 		}
 	}
+}
 
-	/// Returns the provider type for a model name
-	/// FIXME: Right now, just check the mock model, and then, differ to Ollama
-	///        Will need to have full implementation, and do some caching.
-	async fn get_provider_for_model(&self, model_name: &str) -> Result<ClientKind> {
-		if self.fc_client.list_models().await?.x_contains(model_name) {
-			Ok(ClientKind::Fc)
-		}
-		// FIXME: Right now fall back on Ollama
-		else {
-			Ok(ClientKind::Ollama)
+/// Private methods
+impl AiManager {
+	/// Returns the model list of a provider, from the cache when not expired.
+	async fn list_models_cached(&self, kind: ClientKind) -> Result<Vec<String>> {
+		{
+			let cache = self.models_cache.lock().map_err(|_| Error::MutexPoison)?;
+			if let Some(cached) = cache.get(&kind) {
+				if cached.time.elapsed() < self.models_ttl {
+					return Ok(cached.models.clone());
+				}
+			}
 		}
+
+		let models = self.get_client(kind)?.list_models().await?;
+
+		let mut cache = self.models_cache.lock().map_err(|_| Error::MutexPoison)?;
+		cache.insert(
+			kind,
+			CachedModels {
+				time: Instant::now(),
+				models: models.clone(),
+			},
+		);
+
+		Ok(models)
+	}
+}
+
+// region:    --- Support
+
+/// Find the provider model name for a model name.
+/// Matches the exact name, or the name without the tag (e.g., `mixtral` for `mixtral:latest`).
+fn find_model<'a>(models: &'a [String], model: &str) -> Option<&'a str> {
+	models
+		.iter()
+		.find(|m| m.as_str() == model)
+		.or_else(|| {
+			models
+				.iter()
+				.find(|m| !model.contains(':') && m.split(':').next() == Some(model))
+		})
+		.map(String::as_str)
+}
+
+// endregion: --- Support
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::_test_support::{MockHttpServer, MockRoute};
+	use crate::OpenaiConfig;
+
+	const FX_MODELS_JSON: &str = r#"{"object": "list", "data": [
+		{"id": "gpt-mock-1", "object": "model", "created": 1700000000, "owned_by": "mock"}
+	]}"#;
+
+	async fn fx_server_and_aim(ttl: Duration) -> Result<(MockHttpServer, AiManager)> {
+		let server = MockHttpServer::start(vec![MockRoute::json("GET", "/models", FX_MODELS_JSON)]).await?;
+		let openai_client = OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key"));
+		let aim = AiManager::default().with_openai_client(openai_client).with_models_ttl(ttl);
+
+		Ok((server, aim))
+	}
+
+	#[tokio::test]
+	async fn test_resolve_model_explicit_provider() -> Result<()> {
+		// -- Setup & Fixtures
+		let aim = AiManager::default();
+
+		// -- Exec & Check
+		let target = aim.resolve_model(Some("openai"), "gpt-any").await?;
+		assert_eq!(target.kind, ClientKind::Openai);
+		assert_eq!(target.model, "gpt-any");
+
+		// prefix takes precedence over the provider param
+		let target = aim.resolve_model(Some("openai"), "ollama::mixtral:8x7b").await?;
+		assert_eq!(target.kind, ClientKind::Ollama);
+		assert_eq!(target.model, "mixtral:8x7b");
+
+		// empty provider is like no provider
+		let target = aim.resolve_model(Some(""), "fc-mock-echo-inst").await?;
+		assert_eq!(target.kind, ClientKind::Fc);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_resolve_model_unknown_provider() -> Result<()> {
+		// -- Setup & Fixtures
+		let aim = AiManager::default();
+
+		// -- Exec
+		let res = aim.resolve_model(None, "not-a-provider::some-model").await;
+
+		// -- Check
+		assert!(matches!(res, Err(crate::Error::AiProviderUnknown(p)) if p == "not-a-provider"));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_resolve_model_lookup_and_cache() -> Result<()> {
+		// -- Setup & Fixtures
+		let (server, aim) = fx_server_and_aim(Duration::from_secs(60)).await?;
+
+		// -- Exec
+		let target = aim.resolve_model(None, "gpt-mock-1").await?;
+		let _ = aim.resolve_model(None, "gpt-mock-1").await?;
+
+		// -- Check
+		assert_eq!(target.kind, ClientKind::Openai);
+		let list_count = server.requests().iter().filter(|r| r.path == "/models").count();
+		assert_eq!(list_count, 1, "model list should be cached");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_resolve_model_cache_expired() -> Result<()> {
+		// -- Setup & Fixtures
+		let (server, aim) = fx_server_and_aim(Duration::ZERO).await?;
+
+		// -- Exec
+		aim.resolve_model(None, "gpt-mock-1").await?;
+		aim.resolve_model(None, "gpt-mock-1").await?;
+
+		// -- Check
+		let list_count = server.requests().iter().filter(|r| r.path == "/models").count();
+		assert_eq!(list_count, 2, "model list should be listed again");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_resolve_model_not_found() -> Result<()> {
+		// -- Setup & Fixtures
+		let (_server, aim) = fx_server_and_aim(Duration::from_secs(60)).await?;
+
+		// -- Exec
+		let res = aim.resolve_model(None, "model-nobody-has").await;
+
+		// -- Check
+		assert!(matches!(res, Err(crate::Error::AiModelNotFound { model }) if model == "model-nobody-has"));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_list_all_models_provider_fail() -> Result<()> {
+		// -- Setup & Fixtures
+		let server = MockHttpServer::start(vec![MockRoute::json("GET", "/models", "{}").with_status(500)]).await?;
+		let openai_client = OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key"));
+		let aim = AiManager::default().with_openai_client(openai_client);
+
+		// -- Exec
+		let models = aim.list_all_models().await?;
+
+		// -- Check
+		assert!(server.requests().iter().any(|r| r.path == "/models"));
+		assert!(
+			models.iter().any(|m| m == "fc-mock-echo-inst"),
+			"Should have the fc models"
+		);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_embed_fc_mock() -> Result<()> {
+		// -- Setup & Fixtures
+		let aim = AiManager::default().with_embed_model("fc::fc-mock-embed");
+		let fx_inputs = vec!["The blue sky".to_string(), "the sky is BLUE".to_string()];
+
+		// -- Exec
+		let vecs = aim
+			.embed(aim.embed_model().ok_or("Should have embed model")?, fx_inputs)
+			.await?;
+
+		// -- Check
+		assert_eq!(vecs.len(), 2);
+		assert_eq!(vecs[0].len(), 64);
+		let dot: f32 = vecs[0].iter().zip(vecs[1].iter()).map(|(a, b)| a * b).sum();
+		assert!(dot > 0.7, "Should be similar, but dot was {dot}");
+
+		// Anthropic has no embeddings
+		let res = aim.embed("anthropic::claude-any", vec!["sky".to_string()]).await;
+		assert!(matches!(res, Err(crate::Error::AiEmbedNotSupported { .. })));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_find_model_tag() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_models = vec!["mixtral:latest".to_string(), "llama3:8b".to_string()];
+
+		// -- Exec & Check
+		assert_eq!(find_model(&fx_models, "mixtral"), Some("mixtral:latest"));
+		assert_eq!(find_model(&fx_models, "llama3:8b"), Some("llama3:8b"));
+		assert_eq!(find_model(&fx_models, "llama3:70b"), None);
+
+		Ok(())
 	}
 }
+
+// endregion: --- Tests
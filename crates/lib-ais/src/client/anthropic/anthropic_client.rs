@@ -0,0 +1,645 @@
+use crate::client::sse::sse_events;
+use crate::client::AiClient;
+use crate::types::{GenResChunk, GenResStream};
+use crate::{ChatRole, Error, GenReq, GenRes, GenUsage, Result, ToolCall};
+use async_trait::async_trait;
+use futures::StreamExt;
+use lib_core::model::agent::OutFormat;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::debug;
+
+const ANTHROPIC_API_BASE: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const ANTHROPIC_MAX_TOKENS: u32 = 1024;
+
+const ENV_ANTHROPIC_API_KEY: &str = "ANTHROPIC_API_KEY";
+const ENV_ANTHROPIC_API_BASE: &str = "ANTHROPIC_API_BASE";
+
+/// The system instruction appended for `OutFormat::Json`, since the Messages API has no JSON mode.
+/// The assistant answer is also prefilled with `{` (and `{` is prepended to the response),
+/// unless the request has tools (as the model could not call them).
+const JSON_MODE_INST: &str = "Respond only with a valid JSON object, without any other text before or after.";
+const JSON_MODE_PREFILL: &str = "{";
+
+// region:    --- AnthropicConfig
+
+/// Connection settings for the Anthropic Messages API.
+#[derive(Debug, Clone)]
+pub struct AnthropicConfig {
+	/// e.g., `https://api.anthropic.com/v1`
+	pub api_base: String,
+	pub api_key: String,
+	/// Required by the Messages API.
+	pub max_tokens: u32,
+}
+
+impl AnthropicConfig {
+	pub fn new(api_base: impl Into<String>, api_key: impl Into<String>) -> Self {
+		Self {
+			api_base: api_base.into(),
+			api_key: api_key.into(),
+			max_tokens: ANTHROPIC_MAX_TOKENS,
+		}
+	}
+
+	/// Returns the config from the `ANTHROPIC_API_KEY` (required) and `ANTHROPIC_API_BASE` (optional) env vars.
+	pub fn from_env() -> Option<Self> {
+		let api_key = std::env::var(ENV_ANTHROPIC_API_KEY).ok()?;
+		let api_base = std::env::var(ENV_ANTHROPIC_API_BASE).unwrap_or_else(|_| ANTHROPIC_API_BASE.to_string());
+
+		Some(Self::new(api_base, api_key))
+	}
+}
+
+// endregion: --- AnthropicConfig
+
+/// Client for the Anthropic Messages API.
+///
+/// When not configured (no config given, and no `ANTHROPIC_API_KEY`), it lists no models
+/// and fails on generation.
+#[derive(Clone)]
+pub struct AnthropicClient {
+	conn: Option<Arc<AnthropicConn>>,
+}
+
+struct AnthropicConn {
+	http: reqwest::Client,
+	config: AnthropicConfig,
+}
+
+impl Default for AnthropicClient {
+	fn default() -> Self {
+		Self {
+			conn: AnthropicConfig::from_env().map(AnthropicConn::new),
+		}
+	}
+}
+
+impl AnthropicClient {
+	pub fn new(config: AnthropicConfig) -> Self {
+		Self {
+			conn: Some(AnthropicConn::new(config)),
+		}
+	}
+
+	fn conn(&self) -> Result<&AnthropicConn> {
+		self.conn.as_deref().ok_or(Error::AnthropicNotConfigured)
+	}
+}
+
+impl AnthropicConn {
+	fn new(config: AnthropicConfig) -> Arc<Self> {
+		Arc::new(Self {
+			http: reqwest::Client::new(),
+			config,
+		})
+	}
+
+	fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+		let url = format!("{}{path}", self.config.api_base.trim_end_matches('/'));
+		self.http
+			.request(method, url)
+			.header("x-api-key", &self.config.api_key)
+			.header("anthropic-version", ANTHROPIC_VERSION)
+	}
+
+	/// Send the request, and return an error with the response body if not a success status.
+	async fn send(&self, req: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+		let res = req.send().await?;
+		let status = res.status();
+		if !status.is_success() {
+			let body = res.text().await.unwrap_or_default();
+			return Err(Error::AnthropicHttp {
+				status: status.as_u16(),
+				body,
+			});
+		}
+		Ok(res)
+	}
+}
+
+#[async_trait]
+impl AiClient for AnthropicClient {
+	async fn list_models(&self) -> Result<Vec<String>> {
+		let Some(conn) = self.conn.as_deref() else {
+			return Ok(Vec::new());
+		};
+
+		let res = conn.send(conn.request(reqwest::Method::GET, "/models")).await?;
+		let res: AnthropicModelsRes = res.json().await?;
+		let models = res.data.into_iter().map(|m| m.id).collect();
+
+		Ok(models)
+	}
+
+	async fn gen(&self, model: &str, req: GenReq) -> Result<GenRes> {
+		let conn = self.conn()?;
+
+		let json_prefill = req.has_json_prefill();
+		let body = req.into_anthropic_body(model, conn.config.max_tokens, false);
+		debug!("AnthropicClient.gen model: {model}");
+		let res = conn.send(conn.request(reqwest::Method::POST, "/messages").json(&body)).await?;
+		let res: AnthropicMessageRes = res.json().await?;
+		debug!("AnthropicClient.gen DONE");
+
+		let mut response = String::new();
+		let mut tool_calls = Vec::new();
+		for block in res.content {
+			match (block.typ.as_str(), block.text, block.id, block.name) {
+				("text", Some(text), _, _) => response.push_str(&text),
+				("tool_use", _, Some(call_id), Some(fn_name)) => tool_calls.push(ToolCall {
+					call_id,
+					fn_name,
+					fn_arguments: block.input.unwrap_or_default(),
+				}),
+				_ => (),
+			}
+		}
+		if json_prefill {
+			response.insert_str(0, JSON_MODE_PREFILL);
+		}
+
+		let usage = res
+			.usage
+			.map(|u| GenUsage {
+				prompt_tokens: u.input_tokens,
+				completion_tokens: u.output_tokens,
+			})
+			.unwrap_or_default();
+
+		Ok(GenRes {
+			response,
+			tool_calls,
+			usage,
+		})
+	}
+
+	async fn gen_stream(&self, model: &str, req: GenReq) -> Result<GenResStream> {
+		let conn = self.conn()?;
+
+		let json_prefill = req.has_json_prefill();
+		let body = req.into_anthropic_body(model, conn.config.max_tokens, true);
+		let res = conn.send(conn.request(reqwest::Method::POST, "/messages").json(&body)).await?;
+
+		let prefill = json_prefill.then(|| {
+			Ok(vec![GenResChunk {
+				response: JSON_MODE_PREFILL.to_string(),
+				usage: None,
+			}])
+		});
+
+		let stream = sse_events(res).filter_map(|evt| async move {
+			let evt = match evt {
+				Ok(evt) => evt,
+				Err(err) => return Some(Err(Error::from(err))),
+			};
+			let event = evt.event.as_deref();
+			if event == Some("error") {
+				return Some(Err(Error::AnthropicStreamError(evt.data)));
+			}
+			if !matches!(event, Some("content_block_delta" | "message_start" | "message_delta")) {
+				return None;
+			}
+			let data: Value = match serde_json::from_str(&evt.data) {
+				Ok(data) => data,
+				Err(err) => return Some(Err(Error::AnthropicFailParse(err))),
+			};
+			let chunk = match event {
+				Some("content_block_delta") => GenResChunk {
+					response: data.pointer("/delta/text").and_then(Value::as_str)?.to_string(),
+					usage: None,
+				},
+				// Note: The input tokens are in `message_start`, and the (cumulative) output tokens in `message_delta`.
+				Some("message_start") => GenResChunk {
+					response: String::new(),
+					usage: Some(GenUsage {
+						prompt_tokens: data.pointer("/message/usage/input_tokens").and_then(Value::as_i64),
+						completion_tokens: None,
+					}),
+				},
+				_ => GenResChunk {
+					response: String::new(),
+					usage: Some(GenUsage {
+						prompt_tokens: None,
+						completion_tokens: data.pointer("/usage/output_tokens").and_then(Value::as_i64),
+					}),
+				},
+			};
+			Some(Ok(vec![chunk]))
+		});
+		let stream = futures::stream::iter(prefill).chain(stream);
+
+		Ok(Box::pin(stream))
+	}
+
+	/// Note: Anthropic has no embeddings API.
+	async fn embed(&self, _model: &str, _inputs: Vec<String>) -> Result<Vec<Vec<f32>>> {
+		Err(Error::AiEmbedNotSupported { provider: "anthropic" })
+	}
+}
+
+// region:    --- Anthropic Types
+
+#[derive(Deserialize)]
+struct AnthropicModelsRes {
+	data: Vec<AnthropicModel>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicModel {
+	id: String,
+}
+
+#[derive(Deserialize)]
+struct AnthropicMessageRes {
+	content: Vec<AnthropicContentBlock>,
+	usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicUsage {
+	input_tokens: Option<i64>,
+	output_tokens: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicContentBlock {
+	#[serde(rename = "type")]
+	typ: String,
+	text: Option<String>,
+
+	// -- For the `tool_use` blocks
+	id: Option<String>,
+	name: Option<String>,
+	input: Option<Value>,
+}
+
+// endregion: --- Anthropic Types
+
+// region:    --- Custom Intos
+
+impl GenReq {
+	fn has_json_prefill(&self) -> bool {
+		matches!(self.out_format, Some(OutFormat::Json)) && self.tools.is_empty()
+	}
+
+	fn into_anthropic_body(self, model: &str, max_tokens: u32, stream: bool) -> Value {
+		let json_mode = matches!(self.out_format, Some(OutFormat::Json));
+		let json_prefill = self.has_json_prefill();
+
+		let system = match (self.inst, json_mode) {
+			(Some(inst), true) => Some(format!("{inst}\n\n{JSON_MODE_INST}")),
+			(None, true) => Some(JSON_MODE_INST.to_string()),
+			(inst, false) => inst,
+		};
+
+		// The Messages API requires alternating roles, so consecutive same role messages are merged.
+		// Note: The tool results are `user` messages, with `tool_result` content blocks.
+		// Note: The Messages API also requires a `user` first message, so the turns before the first
+		//       user message are dropped (e.g., a history window starting with an answer).
+		let mut messages: Vec<Value> = Vec::new();
+		for msg in self.messages.into_iter().skip_while(|msg| msg.role != ChatRole::User) {
+			let (role, content) = match msg.role {
+				ChatRole::User => ("user", Value::String(msg.content)),
+				ChatRole::Assistant if msg.tool_calls.is_empty() => ("assistant", Value::String(msg.content)),
+				ChatRole::Assistant => {
+					let mut blocks = text_blocks(Value::String(msg.content));
+					for tool_call in msg.tool_calls {
+						blocks.push(json!({
+							"type": "tool_use",
+							"id": tool_call.call_id,
+							"name": tool_call.fn_name,
+							"input": tool_call.fn_arguments,
+						}));
+					}
+					("assistant", Value::Array(blocks))
+				}
+				ChatRole::Tool => (
+					"user",
+					json!([{
+						"type": "tool_result",
+						"tool_use_id": msg.tool_call_id.unwrap_or_default(),
+						"content": msg.content,
+					}]),
+				),
+			};
+			match messages.last_mut() {
+				Some(last) if last["role"] == role => {
+					last["content"] = match (last["content"].take(), content) {
+						(Value::String(last_content), Value::String(content)) => {
+							format!("{last_content}\n\n{content}").into()
+						}
+						(last_content, content) => {
+							let mut blocks = text_blocks(last_content);
+							blocks.extend(text_blocks(content));
+							Value::Array(blocks)
+						}
+					};
+				}
+				_ => messages.push(json!({"role": role, "content": content})),
+			}
+		}
+		if json_prefill {
+			messages.push(json!({"role": "assistant", "content": JSON_MODE_PREFILL}));
+		}
+
+		let mut body = json!({
+			"model": model,
+			"max_tokens": max_tokens,
+			"messages": messages,
+		});
+		if let Some(system) = system {
+			body["system"] = system.into();
+		}
+		if !self.tools.is_empty() {
+			let tools: Vec<Value> = self
+				.tools
+				.into_iter()
+				.map(|tool| {
+					json!({
+						"name": tool.fn_name,
+						"description": tool.fn_description,
+						"input_schema": tool.params,
+					})
+				})
+				.collect();
+			body["tools"] = tools.into();
+		}
+		if stream {
+			body["stream"] = true.into();
+		}
+
+		body
+	}
+}
+
+/// Returns the content as content blocks (a text content becomes a text block, none if empty).
+fn text_blocks(content: Value) -> Vec<Value> {
+	match content {
+		Value::Array(blocks) => blocks,
+		Value::String(text) if text.is_empty() => Vec::new(),
+		Value::String(text) => vec![json!({"type": "text", "text": text})],
+		other => vec![other],
+	}
+}
+
+// endregion: --- Custom Intos
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::_test_support::{MockHttpServer, MockRoute};
+	use crate::ChatMsg;
+
+	const FX_MODELS_JSON: &str = r#"{"data": [
+		{"type": "model", "id": "claude-mock-1", "display_name": "Mock 1", "created_at": "2024-01-01T00:00:00Z"}
+	], "has_more": false}"#;
+
+	const FX_MESSAGE_JSON: &str = r#"{
+		"id": "msg_1", "type": "message", "role": "assistant", "model": "claude-mock-1",
+		"content": [{"type": "text", "text": "\"answer\": 42}"}],
+		"stop_reason": "end_turn", "usage": {"input_tokens": 20, "output_tokens": 6}
+	}"#;
+
+	// Recorded (and trimmed) Messages API SSE response.
+	const FX_MESSAGE_SSE: &str = "event: message_start
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"claude-mock-1\",\"usage\":{\"input_tokens\":10,\"output_tokens\":1}}}
+
+event: content_block_start
+data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}
+
+event: ping
+data: {\"type\": \"ping\"}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" world\"}}
+
+event: content_block_stop
+data: {\"type\":\"content_block_stop\",\"index\":0}
+
+event: message_delta
+data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":2}}
+
+event: message_stop
+data: {\"type\":\"message_stop\"}
+
+";
+
+	async fn fx_server_and_client() -> Result<(MockHttpServer, AnthropicClient)> {
+		let server = MockHttpServer::start(vec![
+			MockRoute::json("GET", "/v1/models", FX_MODELS_JSON),
+			MockRoute::json("POST", "/v1/messages", FX_MESSAGE_JSON),
+		])
+		.await?;
+		let client = AnthropicClient::new(AnthropicConfig::new(format!("{}/v1", server.base_url()), "fx-key"));
+
+		Ok((server, client))
+	}
+
+	#[tokio::test]
+	async fn test_anthropic_list_models() -> Result<()> {
+		// -- Setup & Fixtures
+		let (server, client) = fx_server_and_client().await?;
+
+		// -- Exec
+		let models = client.list_models().await?;
+
+		// -- Check
+		assert_eq!(models, vec!["claude-mock-1"]);
+		let req = &server.requests()[0];
+		assert_eq!(req.header("x-api-key"), Some("fx-key"));
+		assert_eq!(req.header("anthropic-version"), Some(ANTHROPIC_VERSION));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_anthropic_gen_json_mode() -> Result<()> {
+		// -- Setup & Fixtures
+		let (server, client) = fx_server_and_client().await?;
+		let fx_req = GenReq {
+			messages: vec![ChatMsg::user("Hi"), ChatMsg::user("What is the answer?")],
+			inst: Some("You know everything.".to_string()),
+			out_format: Some(OutFormat::Json),
+			tools: Vec::new(),
+		};
+
+		// -- Exec
+		let res = client.gen("claude-mock-1", fx_req).await?;
+
+		// -- Check
+		let answer: Value = serde_json::from_str(&res.response)?;
+		assert_eq!(answer["answer"], 42);
+		assert_eq!(res.usage.prompt_tokens, Some(20));
+		assert_eq!(res.usage.completion_tokens, Some(6));
+		let body = server.requests()[0].body_json()?;
+		assert_eq!(body["model"], "claude-mock-1");
+		assert!(body["system"]
+			.as_str()
+			.ok_or("Should have system")?
+			.starts_with("You know everything."));
+		assert_eq!(body["messages"][0]["role"], "user");
+		assert_eq!(body["messages"][0]["content"], "Hi\n\nWhat is the answer?");
+		assert_eq!(body["messages"][1]["role"], "assistant");
+		assert_eq!(body["messages"][1]["content"], "{");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_anthropic_gen_leading_assistant() -> Result<()> {
+		// -- Setup & Fixtures
+		let (server, client) = fx_server_and_client().await?;
+		let fx_req = GenReq {
+			messages: vec![
+				ChatMsg::assistant("answer 1"),
+				ChatMsg::user("question 2"),
+				ChatMsg::assistant("answer 2"),
+				ChatMsg::user("question 3"),
+			],
+			inst: None,
+			out_format: None,
+			tools: Vec::new(),
+		};
+
+		// -- Exec
+		client.gen("claude-mock-1", fx_req).await?;
+
+		// -- Check
+		let body = server.requests()[0].body_json()?;
+		let roles: Vec<&str> = body["messages"]
+			.as_array()
+			.ok_or("Should have messages")?
+			.iter()
+			.filter_map(|msg| msg["role"].as_str())
+			.collect();
+		assert_eq!(roles, ["user", "assistant", "user"]);
+		assert_eq!(body["messages"][0]["content"], "question 2");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_anthropic_gen_stream() -> Result<()> {
+		// -- Setup & Fixtures
+		let server = MockHttpServer::start(vec![MockRoute::sse("POST", "/messages", FX_MESSAGE_SSE)]).await?;
+		let client = AnthropicClient::new(AnthropicConfig::new(server.base_url(), "fx-key"));
+
+		// -- Exec
+		let mut stream = client.gen_stream("claude-mock-1", "Say hello".into()).await?;
+		let mut content = String::new();
+		let mut usage = GenUsage::default();
+		while let Some(chunks) = stream.next().await {
+			for chunk in chunks? {
+				content.push_str(&chunk.response);
+				usage.add(&chunk.usage.unwrap_or_default());
+			}
+		}
+
+		// -- Check
+		assert_eq!(content, "Hello world");
+		assert_eq!(usage.prompt_tokens, Some(10));
+		assert_eq!(usage.completion_tokens, Some(2));
+		let body = server.requests()[0].body_json()?;
+		assert_eq!(body["stream"], true);
+		assert!(body.get("system").is_none());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_anthropic_gen_http_error() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_err = r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/messages", fx_err).with_status(529)]).await?;
+		let client = AnthropicClient::new(AnthropicConfig::new(server.base_url(), "fx-key"));
+
+		// -- Exec
+		let res = client.gen("claude-mock-1", "Say hello".into()).await;
+
+		// -- Check
+		assert!(matches!(res, Err(crate::Error::AnthropicHttp { status: 529, .. })));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_anthropic_gen_tool_calls() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_res = r#"{
+			"id": "msg_2", "type": "message", "role": "assistant", "model": "claude-mock-1",
+			"content": [
+				{"type": "text", "text": "Let me search."},
+				{"type": "tool_use", "id": "toolu_1", "name": "drive_search", "input": {"query": "sky"}}
+			],
+			"stop_reason": "tool_use", "usage": {"input_tokens": 30, "output_tokens": 10}
+		}"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/messages", fx_res)]).await?;
+		let client = AnthropicClient::new(AnthropicConfig::new(server.base_url(), "fx-key"));
+		let fx_tool_call = ToolCall {
+			call_id: "toolu_0".to_string(),
+			fn_name: "list_files".to_string(),
+			fn_arguments: json!({}),
+		};
+		let fx_req = GenReq {
+			messages: vec![
+				ChatMsg::user("Find the sky files"),
+				ChatMsg::assistant_tool_calls("", vec![fx_tool_call]),
+				ChatMsg::tool_result("toolu_0", "[]"),
+				ChatMsg::user("Only the sky ones"),
+			],
+			inst: None,
+			out_format: Some(OutFormat::Json),
+			tools: vec![crate::Tool {
+				fn_name: "drive_search".to_string(),
+				fn_description: "Search the drives".to_string(),
+				params: json!({"type": "object"}),
+			}],
+		};
+
+		// -- Exec
+		let res = client.gen("claude-mock-1", fx_req).await?;
+
+		// -- Check
+		assert_eq!(res.response, "Let me search.");
+		assert_eq!(
+			res.tool_calls,
+			vec![ToolCall {
+				call_id: "toolu_1".to_string(),
+				fn_name: "drive_search".to_string(),
+				fn_arguments: json!({"query": "sky"}),
+			}]
+		);
+		let body = server.requests()[0].body_json()?;
+		assert_eq!(body["tools"][0]["name"], "drive_search");
+		assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+		assert_eq!(
+			body["messages"][1]["content"],
+			json!([{"type": "tool_use", "id": "toolu_0", "name": "list_files", "input": {}}])
+		);
+		assert_eq!(body["messages"][2]["role"], "user");
+		assert_eq!(
+			body["messages"][2]["content"],
+			json!([
+				{"type": "tool_result", "tool_use_id": "toolu_0", "content": "[]"},
+				{"type": "text", "text": "Only the sky ones"}
+			])
+		);
+		// no prefill with tools
+		assert_eq!(body["messages"].as_array().map(|m| m.len()), Some(3));
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
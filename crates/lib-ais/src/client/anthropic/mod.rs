@@ -0,0 +1,7 @@
+// region:    --- Modules
+
+mod anthropic_client;
+
+pub use anthropic_client::*;
+
+// endregion: --- Modules
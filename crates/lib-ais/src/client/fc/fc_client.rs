@@ -1,11 +1,17 @@
 use crate::client::AiClient;
+use crate::{ChatRole, GenReq, GenRes, GenResChunk, GenResChunks, GenResStream, ToolCall};
 use crate::{Error, Result};
-use crate::{GenReq, GenRes, GenResStream};
 use async_trait::async_trait;
 use lib_utils::s;
+use serde_json::Value;
 
 const FC_MODEL_MOCK_ECHO_INST: &str = "fc-mock-echo-inst";
-const FC_MODEL_MOCK_ECHO_PROMPT: &str = "fc-mock-echo-prompt";
+pub(crate) const FC_MODEL_MOCK_ECHO_PROMPT: &str = "fc-mock-echo-prompt";
+/// Calls the first tool with the prompt as arguments, then answers with the tool result.
+const FC_MODEL_MOCK_TOOL_CALL: &str = "fc-mock-tool-call";
+/// Embeds the inputs as normalized bags of words (the words hashed into `FC_MOCK_EMBED_DIM` buckets).
+pub(crate) const FC_MODEL_MOCK_EMBED: &str = "fc-mock-embed";
+const FC_MOCK_EMBED_DIM: usize = 64;
 
 #[derive(Clone, Default)]
 pub struct FcClient {}
@@ -13,20 +19,91 @@ pub struct FcClient {}
 #[async_trait]
 impl AiClient for FcClient {
 	async fn list_models(&self) -> Result<Vec<String>> {
-		Ok(vec![s!(FC_MODEL_MOCK_ECHO_INST)])
+		Ok(vec![
+			s!(FC_MODEL_MOCK_ECHO_INST),
+			s!(FC_MODEL_MOCK_ECHO_PROMPT),
+			s!(FC_MODEL_MOCK_TOOL_CALL),
+			s!(FC_MODEL_MOCK_EMBED),
+		])
 	}
 
 	async fn gen(&self, model: &str, req: GenReq) -> Result<GenRes> {
 		match model {
 			FC_MODEL_MOCK_ECHO_INST => Ok(GenRes {
 				response: req.inst.unwrap_or_default().to_string(),
+				..Default::default()
 			}),
-			FC_MODEL_MOCK_ECHO_PROMPT => Ok(GenRes { response: req.prompt }),
+			FC_MODEL_MOCK_ECHO_PROMPT => Ok(GenRes {
+				response: req.last_user_content().unwrap_or_default().to_string(),
+				..Default::default()
+			}),
+			FC_MODEL_MOCK_TOOL_CALL => Ok(mock_tool_call_res(req)),
+			_ => Err(Error::AiModelNotImplemented(model.to_string())),
+		}
+	}
+
+	/// Mock stream, the `gen` response split in word chunks.
+	async fn gen_stream(&self, model: &str, req: GenReq) -> Result<GenResStream> {
+		let res = self.gen(model, req).await?;
+		let chunks: Vec<Result<GenResChunks>> = res
+			.response
+			.split_inclusive(' ')
+			.map(|word| {
+				Ok(vec![GenResChunk {
+					response: word.to_string(),
+					usage: None,
+				}])
+			})
+			.collect();
+
+		Ok(Box::pin(futures::stream::iter(chunks)))
+	}
+
+	async fn embed(&self, model: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>> {
+		match model {
+			FC_MODEL_MOCK_EMBED => Ok(inputs.iter().map(|input| mock_embed_vec(input)).collect()),
 			_ => Err(Error::AiModelNotImplemented(model.to_string())),
 		}
 	}
+}
+
+/// Note: Deterministic, so the inputs sharing words have close vectors.
+fn mock_embed_vec(input: &str) -> Vec<f32> {
+	let mut vec = vec![0f32; FC_MOCK_EMBED_DIM];
+	for word in input.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+		let hash = word
+			.to_lowercase()
+			.bytes()
+			.fold(0usize, |hash, b| hash.wrapping_mul(31).wrapping_add(b as usize));
+		vec[hash % FC_MOCK_EMBED_DIM] += 1.;
+	}
+
+	let norm = vec.iter().map(|v| v * v).sum::<f32>().sqrt();
+	if norm > 0. {
+		vec.iter_mut().for_each(|v| *v /= norm);
+	}
+
+	vec
+}
 
-	async fn gen_stream(&self, _model: &str, _req: GenReq) -> Result<GenResStream> {
-		todo!("MockClient gen_stream not implemented")
+fn mock_tool_call_res(req: GenReq) -> GenRes {
+	let prompt = req.last_user_content().unwrap_or_default().to_string();
+	match (req.messages.last(), req.tools.first()) {
+		(Some(msg), _) if matches!(msg.role, ChatRole::Tool) => GenRes {
+			response: msg.content.to_string(),
+			..Default::default()
+		},
+		(_, Some(tool)) => GenRes {
+			tool_calls: vec![ToolCall {
+				call_id: s!("fc-call-0"),
+				fn_name: tool.fn_name.to_string(),
+				fn_arguments: Value::String(prompt),
+			}],
+			..Default::default()
+		},
+		_ => GenRes {
+			response: prompt,
+			..Default::default()
+		},
 	}
 }
@@ -1,11 +1,14 @@
 // region:    --- Modules
 
 mod ai_manager;
+mod anthropic;
 mod fc;
 mod ollama_client;
 mod openai_client;
+mod sse;
 
 pub use ai_manager::*;
+pub use anthropic::*;
 pub use fc::*;
 pub use ollama_client::*;
 pub use openai_client::*;
@@ -15,12 +18,36 @@ use async_trait::async_trait;
 
 // endregion: --- Modules
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ClientKind {
 	Ollama,
 	Openai,
+	Anthropic,
 	Fc,
 }
 
+impl ClientKind {
+	/// Returns the ClientKind for a provider name (e.g., `Agent.provider`), case insensitive.
+	pub fn from_provider(provider: &str) -> Option<Self> {
+		match provider.trim().to_lowercase().as_str() {
+			"ollama" => Some(Self::Ollama),
+			"openai" => Some(Self::Openai),
+			"anthropic" => Some(Self::Anthropic),
+			"fc" => Some(Self::Fc),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Ollama => "ollama",
+			Self::Openai => "openai",
+			Self::Anthropic => "anthropic",
+			Self::Fc => "fc",
+		}
+	}
+}
+
 #[async_trait]
 pub trait AiClient {
 	async fn list_models(&self) -> Result<Vec<String>>;
@@ -28,4 +55,7 @@ pub trait AiClient {
 	async fn gen(&self, model: &str, req: GenReq) -> Result<GenRes>;
 
 	async fn gen_stream(&self, model: &str, req: GenReq) -> Result<GenResStream>;
+
+	/// Returns the embedding vectors of the `inputs` (one per input, in the same order).
+	async fn embed(&self, model: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>>;
 }
@@ -1,19 +1,47 @@
 use super::AiClient;
 use crate::types::{GenResChunk, GenResStream};
-use crate::{Error, GenReq, GenRes, Result};
+use crate::{ChatRole, Error, GenReq, GenRes, GenUsage, Result, ToolCall};
 use async_trait::async_trait;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use lib_core::model::agent::OutFormat;
-use ollama_rs::generation::completion::request::GenerationRequest;
-use ollama_rs::generation::completion::GenerationResponse;
-use ollama_rs::generation::parameters::FormatType;
 use ollama_rs::Ollama;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::pin::Pin;
 use std::sync::Arc;
 use tracing::debug;
 
 #[derive(Default, Clone)]
 pub struct OllamaClient {
 	client: Arc<Ollama>,
+	/// For the chat and embed requests (see `OllamaClient::post`).
+	http: reqwest::Client,
+}
+
+impl OllamaClient {
+	/// e.g., `OllamaClient::new("http://127.0.0.1", 11434)` (the default)
+	pub fn new(host: impl Into<String>, port: u16) -> Self {
+		Self {
+			client: Arc::new(Ollama::new(host.into(), port)),
+			http: reqwest::Client::new(),
+		}
+	}
+
+	/// Post the body to the `path` endpoint, and return an error with the response body if not a success status
+	/// (e.g., `{"error": "model 'x' not found"}`).
+	async fn post(&self, path: &str, body: &Value) -> Result<reqwest::Response> {
+		let url = format!("{}{path}", self.client.uri());
+		let res = self.http.post(url).json(body).send().await?;
+		let status = res.status();
+		if !status.is_success() {
+			let body = res.text().await.unwrap_or_default();
+			return Err(Error::OllamaHttp {
+				status: status.as_u16(),
+				body,
+			});
+		}
+		Ok(res)
+	}
 }
 
 #[async_trait]
@@ -25,76 +53,236 @@ impl AiClient for OllamaClient {
 		Ok(models)
 	}
 
+	/// Note: The chat requests are sent with `self.http` (rather than `ollama_rs`), for the tool calls,
+	///       and to keep the http status and cause of the errors.
 	async fn gen(&self, model: &str, req: GenReq) -> Result<GenRes> {
-		let ollama = &self.client;
-
-		let ola_req = req.into_ollama_req(model);
-		debug!(
-			"OllamaClient.gen model: {}, format: {:?}",
-			ola_req.model_name, ola_req.format
-		);
-		let ola_res = ollama
-			.generate(ola_req)
-			.await
-			.map_err(|oe| Error::OllamaCustom(oe.to_string()))?;
+		let body = req.into_ollama_body(model, false);
+		debug!("OllamaClient.gen model: {model}");
+		let res: OllamaChatRes = self.post("/api/chat", &body).await?.json().await?;
 		debug!("OllamaClient.gen DONE");
 
-		Ok(ola_res.into())
+		Ok(res.into())
 	}
 
 	async fn gen_stream(&self, model: &str, req: GenReq) -> Result<GenResStream> {
-		let ollama = &self.client;
-
-		let ola_req = req.into_ollama_req(model);
-		let ollama_stream = ollama.generate_stream(ola_req).await.unwrap();
+		let body = req.into_ollama_body(model, true);
+		debug!("OllamaClient.gen_stream model: {model}");
+		let res = self.post("/api/chat", &body).await?;
 
-		let stream = ollama_stream.map(|chunk_result| {
-			chunk_result
-				.map(|chunk| {
-					let res: Vec<GenResChunk> = chunk.into_iter().map(GenResChunk::from).collect();
-					res
-				})
-				.map_err(Error::Ollama)
+		let stream = ndjson_lines(res).map(|line| {
+			let chunk: OllamaStreamRes = serde_json::from_slice(&line?).map_err(Error::OllamaFailParse)?;
+			if let Some(err) = chunk.error {
+				return Err(Error::OllamaStreamError(err));
+			}
+			// Note: The token counts are on the last (`done`) line.
+			let usage = (chunk.prompt_eval_count.is_some() || chunk.eval_count.is_some()).then_some(GenUsage {
+				prompt_tokens: chunk.prompt_eval_count,
+				completion_tokens: chunk.eval_count,
+			});
+			let response = chunk.message.map(|msg| msg.content).unwrap_or_default();
+			Ok(vec![GenResChunk { response, usage }])
 		});
 
 		Ok(Box::pin(stream))
 	}
+
+	/// Note: Uses the `/api/embed` endpoint (batch inputs), as `ollama_rs` only has the single prompt one.
+	async fn embed(&self, model: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>> {
+		debug!("OllamaClient.embed model: {model}, inputs: {}", inputs.len());
+		let body = json!({
+			"model": model,
+			"input": inputs,
+		});
+		let res: OllamaEmbedRes = self.post("/api/embed", &body).await?.json().await?;
+		debug!("OllamaClient.embed DONE");
+
+		Ok(res.embeddings)
+	}
 }
 
 // region:    --- Custom Intos
 
 impl GenReq {
-	fn into_ollama_req(self, model: &str) -> GenerationRequest {
-		let mut ola_req = GenerationRequest::new(model.to_string(), self.prompt);
-		ola_req.system = self.inst;
-		ola_req.format = match self.out_format {
-			Some(OutFormat::Json) => Some(FormatType::Json),
-			_ => None,
-		};
+	/// The `/api/chat` body, with the tool calls and tool messages.
+	fn into_ollama_body(self, model: &str, stream: bool) -> Value {
+		let mut messages: Vec<Value> = Vec::new();
+		if let Some(inst) = self.inst {
+			messages.push(json!({"role": "system", "content": inst}));
+		}
+		for msg in self.messages {
+			let msg = match msg.role {
+				ChatRole::User => json!({"role": "user", "content": msg.content}),
+				ChatRole::Assistant => {
+					let tool_calls: Vec<Value> = msg
+						.tool_calls
+						.into_iter()
+						.map(
+							|tool_call| json!({"function": {"name": tool_call.fn_name, "arguments": tool_call.fn_arguments}}),
+						)
+						.collect();
+					json!({"role": "assistant", "content": msg.content, "tool_calls": tool_calls})
+				}
+				ChatRole::Tool => json!({"role": "tool", "content": msg.content}),
+			};
+			messages.push(msg);
+		}
+
+		let tools: Vec<Value> = self
+			.tools
+			.into_iter()
+			.map(|tool| {
+				json!({
+					"type": "function",
+					"function": {
+						"name": tool.fn_name,
+						"description": tool.fn_description,
+						"parameters": tool.params,
+					}
+				})
+			})
+			.collect();
 
-		ola_req
+		let mut body = json!({
+			"model": model,
+			"messages": messages,
+			"stream": stream,
+		});
+		if !tools.is_empty() {
+			body["tools"] = tools.into();
+		}
+		if let Some(OutFormat::Json) = self.out_format {
+			body["format"] = "json".into();
+		}
+
+		body
 	}
 }
 
 // endregion: --- Custom Intos
 
-// region:    --- Froms
+// region:    --- Ollama Chat Types
 
-impl From<GenerationResponse> for GenResChunk {
-	fn from(val: GenerationResponse) -> Self {
-		GenResChunk { response: val.response }
-	}
+#[derive(Deserialize)]
+struct OllamaChatRes {
+	message: Option<OllamaMsg>,
+	prompt_eval_count: Option<i64>,
+	eval_count: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct OllamaMsg {
+	#[serde(default)]
+	content: String,
+	#[serde(default)]
+	tool_calls: Vec<OllamaToolCall>,
 }
 
-impl From<GenerationResponse> for GenRes {
-	fn from(value: GenerationResponse) -> Self {
+#[derive(Deserialize)]
+struct OllamaToolCall {
+	function: OllamaFnCall,
+}
+
+#[derive(Deserialize)]
+struct OllamaFnCall {
+	name: String,
+	#[serde(default)]
+	arguments: Value,
+}
+
+/// Note: Ollama has no tool call ids, so they are generated from the call index (e.g., `call_0`).
+impl From<OllamaChatRes> for GenRes {
+	fn from(value: OllamaChatRes) -> Self {
+		let usage = GenUsage {
+			prompt_tokens: value.prompt_eval_count,
+			completion_tokens: value.eval_count,
+		};
+		let (response, tool_calls) = match value.message {
+			Some(msg) => {
+				let tool_calls = msg
+					.tool_calls
+					.into_iter()
+					.enumerate()
+					.map(|(idx, tool_call)| ToolCall {
+						call_id: format!("call_{idx}"),
+						fn_name: tool_call.function.name,
+						fn_arguments: tool_call.function.arguments,
+					})
+					.collect();
+				(msg.content, tool_calls)
+			}
+			None => (String::new(), Vec::new()),
+		};
+
 		Self {
-			response: value.response,
+			response,
+			tool_calls,
+			usage,
 		}
 	}
 }
 
-// endregion: --- Froms
+// endregion: --- Ollama Chat Types
+
+// region:    --- Ollama Stream
+
+/// A line of the `/api/chat` stream.
+#[derive(Deserialize)]
+struct OllamaStreamRes {
+	message: Option<OllamaMsg>,
+	error: Option<String>,
+	prompt_eval_count: Option<i64>,
+	eval_count: Option<i64>,
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = reqwest::Result<Vec<u8>>> + Send>>;
+
+/// Turn a reqwest response into a stream of its non-empty lines (the stream chunks are not line aligned).
+fn ndjson_lines(res: reqwest::Response) -> impl Stream<Item = Result<Vec<u8>>> + Send {
+	let bytes: ByteStream = Box::pin(res.bytes_stream().map(|r| r.map(|b| b.to_vec())));
+
+	futures::stream::unfold(
+		(bytes, Vec::<u8>::new(), false),
+		|(mut bytes, mut buf, mut done)| async move {
+			loop {
+				// -- Return the next complete line if any
+				if let Some(idx) = buf.iter().position(|b| *b == b'\n') {
+					let line: Vec<u8> = buf.drain(..=idx).collect();
+					if line.iter().all(u8::is_ascii_whitespace) {
+						continue;
+					}
+					return Some((Ok(line), (bytes, buf, done)));
+				}
+
+				if done {
+					// flush what remains (stream closed without the final new line)
+					let line = std::mem::take(&mut buf);
+					return (!line.iter().all(u8::is_ascii_whitespace)).then_some((Ok(line), (bytes, buf, done)));
+				}
+
+				// -- Otherwise, read more bytes
+				match bytes.next().await {
+					Some(Ok(chunk)) => buf.extend_from_slice(&chunk),
+					Some(Err(err)) => {
+						done = true;
+						return Some((Err(Error::from(err)), (bytes, buf, done)));
+					}
+					None => done = true,
+				}
+			}
+		},
+	)
+}
+
+// endregion: --- Ollama Stream
+
+// region:    --- Ollama Embed Types
+
+#[derive(Deserialize)]
+struct OllamaEmbedRes {
+	embeddings: Vec<Vec<f32>>,
+}
+
+// endregion: --- Ollama Embed Types
 
 // region:    --- Tests
 
@@ -106,7 +294,8 @@ mod tests {
 	type Result<T> = core::result::Result<T, Error>; // For tests.
 
 	use super::*;
-	use crate::_test_support::print_gen_stream;
+	use crate::_test_support::{print_gen_stream, MockHttpServer, MockRoute};
+	use crate::ChatMsg;
 
 	// NOTE: For now, we just turn on those test when debugging.
 	//       They take time, so, need see see the right strategy later.
@@ -146,6 +335,156 @@ mod tests {
 
 		Ok(())
 	}
+
+	#[tokio::test]
+	async fn test_ollama_gen_tool_calls() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_res = r#"{
+			"model": "llama-mock", "created_at": "2024-07-01T00:00:00Z", "done": true,
+			"message": {"role": "assistant", "content": "",
+				"tool_calls": [{"function": {"name": "drive_search", "arguments": {"query": "sky"}}}]},
+			"prompt_eval_count": 25, "eval_count": 8
+		}"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/api/chat", fx_res)]).await?;
+		let base_url = server.base_url();
+		let (host, port) = base_url.rsplit_once(':').ok_or("Should have port")?;
+		let ola_client = OllamaClient::new(host, port.parse()?);
+		let fx_req = GenReq {
+			messages: vec![ChatMsg::user("Find the sky files")],
+			inst: Some("Use the tools".to_string()),
+			out_format: None,
+			tools: vec![crate::Tool {
+				fn_name: "drive_search".to_string(),
+				fn_description: "Search the drives".to_string(),
+				params: json!({"type": "object"}),
+			}],
+		};
+
+		// -- Exec
+		let res = ola_client.gen("llama-mock", fx_req).await?;
+
+		// -- Check
+		assert_eq!(
+			res.tool_calls,
+			vec![ToolCall {
+				call_id: "call_0".to_string(),
+				fn_name: "drive_search".to_string(),
+				fn_arguments: json!({"query": "sky"}),
+			}]
+		);
+		assert_eq!(res.usage.prompt_tokens, Some(25));
+		let body = server.requests()[0].body_json()?;
+		assert_eq!(body["stream"], false);
+		assert_eq!(body["messages"][0]["role"], "system");
+		assert_eq!(body["tools"][0]["function"]["name"], "drive_search");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_ollama_gen_stream() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_res = r#"{"model": "llama-mock", "message": {"role": "assistant", "content": "Hello"}, "done": false}
+{"model": "llama-mock", "message": {"role": "assistant", "content": " world"}, "done": false}
+{"model": "llama-mock", "message": {"role": "assistant", "content": ""}, "done": true, "prompt_eval_count": 12, "eval_count": 2}
+"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/api/chat", fx_res)]).await?;
+		let base_url = server.base_url();
+		let (host, port) = base_url.rsplit_once(':').ok_or("Should have port")?;
+		let ola_client = OllamaClient::new(host, port.parse()?);
+
+		// -- Exec
+		let mut stream = ola_client.gen_stream("llama-mock", "Hi".into()).await?;
+		let mut response = String::new();
+		let mut usage = GenUsage::default();
+		while let Some(chunks) = stream.next().await {
+			for chunk in chunks? {
+				response.push_str(&chunk.response);
+				usage.add(&chunk.usage.unwrap_or_default());
+			}
+		}
+
+		// -- Check
+		assert_eq!(response, "Hello world");
+		assert_eq!(usage.prompt_tokens, Some(12));
+		assert_eq!(usage.completion_tokens, Some(2));
+		let body = server.requests()[0].body_json()?;
+		assert_eq!(body["stream"], true);
+		assert!(body.get("tools").is_none());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_ollama_gen_stream_error() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_res = r#"{"model": "llama-mock", "message": {"role": "assistant", "content": "Hel"}, "done": false}
+{"error": "model runner has unexpectedly stopped"}
+"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/api/chat", fx_res)]).await?;
+		let base_url = server.base_url();
+		let (host, port) = base_url.rsplit_once(':').ok_or("Should have port")?;
+		let ola_client = OllamaClient::new(host, port.parse()?);
+
+		// -- Exec
+		let res: Vec<_> = ola_client.gen_stream("llama-mock", "Hi".into()).await?.collect().await;
+
+		// -- Check
+		assert!(res[0].is_ok());
+		match &res[1] {
+			Err(crate::Error::OllamaStreamError(cause)) => assert!(cause.contains("unexpectedly stopped")),
+			_ => return Err("Should be OllamaStreamError".into()),
+		}
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_ollama_gen_http_error() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_err = r#"{"error": "model 'nope' not found, try pulling it first"}"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/api/chat", fx_err).with_status(404)]).await?;
+		let base_url = server.base_url();
+		let (host, port) = base_url.rsplit_once(':').ok_or("Should have port")?;
+		let ola_client = OllamaClient::new(host, port.parse()?);
+
+		// -- Exec
+		let res = ola_client.gen("nope", "Hi".into()).await;
+
+		// -- Check
+		match res {
+			Err(crate::Error::OllamaHttp { status, body }) => {
+				assert_eq!(status, 404);
+				assert!(body.contains("not found"));
+			}
+			_ => return Err("Should be OllamaHttp".into()),
+		}
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_ollama_embed() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_res = r#"{"model": "embed-mock", "embeddings": [[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]]}"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/api/embed", fx_res)]).await?;
+		let base_url = server.base_url();
+		let (host, port) = base_url.rsplit_once(':').ok_or("Should have port")?;
+		let ola_client = OllamaClient::new(host, port.parse()?);
+
+		// -- Exec
+		let vecs = ola_client
+			.embed("embed-mock", vec!["sky".to_string(), "sea".to_string()])
+			.await?;
+
+		// -- Check
+		assert_eq!(vecs, vec![vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6]]);
+		let body = server.requests()[0].body_json()?;
+		assert_eq!(body["model"], "embed-mock");
+		assert_eq!(body["input"], json!(["sky", "sea"]));
+
+		Ok(())
+	}
 }
 
 // endregion: --- Tests
@@ -1,38 +1,616 @@
-#![allow(unused)] // For early development.
-
+use super::sse::sse_events;
 use super::AiClient;
-use crate::{GenReq, GenRes, GenResStream, Result};
-use async_openai::config::OpenAIConfig;
+use crate::types::{GenResChunk, GenResStream};
+use crate::{ChatRole, Error, GenReq, GenRes, GenUsage, Result, ToolCall};
+use async_openai::config::{OpenAIConfig, OPENAI_API_BASE};
+use async_openai::types::{
+	ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+	ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
+	ChatCompletionResponseFormat, ChatCompletionResponseFormatType, ChatCompletionTool, ChatCompletionToolType,
+	CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
+	CreateEmbeddingRequestArgs, FunctionCall, FunctionObject,
+};
 use async_openai::Client;
 use async_trait::async_trait;
-use std::sync::{Arc, Mutex};
+use futures::StreamExt;
+use lib_core::model::agent::OutFormat;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::debug;
 
 pub type OaClient = Client<OpenAIConfig>;
 
+const ENV_OPENAI_API_KEY: &str = "OPENAI_API_KEY";
+const ENV_OPENAI_API_BASE: &str = "OPENAI_API_BASE";
+
+// region:    --- OpenaiConfig
+
+/// Connection settings for an OpenAI-compatible (chat completions) endpoint.
+#[derive(Debug, Clone)]
+pub struct OpenaiConfig {
+	/// e.g., `https://api.openai.com/v1` or `http://localhost:8080/v1`
+	pub api_base: String,
+	pub api_key: String,
+}
+
+impl OpenaiConfig {
+	pub fn new(api_base: impl Into<String>, api_key: impl Into<String>) -> Self {
+		Self {
+			api_base: api_base.into(),
+			api_key: api_key.into(),
+		}
+	}
+
+	/// Returns the config from the `OPENAI_API_KEY` (required) and `OPENAI_API_BASE` (optional) env vars.
+	pub fn from_env() -> Option<Self> {
+		let api_key = std::env::var(ENV_OPENAI_API_KEY).ok()?;
+		let api_base = std::env::var(ENV_OPENAI_API_BASE).unwrap_or_else(|_| OPENAI_API_BASE.to_string());
+
+		Some(Self::new(api_base, api_key))
+	}
+}
+
+// endregion: --- OpenaiConfig
+
+/// Client for the OpenAI chat completions protocol.
+///
+/// When not configured (no config given, and no `OPENAI_API_KEY`), it lists no models
+/// and fails on generation.
 #[derive(Clone)]
 pub struct OpenaiClient {
-	conn: Arc<Mutex<OaClient>>,
+	conn: Option<Arc<OpenaiConn>>,
+}
+
+struct OpenaiConn {
+	oa: OaClient,
+	/// For the streams, which are sent with the `stream_options` (not supported by `async_openai`).
+	http: reqwest::Client,
+	config: OpenaiConfig,
 }
 
 impl Default for OpenaiClient {
 	fn default() -> Self {
 		Self {
-			conn: Arc::new(Mutex::new(Client::new())),
+			conn: OpenaiConfig::from_env().map(OpenaiConn::new),
 		}
 	}
 }
 
+impl OpenaiClient {
+	pub fn new(config: OpenaiConfig) -> Self {
+		Self {
+			conn: Some(OpenaiConn::new(config)),
+		}
+	}
+
+	fn conn(&self) -> Result<&OpenaiConn> {
+		self.conn.as_deref().ok_or(Error::OpenaiNotConfigured)
+	}
+}
+
+impl OpenaiConn {
+	fn new(config: OpenaiConfig) -> Arc<Self> {
+		let oa_config = OpenAIConfig::new()
+			.with_api_base(config.api_base.to_string())
+			.with_api_key(config.api_key.to_string());
+		Arc::new(Self {
+			oa: Client::with_config(oa_config),
+			http: reqwest::Client::new(),
+			config,
+		})
+	}
+}
+
 #[async_trait]
 impl AiClient for OpenaiClient {
 	async fn list_models(&self) -> Result<Vec<String>> {
-		Ok(vec![])
+		let Some(conn) = self.conn.as_deref() else {
+			return Ok(Vec::new());
+		};
+
+		let models = conn.oa.models().list().await?;
+		let models = models.data.into_iter().map(|m| m.id).collect();
+
+		Ok(models)
 	}
 
 	async fn gen(&self, model: &str, req: GenReq) -> Result<GenRes> {
-		todo!()
+		let conn = self.conn()?;
+
+		let oa_req = req.into_openai_req(model)?;
+		debug!("OpenaiClient.gen model: {}", oa_req.model);
+		let oa_res = conn.oa.chat().create(oa_req).await?;
+		debug!("OpenaiClient.gen DONE");
+
+		Ok(oa_res.into())
 	}
 
+	/// Note: Sent with `http` (rather than `async_openai`), to get the usage (`stream_options.include_usage`).
 	async fn gen_stream(&self, model: &str, req: GenReq) -> Result<GenResStream> {
-		todo!()
+		let conn = self.conn()?;
+
+		let mut oa_req = req.into_openai_req(model)?;
+		oa_req.stream = Some(true);
+		let body = OaStreamReq {
+			req: oa_req,
+			stream_options: json!({"include_usage": true}),
+		};
+		let url = format!("{}/chat/completions", conn.config.api_base.trim_end_matches('/'));
+		debug!("OpenaiClient.gen_stream model: {model}");
+		let res = conn.http.post(url).bearer_auth(&conn.config.api_key).json(&body).send().await?;
+		// Note: Not `error_for_status()`, which would drop the body (e.g., `{"error": {"message": ...}}`).
+		let status = res.status();
+		if !status.is_success() {
+			let body = res.text().await.unwrap_or_default();
+			return Err(Error::OpenaiHttp {
+				status: status.as_u16(),
+				body,
+			});
+		}
+
+		let stream = sse_events(res).filter_map(|evt| async move {
+			let evt = match evt {
+				Ok(evt) => evt,
+				Err(err) => return Some(Err(Error::from(err))),
+			};
+			if evt.data == "[DONE]" {
+				return None;
+			}
+			let chunk: OaStreamChunk = match serde_json::from_str(&evt.data) {
+				Ok(chunk) => chunk,
+				Err(err) => return Some(Err(Error::OpenaiFailParse(err))),
+			};
+			if let Some(err) = chunk.error {
+				return Some(Err(Error::OpenaiStreamError(err.to_string())));
+			}
+			Some(Ok(into_gen_res_chunks(chunk)))
+		});
+
+		Ok(Box::pin(stream))
+	}
+
+	async fn embed(&self, model: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>> {
+		let conn = self.conn()?;
+
+		let oa_req = CreateEmbeddingRequestArgs::default().model(model).input(inputs).build()?;
+		debug!("OpenaiClient.embed model: {model}");
+		let oa_res = conn.oa.embeddings().create(oa_req).await?;
+		debug!("OpenaiClient.embed DONE");
+
+		// Note: The data should be in the inputs order, but the index is the reference.
+		let mut data = oa_res.data;
+		data.sort_by_key(|embedding| embedding.index);
+
+		Ok(data.into_iter().map(|embedding| embedding.embedding).collect())
+	}
+}
+
+// region:    --- Custom Intos
+
+impl GenReq {
+	fn into_openai_req(self, model: &str) -> Result<CreateChatCompletionRequest> {
+		let mut messages: Vec<ChatCompletionRequestMessage> = Vec::new();
+		if let Some(inst) = self.inst {
+			messages.push(ChatCompletionRequestSystemMessageArgs::default().content(inst).build()?.into());
+		}
+		for msg in self.messages {
+			let msg: ChatCompletionRequestMessage = match msg.role {
+				ChatRole::User => ChatCompletionRequestUserMessageArgs::default()
+					.content(msg.content)
+					.build()?
+					.into(),
+				ChatRole::Assistant => {
+					let mut msg_args = ChatCompletionRequestAssistantMessageArgs::default();
+					msg_args.content(msg.content);
+					if !msg.tool_calls.is_empty() {
+						let tool_calls: Vec<ChatCompletionMessageToolCall> =
+							msg.tool_calls.into_iter().map(into_openai_tool_call).collect();
+						msg_args.tool_calls(tool_calls);
+					}
+					msg_args.build()?.into()
+				}
+				ChatRole::Tool => ChatCompletionRequestToolMessageArgs::default()
+					.content(msg.content)
+					.tool_call_id(msg.tool_call_id.unwrap_or_default())
+					.build()?
+					.into(),
+			};
+			messages.push(msg);
+		}
+
+		let mut req_args = CreateChatCompletionRequestArgs::default();
+		req_args.model(model).messages(messages);
+		if let Some(OutFormat::Json) = self.out_format {
+			req_args.response_format(ChatCompletionResponseFormat {
+				r#type: ChatCompletionResponseFormatType::JsonObject,
+			});
+		}
+		if !self.tools.is_empty() {
+			let tools: Vec<ChatCompletionTool> = self
+				.tools
+				.into_iter()
+				.map(|tool| ChatCompletionTool {
+					r#type: ChatCompletionToolType::Function,
+					function: FunctionObject {
+						name: tool.fn_name,
+						description: Some(tool.fn_description),
+						parameters: Some(tool.params),
+					},
+				})
+				.collect();
+			req_args.tools(tools);
+		}
+
+		Ok(req_args.build()?)
+	}
+}
+
+// endregion: --- Custom Intos
+
+// region:    --- Froms
+
+/// Note: The usage is on the last chunk (without choices), when `stream_options.include_usage`.
+fn into_gen_res_chunks(val: OaStreamChunk) -> Vec<GenResChunk> {
+	let mut chunks: Vec<GenResChunk> = val
+		.choices
+		.into_iter()
+		.filter_map(|choice| choice.delta.content)
+		.map(|response| GenResChunk { response, usage: None })
+		.collect();
+	if let Some(usage) = val.usage {
+		chunks.push(GenResChunk {
+			response: String::new(),
+			usage: Some(GenUsage {
+				prompt_tokens: Some(usage.prompt_tokens),
+				completion_tokens: Some(usage.completion_tokens),
+			}),
+		});
+	}
+
+	chunks
+}
+
+impl From<CreateChatCompletionResponse> for GenRes {
+	fn from(value: CreateChatCompletionResponse) -> Self {
+		let usage = value
+			.usage
+			.map(|u| GenUsage {
+				prompt_tokens: Some(u.prompt_tokens.into()),
+				completion_tokens: Some(u.completion_tokens.into()),
+			})
+			.unwrap_or_default();
+
+		let (response, tool_calls) = match value.choices.into_iter().next() {
+			Some(choice) => (
+				choice.message.content.unwrap_or_default(),
+				choice
+					.message
+					.tool_calls
+					.unwrap_or_default()
+					.into_iter()
+					.map(from_openai_tool_call)
+					.collect(),
+			),
+			None => (String::new(), Vec::new()),
+		};
+
+		Self {
+			response,
+			tool_calls,
+			usage,
+		}
+	}
+}
+
+/// Note: The model arguments should be a JSON object string, but are kept as a JSON string otherwise.
+fn from_openai_tool_call(tool_call: ChatCompletionMessageToolCall) -> ToolCall {
+	let FunctionCall { name, arguments } = tool_call.function;
+	let fn_arguments = serde_json::from_str(&arguments).unwrap_or(Value::String(arguments));
+	ToolCall {
+		call_id: tool_call.id,
+		fn_name: name,
+		fn_arguments,
 	}
 }
+
+fn into_openai_tool_call(tool_call: ToolCall) -> ChatCompletionMessageToolCall {
+	ChatCompletionMessageToolCall {
+		id: tool_call.call_id,
+		r#type: ChatCompletionToolType::Function,
+		function: FunctionCall {
+			name: tool_call.fn_name,
+			arguments: tool_call.fn_arguments.to_string(),
+		},
+	}
+}
+
+// endregion: --- Froms
+
+// region:    --- OpenAI Stream Types
+
+/// The streamed chat request, with the `stream_options` (not supported by `async_openai`).
+#[derive(Serialize)]
+struct OaStreamReq {
+	#[serde(flatten)]
+	req: CreateChatCompletionRequest,
+	stream_options: Value,
+}
+
+#[derive(Deserialize)]
+struct OaStreamChunk {
+	#[serde(default)]
+	choices: Vec<OaStreamChoice>,
+	usage: Option<OaStreamUsage>,
+	error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct OaStreamChoice {
+	delta: OaStreamDelta,
+}
+
+#[derive(Deserialize)]
+struct OaStreamDelta {
+	content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OaStreamUsage {
+	prompt_tokens: i64,
+	completion_tokens: i64,
+}
+
+// endregion: --- OpenAI Stream Types
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::_test_support::{MockHttpServer, MockRoute};
+	use crate::ChatMsg;
+
+	const FX_MODELS_JSON: &str = r#"{"object": "list", "data": [
+		{"id": "gpt-mock-1", "object": "model", "created": 1700000000, "owned_by": "mock"},
+		{"id": "gpt-mock-2", "object": "model", "created": 1700000000, "owned_by": "mock"}
+	]}"#;
+
+	const FX_CHAT_JSON: &str = r#"{
+		"id": "chatcmpl-1", "object": "chat.completion", "created": 1700000000, "model": "gpt-mock-1",
+		"choices": [{"index": 0, "message": {"role": "assistant", "content": "Hello there"}, "finish_reason": "stop"}],
+		"usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15}
+	}"#;
+
+	const FX_CHAT_SSE: &str = concat!(
+		"data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1700000000,\"model\":\"gpt-mock-1\",",
+		"\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n",
+		"data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1700000000,\"model\":\"gpt-mock-1\",",
+		"\"choices\":[{\"index\":0,\"delta\":{\"content\":\"lo\"},\"finish_reason\":null}]}\n\n",
+		"data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1700000000,\"model\":\"gpt-mock-1\",",
+		"\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+		"data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1700000000,\"model\":\"gpt-mock-1\",",
+		"\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":2,\"total_tokens\":14}}\n\n",
+		"data: [DONE]\n\n"
+	);
+
+	async fn fx_server_and_client() -> Result<(MockHttpServer, OpenaiClient)> {
+		let server = MockHttpServer::start(vec![
+			MockRoute::json("GET", "/v1/models", FX_MODELS_JSON),
+			MockRoute::json("POST", "/v1/chat/completions", FX_CHAT_JSON),
+		])
+		.await?;
+		let client = OpenaiClient::new(OpenaiConfig::new(format!("{}/v1", server.base_url()), "fx-key"));
+
+		Ok((server, client))
+	}
+
+	#[tokio::test]
+	async fn test_openai_list_models() -> Result<()> {
+		// -- Setup & Fixtures
+		let (server, client) = fx_server_and_client().await?;
+
+		// -- Exec
+		let models = client.list_models().await?;
+
+		// -- Check
+		assert_eq!(models, vec!["gpt-mock-1", "gpt-mock-2"]);
+		let reqs = server.requests();
+		assert_eq!(reqs[0].header("authorization"), Some("Bearer fx-key"));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_openai_list_models_not_configured() -> Result<()> {
+		// -- Setup & Fixtures
+		let client = OpenaiClient { conn: None };
+
+		// -- Exec
+		let models = client.list_models().await?;
+
+		// -- Check
+		assert!(models.is_empty());
+		assert!(matches!(
+			client.gen("gpt-mock-1", "Hi".into()).await,
+			Err(crate::Error::OpenaiNotConfigured)
+		));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_openai_gen() -> Result<()> {
+		// -- Setup & Fixtures
+		let (server, client) = fx_server_and_client().await?;
+		let fx_req = GenReq {
+			messages: vec![
+				ChatMsg::user("Hi"),
+				ChatMsg::assistant("Hi, how can I help?"),
+				ChatMsg::user("Say hello"),
+			],
+			inst: Some("Be nice".to_string()),
+			out_format: Some(OutFormat::Json),
+			tools: Vec::new(),
+		};
+
+		// -- Exec
+		let res = client.gen("gpt-mock-1", fx_req).await?;
+
+		// -- Check
+		assert_eq!(res.response, "Hello there");
+		assert_eq!(res.usage.prompt_tokens, Some(12));
+		assert_eq!(res.usage.completion_tokens, Some(3));
+		let req_body = server.requests()[0].body_json()?;
+		assert_eq!(req_body["model"], "gpt-mock-1");
+		assert_eq!(req_body["messages"][0]["role"], "system");
+		assert_eq!(req_body["messages"][0]["content"], "Be nice");
+		assert_eq!(req_body["messages"][1]["role"], "user");
+		assert_eq!(req_body["messages"][1]["content"], "Hi");
+		assert_eq!(req_body["messages"][2]["role"], "assistant");
+		assert_eq!(req_body["messages"][3]["role"], "user");
+		assert_eq!(req_body["messages"][3]["content"], "Say hello");
+		assert_eq!(req_body["response_format"]["type"], "json_object");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_openai_gen_stream() -> Result<()> {
+		// -- Setup & Fixtures
+		let server = MockHttpServer::start(vec![MockRoute::sse("POST", "/chat/completions", FX_CHAT_SSE)]).await?;
+		let client = OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key"));
+
+		// -- Exec
+		let mut stream = client.gen_stream("gpt-mock-1", "Say hello".into()).await?;
+		let mut content = String::new();
+		let mut usage = GenUsage::default();
+		while let Some(chunks) = stream.next().await {
+			for chunk in chunks? {
+				content.push_str(&chunk.response);
+				usage.add(&chunk.usage.unwrap_or_default());
+			}
+		}
+
+		// -- Check
+		assert_eq!(content, "Hello");
+		assert_eq!(usage.prompt_tokens, Some(12));
+		assert_eq!(usage.completion_tokens, Some(2));
+		let req_body = server.requests()[0].body_json()?;
+		assert_eq!(req_body["stream"], true);
+		assert_eq!(req_body["stream_options"]["include_usage"], true);
+		assert_eq!(server.requests()[0].header("authorization"), Some("Bearer fx-key"));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_openai_gen_stream_http_error() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_err = r#"{"error": {"message": "Rate limit reached", "type": "rate_limit_exceeded"}}"#;
+		let server = MockHttpServer::start(vec![
+			MockRoute::json("POST", "/chat/completions", fx_err).with_status(429)
+		])
+		.await?;
+		let client = OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key"));
+
+		// -- Exec
+		let res = client.gen_stream("gpt-mock-1", "Say hello".into()).await;
+
+		// -- Check
+		let Err(err) = res else {
+			return Err("Should be an error".into());
+		};
+		assert_eq!(err.kind(), crate::runner::ErrorKind::RateLimit);
+		match err {
+			crate::Error::OpenaiHttp { status, body } => {
+				assert_eq!(status, 429);
+				assert!(body.contains("Rate limit reached"));
+			}
+			_ => return Err("Should be OpenaiHttp".into()),
+		}
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_openai_gen_tool_calls() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_res = r#"{
+			"id": "chatcmpl-2", "object": "chat.completion", "created": 1700000000, "model": "gpt-mock-1",
+			"choices": [{"index": 0, "finish_reason": "tool_calls", "message": {"role": "assistant", "content": null,
+				"tool_calls": [{"id": "call_a", "type": "function",
+					"function": {"name": "drive_search", "arguments": "{\"query\": \"sky\"}"}}]}}]
+		}"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/chat/completions", fx_res)]).await?;
+		let client = OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key"));
+		let fx_tool_call = ToolCall {
+			call_id: "call_0".to_string(),
+			fn_name: "list_files".to_string(),
+			fn_arguments: serde_json::json!({}),
+		};
+		let fx_req = GenReq {
+			messages: vec![
+				ChatMsg::user("Find the sky files"),
+				ChatMsg::assistant_tool_calls("", vec![fx_tool_call]),
+				ChatMsg::tool_result("call_0", "[]"),
+			],
+			inst: None,
+			out_format: None,
+			tools: vec![crate::Tool {
+				fn_name: "drive_search".to_string(),
+				fn_description: "Search the drives".to_string(),
+				params: serde_json::json!({"type": "object"}),
+			}],
+		};
+
+		// -- Exec
+		let res = client.gen("gpt-mock-1", fx_req).await?;
+
+		// -- Check
+		assert_eq!(res.response, "");
+		assert_eq!(res.tool_calls.len(), 1);
+		assert_eq!(res.tool_calls[0].call_id, "call_a");
+		assert_eq!(res.tool_calls[0].fn_name, "drive_search");
+		assert_eq!(res.tool_calls[0].fn_arguments["query"], "sky");
+		let req_body = server.requests()[0].body_json()?;
+		assert_eq!(req_body["tools"][0]["type"], "function");
+		assert_eq!(req_body["tools"][0]["function"]["name"], "drive_search");
+		assert_eq!(req_body["messages"][1]["tool_calls"][0]["function"]["arguments"], "{}");
+		assert_eq!(req_body["messages"][2]["role"], "tool");
+		assert_eq!(req_body["messages"][2]["tool_call_id"], "call_0");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_openai_embed() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_res = r#"{
+			"object": "list", "model": "embed-mock",
+			"data": [
+				{"object": "embedding", "index": 1, "embedding": [0.4, 0.5]},
+				{"object": "embedding", "index": 0, "embedding": [0.1, 0.2]}
+			],
+			"usage": {"prompt_tokens": 4, "total_tokens": 4}
+		}"#;
+		let server = MockHttpServer::start(vec![MockRoute::json("POST", "/embeddings", fx_res)]).await?;
+		let client = OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key"));
+
+		// -- Exec
+		let vecs = client.embed("embed-mock", vec!["sky".to_string(), "sea".to_string()]).await?;
+
+		// -- Check
+		assert_eq!(vecs, vec![vec![0.1, 0.2], vec![0.4, 0.5]]);
+		let req_body = server.requests()[0].body_json()?;
+		assert_eq!(req_body["model"], "embed-mock");
+		assert_eq!(req_body["input"], serde_json::json!(["sky", "sea"]));
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,153 @@
+//! Minimal Server-Sent Events parsing over a response byte stream.
+
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SseEvent {
+	pub event: Option<String>,
+	/// The `data:` lines (joined with `\n` when multiple)
+	pub data: String,
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = reqwest::Result<Vec<u8>>> + Send>>;
+
+struct SseState {
+	bytes: ByteStream,
+	/// The raw bytes not yet parsed (only the complete event blocks are decoded, so a multibyte
+	/// char or a `\r\n` split across two network chunks is kept intact).
+	buf: Vec<u8>,
+	done: bool,
+}
+
+/// Turn a reqwest response into a stream of `SseEvent`.
+pub fn sse_events(res: reqwest::Response) -> impl Stream<Item = reqwest::Result<SseEvent>> + Send {
+	let bytes: ByteStream = Box::pin(res.bytes_stream().map(|r| r.map(|b| b.to_vec())));
+	sse_events_from_bytes(bytes)
+}
+
+fn sse_events_from_bytes(bytes: ByteStream) -> impl Stream<Item = reqwest::Result<SseEvent>> + Send {
+	let state = SseState {
+		bytes,
+		buf: Vec::new(),
+		done: false,
+	};
+
+	futures::stream::unfold(state, |mut state| async move {
+		loop {
+			// -- Return the next complete event if any
+			if let Some(end) = find_block_end(&state.buf) {
+				let block: Vec<u8> = state.buf.drain(..end).collect();
+				if let Some(evt) = parse_event_block(&String::from_utf8_lossy(&block)) {
+					return Some((Ok(evt), state));
+				}
+				continue;
+			}
+
+			if state.done {
+				// flush what remains (stream closed without the final blank line)
+				let block = std::mem::take(&mut state.buf);
+				return parse_event_block(&String::from_utf8_lossy(&block)).map(|evt| (Ok(evt), state));
+			}
+
+			// -- Otherwise, read more bytes
+			match state.bytes.next().await {
+				Some(Ok(bytes)) => state.buf.extend_from_slice(&bytes),
+				Some(Err(err)) => {
+					state.done = true;
+					return Some((Err(err), state));
+				}
+				None => state.done = true,
+			}
+		}
+	})
+}
+
+/// Returns the end (delimiter included) of the first event block of `buf`, if complete.
+/// The block delimiter is a blank line, so `\n\n` or `\n\r\n` (for the `\r\n` line ends).
+fn find_block_end(buf: &[u8]) -> Option<usize> {
+	buf.iter()
+		.enumerate()
+		.find_map(|(idx, b)| match (b, buf.get(idx + 1), buf.get(idx + 2)) {
+			(b'\n', Some(b'\n'), _) => Some(idx + 2),
+			(b'\n', Some(b'\r'), Some(b'\n')) => Some(idx + 3),
+			_ => None,
+		})
+}
+
+fn parse_event_block(block: &str) -> Option<SseEvent> {
+	let mut evt = SseEvent::default();
+	let mut data_lines: Vec<&str> = Vec::new();
+
+	for line in block.lines() {
+		if let Some(event) = line.strip_prefix("event:") {
+			evt.event = Some(event.trim().to_string());
+		} else if let Some(data) = line.strip_prefix("data:") {
+			data_lines.push(data.strip_prefix(' ').unwrap_or(data));
+		}
+	}
+
+	if evt.event.is_none() && data_lines.is_empty() {
+		return None;
+	}
+	evt.data = data_lines.join("\n");
+
+	Some(evt)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_sse_parse_event_block() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_block = "event: content_block_delta\ndata: {\"a\":1}\ndata: {\"b\":2}\n\n";
+
+		// -- Exec
+		let evt = parse_event_block(fx_block).ok_or("Should have event")?;
+
+		// -- Check
+		assert_eq!(evt.event.as_deref(), Some("content_block_delta"));
+		assert_eq!(evt.data, "{\"a\":1}\n{\"b\":2}");
+		assert!(parse_event_block(": ping comment\n\n").is_none());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_sse_events_split_chunks() -> Result<()> {
+		// -- Setup & Fixtures
+		// Note: The `é` (2 bytes) and the `\r\n` of the blank line are split across the chunks.
+		let fx_body = "event: a\r\ndata: caf\u{e9}\r\n\r\nevent: b\r\ndata: 2\r\n\r\n".as_bytes();
+		let fx_chunks: Vec<reqwest::Result<Vec<u8>>> = vec![
+			Ok(fx_body[..20].to_vec()),
+			Ok(fx_body[20..24].to_vec()),
+			Ok(fx_body[24..].to_vec()),
+		];
+		let bytes: ByteStream = Box::pin(futures::stream::iter(fx_chunks));
+
+		// -- Exec
+		let evts: Vec<SseEvent> = sse_events_from_bytes(bytes)
+			.collect::<Vec<_>>()
+			.await
+			.into_iter()
+			.collect::<reqwest::Result<_>>()?;
+
+		// -- Check
+		assert_eq!(evts.len(), 2);
+		assert_eq!(evts[0].event.as_deref(), Some("a"));
+		assert_eq!(evts[0].data, "caf\u{e9}");
+		assert_eq!(evts[1].event.as_deref(), Some("b"));
+		assert_eq!(evts[1].data, "2");
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
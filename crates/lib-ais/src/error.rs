@@ -1,3 +1,4 @@
+use crate::chain::ChainDiagnostic;
 use derive_more::From;
 use lib_core::model::Id;
 use lib_utils::hbs;
@@ -17,12 +18,40 @@ pub enum Error {
 		agent_id: Id,
 	},
 	FailSerializeStack(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
+	ChainVarNotFound {
+		name: String,
+	},
+	ChainInvalid {
+		diagnostics: Vec<ChainDiagnostic>,
+	},
+	ChainMapNotFound {
+		idxs: Vec<usize>,
+	},
+	ChainMapItemsNotArray {
+		from: String,
+	},
+	ChainStackOverLimit {
+		limit: usize,
+	},
+	ChainTransformFail {
+		transform: &'static str,
+		cause: String,
+	},
 
 	// -- Runner
 	CantRunStepStackEmpty {
 		step_id: Id,
 	},
+	StepRunCancelled {
+		step_id: Id,
+	},
 	FailToHbsRenderPrompt(#[serde_as(as = "DisplayFromStr")] hbs::Error),
+	RetryPolicyFailParse(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
+	RetrievalConfigFailParse(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
+	RetrievalFailSerializeHits(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
+
+	// -- Search
+	DriveSearchNoEmbedModel,
 
 	// -- Stack
 	StackFailParse(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
@@ -33,6 +62,38 @@ pub enum Error {
 
 	// -- AiClient
 	AiModelNotImplemented(String),
+	AiEmbedNotSupported {
+		provider: &'static str,
+	},
+	AiEmbedCountMismatch {
+		model: String,
+		inputs: usize,
+		vecs: usize,
+	},
+	OpenaiNotConfigured,
+	OpenaiFailParse(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
+	OpenaiStreamError(String),
+	OpenaiHttp {
+		status: u16,
+		body: String,
+	},
+	AnthropicNotConfigured,
+	AnthropicHttp {
+		status: u16,
+		body: String,
+	},
+	AnthropicFailParse(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
+	AnthropicStreamError(String),
+	OllamaHttp {
+		status: u16,
+		body: String,
+	},
+	OllamaFailParse(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
+	OllamaStreamError(String),
+	AiProviderUnknown(String),
+	AiModelNotFound {
+		model: String,
+	},
 
 	// -- Model related
 	SpaceHasNoAgent {
@@ -43,15 +104,43 @@ pub enum Error {
 		agent_id: Id,
 		agent_name: String,
 	},
+	LogicAgentHasNoTool {
+		agent_id: Id,
+		agent_name: String,
+	},
+	AgentToolsInvalid {
+		agent_id: Id,
+		agent_name: String,
+		cause: String,
+	},
+	AgentToolRoundsOverLimit {
+		agent_name: String,
+		limit: usize,
+	},
+
+	// -- Logic Tools
+	LogicToolNotFound {
+		name: String,
+	},
+	LogicToolParamsInvalid {
+		tool: String,
+		cause: String,
+	},
+	LogicToolFailSerializeResult(#[serde_as(as = "DisplayFromStr")] serde_json::Error),
 
 	// -- App Libs
 	#[from]
 	Model(lib_core::model::Error),
+	#[from]
+	Event(lib_core::event::Error),
 
 	// -- Externals
 	#[from]
 	Ollama(#[serde_as(as = "DisplayFromStr")] ollama_rs::error::OllamaError),
-	OllamaCustom(String),
+	#[from]
+	OpenAI(#[serde_as(as = "DisplayFromStr")] async_openai::error::OpenAIError),
+	#[from]
+	Reqwest(#[serde_as(as = "DisplayFromStr")] reqwest::Error),
 }
 
 // region:    --- Error Boilerplate
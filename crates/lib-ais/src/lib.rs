@@ -8,13 +8,16 @@ mod tools;
 mod types;
 
 // -- Flatten
+pub use chain::{agent_chain_check, check_agent_chain, validate_agent_chain, ChainDiagnostic, ChainDiagnosticKind};
 pub use client::*;
 pub use error::{Error, Result};
+pub use tools::{LogicTool, LogicToolCtx, LogicToolRegistry};
 pub use types::*;
 
 // -- Public
 pub mod agent_defs;
 pub mod runner;
+pub mod search;
 
 // -- Test
 #[cfg(any(test, feature = "for-test"))]
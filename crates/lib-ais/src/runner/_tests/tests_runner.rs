@@ -1,12 +1,23 @@
-use crate::_test_support::{seed_all_for_test_runner, seed_mock_echo_agents};
-use crate::runner::runner::run_agent_model;
-use crate::runner::{resolve_stack_step, run_stack_step, RunStepStatus};
-use crate::AiManager;
+use crate::_test_support::{seed_all_for_test_runner, seed_mock_echo_agents, MockHttpServer, MockRoute};
+use crate::runner::runner::{get_agent_history, run_agent_model};
+use crate::runner::{resolve_stack_step, run_stack_step, run_stack_step_agent, RunLimiter, RunStepStatus};
+use crate::{AiManager, ChatRole, OpenaiClient, OpenaiConfig};
+use lib_core::_test_support::{seed_ditem_parts, seed_drive, seed_dsource, seed_space};
+use lib_core::event::{ConvEvent, Subscriber};
 use lib_core::model::agent::AgentBmc;
+use lib_core::model::agent::{AgentForCreate, AgentForUpdate, AgentKind};
+use lib_core::model::cfile::CFileBmc;
 use lib_core::model::conv::ConvBmc;
-use lib_core::model::stack_step::StackStepBmc;
+use lib_core::model::msg::{MsgBmc, MSG_ERR_CANCELLED};
+use lib_core::model::space::SpaceBmc;
+use lib_core::model::stack_step::{StackStepBmc, UsageRange};
+use lib_core::model::step_tool_call::StepToolCallBmc;
 use lib_core::model::ModelManager;
+use lib_utils::time::now;
 use lib_utils::x_vec::XStringVec;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
 
 pub type Result<T> = core::result::Result<T, Error>;
 pub type Error = Box<dyn std::error::Error>; // For early dev.
@@ -19,11 +30,12 @@ async fn test_runner_persistent_stack_steps_walkthrough() -> Result<()> {
 	let aim = AiManager::default();
 
 	let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
+	let mut conv_sub: Subscriber<ConvEvent> = mm.hub().subscriber()?;
 
 	// Create the original message
 	let first_input = "Hello world";
 	// Note: this will create the first task_step
-	let _orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, first_input.into()).await?;
+	let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, first_input.into()).await?;
 
 	// -- Exec
 	let mut agent_names: Vec<String> = Vec::new();
@@ -74,6 +86,9 @@ async fn test_runner_persistent_stack_steps_walkthrough() -> Result<()> {
 		match run_status {
 			RunStepStatus::Ended => break,
 			RunStepStatus::Ongoing => (),
+			RunStepStatus::Cancelled | RunStepStatus::Retrying { .. } => {
+				return Err(format!("Should not be {run_status:?}").into())
+			}
 		}
 	} // loop
 
@@ -96,6 +111,89 @@ async fn test_runner_persistent_stack_steps_walkthrough() -> Result<()> {
 		["Agent One", "fc_tool_executors", "fc_tool_renderers", "Final Agent",]
 	);
 
+	// check usage (fc mock models do not report tokens)
+	let usages = ConvBmc::usage_report(&mm, conv.id, &UsageRange::default()).await?;
+	assert_eq!(usages.len(), 4, "should have one usage per agent");
+	for usage in usages.iter() {
+		assert_eq!(usage.step_count, 1);
+		assert_eq!(usage.model.as_deref(), Some("fc::fc-mock-echo-inst"));
+		assert_eq!(usage.prompt_tokens, 0);
+	}
+	let fx_range = UsageRange {
+		from: Some(now()),
+		to: None,
+	};
+	let usages = ConvBmc::usage_report(&mm, conv.id, &fx_range).await?;
+	assert!(usages.is_empty(), "should have no usage after now");
+
+	// check the last generation was streamed in the answer msg
+	let mut deltas = String::new();
+	while let Ok(evt) = timeout(Duration::from_millis(10), conv_sub.next()).await {
+		if let ConvEvent::ConvMsgChunk { conv_id, delta, .. } = evt? {
+			assert_eq!(conv_id, conv.id);
+			deltas.push_str(&delta);
+		}
+	}
+	let answer = MsgBmc::list(&cfile_db, None, None)
+		.await?
+		.into_iter()
+		.find(|m| m.orig_msg_id == Some(orig_msg_id))
+		.ok_or("Should have answer msg")?;
+	assert_eq!(answer.content.as_deref(), Some("Final Agent response"));
+	assert_eq!(deltas, "Final Agent response");
+	assert!(answer.start_time.is_some() && answer.done_time.is_some());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_stream_usage() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_sse = concat!(
+		"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Streamed\"}}]}\n\n",
+		"data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":3,\"total_tokens\":15}}\n\n",
+		"data: [DONE]\n\n"
+	);
+	let server = MockHttpServer::start(vec![MockRoute::sse("POST", "/chat/completions", fx_sse)]).await?;
+	let aim =
+		AiManager::default().with_openai_client(OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key")));
+	let mm = ModelManager::new().await?;
+	let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
+	let final_agent = AgentBmc::first_by_name(&mm, "Final Agent")
+		.await?
+		.ok_or("Should have Final Agent")?;
+	AgentBmc::update(
+		&mm,
+		final_agent.id,
+		AgentForUpdate {
+			model: Some("openai::gpt-mock-1".to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	ConvBmc::add_conv_msg(&mm, conv.id, "Hello world".into()).await?;
+
+	// -- Exec
+	for _ in 0..10 {
+		let step = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+			.await?
+			.ok_or("Should have a step to resolve")?;
+		resolve_stack_step(&mm, &cfile_db, step.id).await?;
+		if let RunStepStatus::Ended = run_stack_step(&aim, &mm, &cfile_db, step.id).await? {
+			break;
+		}
+	}
+
+	// -- Check
+	let usages = ConvBmc::usage_report(&mm, conv.id, &UsageRange::default()).await?;
+	let usage = usages
+		.iter()
+		.find(|u| u.model.as_deref() == Some("openai::gpt-mock-1"))
+		.ok_or("Should have the streamed step usage")?;
+	assert_eq!(usage.step_count, 1);
+	assert_eq!(usage.prompt_tokens, 12);
+	assert_eq!(usage.completion_tokens, 3);
+
 	Ok(())
 }
 
@@ -109,10 +207,716 @@ async fn test_runner_fc_mock_echo_model() -> Result<()> {
 	let agent = agents.pop().ok_or("Should have a least one agent")?;
 
 	// -- Exec
-	let res = run_agent_model(&aim, &agent, "Some input".into()).await?;
+	let (target, res) = run_agent_model(&aim, &agent, "Some input".into(), Vec::new()).await?;
 
 	// -- Check
 	assert_eq!(res.response, fx_inst);
+	assert_eq!(target.to_string(), "fc::fc-mock-echo-inst");
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_agent_history_window() -> Result<()> {
+	// -- Setup & Fixtures
+	let mm = ModelManager::new().await?;
+	let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
+	let agent = AgentBmc::first_by_name(&mm, "Agent One")
+		.await?
+		.ok_or("Should have Agent One")?;
+	AgentBmc::update(
+		&mm,
+		agent.id,
+		AgentForUpdate {
+			history_window: Some(2),
+			..Default::default()
+		},
+	)
+	.await?;
+	let agent = AgentBmc::get(&mm, agent.id).await?;
+
+	ConvBmc::add_conv_msg(&mm, conv.id, "question 1".into()).await?;
+	let step_1 = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have step 1")?;
+	MsgBmc::create_agent_answer(&cfile_db, step_1, "answer 1").await?;
+	ConvBmc::add_conv_msg(&mm, conv.id, "question 2".into()).await?;
+	let msg_3_id = ConvBmc::add_conv_msg(&mm, conv.id, "question 3".into()).await?;
+
+	// -- Exec
+	let history = get_agent_history(&cfile_db, &agent, msg_3_id).await?;
+
+	// -- Check
+	let contents: Vec<&str> = history.iter().map(|m| m.content.as_str()).collect();
+	assert_eq!(contents, ["answer 1", "question 2"]);
+	assert_eq!(history[0].role, ChatRole::Assistant);
+	assert_eq!(history[1].role, ChatRole::User);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_cancel_in_flight() -> Result<()> {
+	// -- Setup & Fixtures
+	let server = MockHttpServer::start(vec![
+		MockRoute::json("POST", "/chat/completions", "{}").with_delay(Duration::from_secs(10))
+	])
+	.await?;
+	let aim =
+		AiManager::default().with_openai_client(OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key")));
+	let mm = ModelManager::new().await?;
+	let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
+	let agent = AgentBmc::first_by_name(&mm, "Agent One")
+		.await?
+		.ok_or("Should have Agent One")?;
+	AgentBmc::update(
+		&mm,
+		agent.id,
+		AgentForUpdate {
+			model: Some("openai::gpt-mock-slow".to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "Hello world".into()).await?;
+	let step = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have step")?;
+	resolve_stack_step(&mm, &cfile_db, step.id).await?;
+
+	// -- Exec
+	let run_handle = tokio::spawn({
+		let (aim, mm, cfile_db) = (aim.clone(), mm.clone(), cfile_db.clone());
+		async move { run_stack_step(&aim, &mm, &cfile_db, step.id).await }
+	});
+	sleep(Duration::from_millis(50)).await;
+	ConvBmc::cancel_work(&mm, conv.id).await?;
+	let run_status = timeout(Duration::from_secs(2), run_handle).await???;
+
+	// -- Check
+	assert!(matches!(run_status, RunStepStatus::Cancelled));
+	let step = StackStepBmc::get(&cfile_db, step.id).await?;
+	assert!(step.run_tcancel.is_some());
+	assert!(step.run_terr.is_none());
+	let answer = MsgBmc::list(&cfile_db, None, None)
+		.await?
+		.into_iter()
+		.find(|m| m.orig_msg_id == Some(orig_msg_id))
+		.ok_or("Should have the cancelled answer")?;
+	assert_eq!(answer.err.as_deref(), Some(MSG_ERR_CANCELLED));
+	assert!(StackStepBmc::seek_next_to_run_for_conv(&cfile_db, &conv.uid).await?.is_none());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_cancel_before_pending_msg() -> Result<()> {
+	// -- Setup & Fixtures
+	let mm = ModelManager::new().await?;
+	let aim = AiManager::default();
+	let mut agents = seed_mock_echo_agents(&mm, &[("Solo Agent", "Solo Agent response")]).await?;
+	let agent = agents.pop().ok_or("Should have agent")?;
+	let space_id = seed_space(&mm, "Space Cancel Race").await?;
+	SpaceBmc::set_agent(&mm, space_id, agent.id).await?;
+	let conv = SpaceBmc::get_latest_conv(&mm, space_id).await?;
+	let cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &conv).await?;
+	let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "Hello world".into()).await?;
+	let step = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have step")?;
+	resolve_stack_step(&mm, &cfile_db, step.id).await?;
+	let step = StackStepBmc::get(&cfile_db, step.id).await?;
+	// Note: The cancel lands after the run start check (so, the step is run directly).
+	StackStepBmc::cancel_unfinished_for_conv(&cfile_db, &conv.uid).await?;
+
+	// -- Exec
+	let res = run_stack_step_agent(&aim, &mm, &cfile_db, &step).await;
+
+	// -- Check
+	assert!(matches!(res, Err(crate::Error::StepRunCancelled { .. })));
+	assert!(MsgBmc::first_pending_answer(&cfile_db, orig_msg_id).await?.is_none());
+	let answer = MsgBmc::list(&cfile_db, None, None)
+		.await?
+		.into_iter()
+		.find(|m| m.orig_msg_id == Some(orig_msg_id))
+		.ok_or("Should have the cancelled answer")?;
+	assert_eq!(answer.err.as_deref(), Some(MSG_ERR_CANCELLED));
+	assert!(answer.done_time.is_some());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_retry_failed_step() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_err_body = r#"{"error": {"message": "boom", "type": "server_error", "param": null, "code": null}}"#;
+	let server = MockHttpServer::start(vec![
+		MockRoute::json("POST", "/chat/completions", fx_err_body).with_status(500)
+	])
+	.await?;
+	let aim =
+		AiManager::default().with_openai_client(OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key")));
+	let mm = ModelManager::new().await?;
+	let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
+	let agent = AgentBmc::first_by_name(&mm, "Agent One")
+		.await?
+		.ok_or("Should have Agent One")?;
+	AgentBmc::update(
+		&mm,
+		agent.id,
+		AgentForUpdate {
+			model: Some("openai::gpt-mock-1".to_string()),
+			retry_policy: Some(r#"{"max_attempts": 2, "backoff_ms": 0, "retry_on": ["server"]}"#.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	ConvBmc::add_conv_msg(&mm, conv.id, "Hello world".into()).await?;
+	let step = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have step")?;
+	resolve_stack_step(&mm, &cfile_db, step.id).await?;
+
+	// -- Exec & Check - first attempt fails, and gets retried per policy
+	let run_status = run_stack_step(&aim, &mm, &cfile_db, step.id).await?;
+	let RunStepStatus::Retrying { retry_step_id, .. } = run_status else {
+		return Err(format!("Should be Retrying, but was {run_status:?}").into());
+	};
+	let retry_step = StackStepBmc::get(&cfile_db, retry_step_id).await?;
+	assert_eq!(retry_step.retry_of_step_id, Some(step.id));
+	assert_eq!(retry_step.attempt, 2);
+	assert_eq!(
+		retry_step.call_stack,
+		StackStepBmc::get(&cfile_db, step.id).await?.call_stack
+	);
+
+	// -- Exec & Check - second attempt fails, and max_attempts reached
+	let next_step = StackStepBmc::seek_next_to_run_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have the retry step to run")?;
+	assert_eq!(next_step.id, retry_step_id);
+	let res = run_stack_step(&aim, &mm, &cfile_db, retry_step_id).await;
+	assert!(res.is_err(), "Should fail without retry");
+
+	// -- Exec & Check - manual retry
+	let manual_retry_id = ConvBmc::retry_step(&mm, conv.id, retry_step_id).await?;
+	let manual_retry = StackStepBmc::get(&cfile_db, manual_retry_id).await?;
+	assert_eq!(manual_retry.attempt, 3);
+	assert!(
+		ConvBmc::retry_step(&mm, conv.id, retry_step_id).await.is_err(),
+		"Should not retry twice"
+	);
+	let steps = ConvBmc::list_steps(&mm, conv.id, step.orig_msg_id).await?;
+	assert_eq!(steps.len(), 3, "Failed attempts should stay in the history");
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_retry_failed_stream() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_sse_fail = concat!(
+		"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Partial\"}}]}\n\n",
+		"data: {\"error\":{\"message\":\"boom\",\"type\":\"server_error\"}}\n\n"
+	);
+	let fx_sse_ok = concat!(
+		"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Streamed\"}}]}\n\n",
+		"data: [DONE]\n\n"
+	);
+	let server = MockHttpServer::start(vec![
+		MockRoute::sse("POST", "/chat/completions", fx_sse_fail).with_max_hits(1),
+		MockRoute::sse("POST", "/chat/completions", fx_sse_ok),
+	])
+	.await?;
+	let aim =
+		AiManager::default().with_openai_client(OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key")));
+	let mm = ModelManager::new().await?;
+	let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
+	let final_agent = AgentBmc::first_by_name(&mm, "Final Agent")
+		.await?
+		.ok_or("Should have Final Agent")?;
+	AgentBmc::update(
+		&mm,
+		final_agent.id,
+		AgentForUpdate {
+			model: Some("openai::gpt-mock-1".to_string()),
+			retry_policy: Some(r#"{"max_attempts": 2, "backoff_ms": 0, "retry_on": ["server"]}"#.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "Hello world".into()).await?;
+
+	// -- Exec
+	let mut retried = false;
+	for _ in 0..10 {
+		if let Some(step) = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid).await? {
+			resolve_stack_step(&mm, &cfile_db, step.id).await?;
+		}
+		let step = StackStepBmc::seek_next_to_run_for_conv(&cfile_db, &conv.uid)
+			.await?
+			.ok_or("Should have a step to run")?;
+		match run_stack_step(&aim, &mm, &cfile_db, step.id).await? {
+			RunStepStatus::Ended => break,
+			RunStepStatus::Retrying { .. } => retried = true,
+			_ => (),
+		}
+	}
+
+	// -- Check
+	assert!(retried, "Should have retried the failed stream");
+	let answers: Vec<_> = MsgBmc::list(&cfile_db, None, None)
+		.await?
+		.into_iter()
+		.filter(|m| m.orig_msg_id == Some(orig_msg_id))
+		.collect();
+	assert_eq!(answers.len(), 1, "Should reuse the failed answer msg");
+	let answer = &answers[0];
+	assert_eq!(answer.content.as_deref(), Some("Streamed"));
+	assert!(answer.err.is_none());
+	assert!(answer.done_time.is_some());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_chain_vars() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_chain = r#"
+{
+	"nodes": [
+		{
+			"agent": {"name": "Inst Agent"},
+			"name_input": "original_input",
+			"name_output": "first_out"
+		},
+		{
+			"agent": "self",
+			"input": "${original_input}"
+		}
+	]
+}
+	"#;
+	let mm = ModelManager::new().await?;
+	let aim = AiManager::default();
+	let mut agents = seed_mock_echo_agents(&mm, &[("Echo Agent", ""), ("Inst Agent", "first out")]).await?;
+	agents.truncate(1);
+	let echo_agent = agents.pop().ok_or("Should have Echo Agent")?;
+	AgentBmc::update(
+		&mm,
+		echo_agent.id,
+		AgentForUpdate {
+			model: Some("fc-mock-echo-prompt".to_string()),
+			prompt_tmpl: Some("{{input}} / {{first_out}}".to_string()),
+			chain: Some(fx_chain.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let space_id = seed_space(&mm, "Space Vars").await?;
+	SpaceBmc::set_agent(&mm, space_id, echo_agent.id).await?;
+	let conv = SpaceBmc::get_latest_conv(&mm, space_id).await?;
+	let cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &conv).await?;
+
+	// -- Exec
+	let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "Hello".into()).await?;
+	for _ in 0..10 {
+		let Some(step) = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid).await? else {
+			break;
+		};
+		resolve_stack_step(&mm, &cfile_db, step.id).await?;
+		if let RunStepStatus::Ended = run_stack_step(&aim, &mm, &cfile_db, step.id).await? {
+			break;
+		}
+	}
+
+	// -- Check
+	let answer = MsgBmc::list(&cfile_db, None, None)
+		.await?
+		.into_iter()
+		.find(|m| m.orig_msg_id == Some(orig_msg_id))
+		.ok_or("Should have answer")?;
+	assert_eq!(answer.content.as_deref(), Some("Hello / first out"));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_map_node() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_chain = r#"
+{
+	"nodes": [
+		{ "agent": {"name": "Files Agent"} },
+		{
+			"map": [{ "agent": "self" }],
+			"pointer": "/files",
+			"concurrency": 2
+		}
+	]
+}
+	"#;
+	let mm = ModelManager::new().await?;
+	let aim = AiManager::default();
+	let mut agents = seed_mock_echo_agents(
+		&mm,
+		&[("Summary Agent", ""), ("Files Agent", r#"{"files": ["a.md", "b.md", "c.md"]}"#)],
+	)
+	.await?;
+	agents.truncate(1);
+	let summary_agent = agents.pop().ok_or("Should have Summary Agent")?;
+	AgentBmc::update(
+		&mm,
+		summary_agent.id,
+		AgentForUpdate {
+			model: Some("fc-mock-echo-prompt".to_string()),
+			prompt_tmpl: Some("Summary of {{input}}".to_string()),
+			chain: Some(fx_chain.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let space_id = seed_space(&mm, "Space Map").await?;
+	SpaceBmc::set_agent(&mm, space_id, summary_agent.id).await?;
+	let conv = SpaceBmc::get_latest_conv(&mm, space_id).await?;
+	let cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &conv).await?;
+
+	// -- Exec
+	let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "Summarize the files".into()).await?;
+	for _ in 0..10 {
+		let Some(step) = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid).await? else {
+			break;
+		};
+		resolve_stack_step(&mm, &cfile_db, step.id).await?;
+		if let RunStepStatus::Ended = run_stack_step(&aim, &mm, &cfile_db, step.id).await? {
+			break;
+		}
+	}
+
+	// -- Check
+	let answer = MsgBmc::list(&cfile_db, None, None)
+		.await?
+		.into_iter()
+		.find(|m| m.orig_msg_id == Some(orig_msg_id))
+		.ok_or("Should have answer")?;
+	let answer: Vec<String> = serde_json::from_str(answer.content.as_deref().ok_or("Should have content")?)?;
+	assert_eq!(answer, ["Summary of a.md", "Summary of b.md", "Summary of c.md"]);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_map_node_limits_and_cancel() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_chain = r#"
+{
+	"nodes": [
+		{ "agent": {"name": "Files Agent"} },
+		{
+			"map": [{ "agent": "self" }],
+			"pointer": "/files",
+			"concurrency": 2
+		}
+	]
+}
+	"#;
+	let fx_model_limits = HashMap::from([("fc::fc-mock-echo-prompt".to_string(), 1)]);
+	let mm = ModelManager::new().await?;
+	let aim = AiManager::default().with_run_limiter(RunLimiter::new(HashMap::new(), fx_model_limits));
+	let mut agents = seed_mock_echo_agents(
+		&mm,
+		&[("Summary Agent", ""), ("Files Agent", r#"{"files": ["a.md", "b.md"]}"#)],
+	)
+	.await?;
+	agents.truncate(1);
+	let summary_agent = agents.pop().ok_or("Should have Summary Agent")?;
+	AgentBmc::update(
+		&mm,
+		summary_agent.id,
+		AgentForUpdate {
+			model: Some("fc-mock-echo-prompt".to_string()),
+			prompt_tmpl: Some("Summary of {{input}}".to_string()),
+			chain: Some(fx_chain.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let space_id = seed_space(&mm, "Space Map Limits").await?;
+	SpaceBmc::set_agent(&mm, space_id, summary_agent.id).await?;
+	let conv = SpaceBmc::get_latest_conv(&mm, space_id).await?;
+	let cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &conv).await?;
+	ConvBmc::add_conv_msg(&mm, conv.id, "Summarize the files".into()).await?;
+	// run the Files Agent step, and resolve the map step
+	let step = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have first step")?;
+	resolve_stack_step(&mm, &cfile_db, step.id).await?;
+	run_stack_step(&aim, &mm, &cfile_db, step.id).await?;
+	let map_step = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have map step")?;
+	resolve_stack_step(&mm, &cfile_db, map_step.id).await?;
+	let map_step = StackStepBmc::get(&cfile_db, map_step.id).await?;
+	let target = aim.resolve_model(None, "fc-mock-echo-prompt").await?;
+
+	// -- Exec & Check - the item runs wait for the model permit
+	let permits = aim.run_limiter().acquire(Some(&target)).await;
+	let run = timeout(
+		Duration::from_millis(50),
+		run_stack_step_agent(&aim, &mm, &cfile_db, &map_step),
+	)
+	.await;
+	assert!(run.is_err(), "Map item runs should wait for the model permit");
+	drop(permits);
+	let (_, _, res) = run_stack_step_agent(&aim, &mm, &cfile_db, &map_step).await?;
+	let outputs: Vec<String> = serde_json::from_str(&res.response)?;
+	assert_eq!(outputs, ["Summary of a.md", "Summary of b.md"]);
+
+	// -- Exec & Check - the cancel stops the item runs
+	StackStepBmc::cancel_unfinished_for_conv(&cfile_db, &conv.uid).await?;
+	let res = run_stack_step_agent(&aim, &mm, &cfile_db, &map_step).await;
+	assert!(matches!(res, Err(crate::Error::StepRunCancelled { .. })));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_transform_node() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_chain = r#"
+{
+	"nodes": [
+		{ "agent": {"name": "Files Agent"} },
+		{ "transform": [{ "pointer": "/files" }, { "join": ", " }], "name_output": "file_list" },
+		{ "agent": "self" }
+	]
+}
+	"#;
+	let mm = ModelManager::new().await?;
+	let aim = AiManager::default();
+	let mut agents = seed_mock_echo_agents(
+		&mm,
+		&[("Writer Agent", ""), ("Files Agent", r#"{"files": ["a.md", "b.md"]}"#)],
+	)
+	.await?;
+	agents.truncate(1);
+	let writer_agent = agents.pop().ok_or("Should have Writer Agent")?;
+	AgentBmc::update(
+		&mm,
+		writer_agent.id,
+		AgentForUpdate {
+			model: Some("fc-mock-echo-prompt".to_string()),
+			prompt_tmpl: Some("Files: {{input}} ({{file_list}})".to_string()),
+			chain: Some(fx_chain.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let space_id = seed_space(&mm, "Space Transform").await?;
+	SpaceBmc::set_agent(&mm, space_id, writer_agent.id).await?;
+	let conv = SpaceBmc::get_latest_conv(&mm, space_id).await?;
+	let cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &conv).await?;
+
+	// -- Exec
+	let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "List the files".into()).await?;
+	let mut resolve_models = Vec::new();
+	for _ in 0..10 {
+		let Some(step) = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid).await? else {
+			break;
+		};
+		resolve_stack_step(&mm, &cfile_db, step.id).await?;
+		resolve_models.push(StackStepBmc::get(&cfile_db, step.id).await?.resolve_model);
+		if let RunStepStatus::Ended = run_stack_step(&aim, &mm, &cfile_db, step.id).await? {
+			break;
+		}
+	}
+
+	// -- Check
+	let answer = MsgBmc::list(&cfile_db, None, None)
+		.await?
+		.into_iter()
+		.find(|m| m.orig_msg_id == Some(orig_msg_id))
+		.ok_or("Should have answer")?;
+	assert_eq!(answer.content.as_deref(), Some("Files: a.md, b.md (a.md, b.md)"));
+	// Note: The transform step has no model (the closer step neither).
+	let resolve_models: Vec<Option<&str>> = resolve_models.iter().map(|m| m.as_deref()).collect();
+	assert_eq!(
+		resolve_models,
+		[Some("fc-mock-echo-inst"), None, Some("fc-mock-echo-prompt"), None]
+	);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_logic_agent_tool() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_chain = r#"
+{
+	"nodes": [
+		{ "agent": {"name": "Files Tool"}, "input": "{\"name_contain\": \".md\"}" },
+		{ "agent": "self" }
+	]
+}
+	"#;
+	let mm = ModelManager::new().await?;
+	let aim = AiManager::default();
+	AgentBmc::create(
+		&mm,
+		AgentForCreate {
+			kind: Some(AgentKind::Logic),
+			logic_tool: Some("list_files".to_string()),
+			..AgentForCreate::new("Files Tool")
+		},
+	)
+	.await?;
+	let mut agents = seed_mock_echo_agents(&mm, &[("Main Agent", "")]).await?;
+	let main_agent = agents.pop().ok_or("Should have Main Agent")?;
+	AgentBmc::update(
+		&mm,
+		main_agent.id,
+		AgentForUpdate {
+			model: Some("fc-mock-echo-prompt".to_string()),
+			prompt_tmpl: Some("Files: {{#each input}}{{name}} {{/each}}".to_string()),
+			chain: Some(fx_chain.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let space_id = seed_space(&mm, "Space Logic").await?;
+	let drive_id = seed_drive(&mm, "Drive Logic").await?;
+	SpaceBmc::attach_drive(&mm, space_id, drive_id, true).await?;
+	let dsource_id = seed_dsource(&mm, drive_id, "../../test-data").await?;
+	seed_ditem_parts(&mm, dsource_id, "/notes/a.md", &["Note A"]).await?;
+	seed_ditem_parts(&mm, dsource_id, "/notes/b.md", &["Note B"]).await?;
+	seed_ditem_parts(&mm, dsource_id, "/notes/c.txt", &["Note C"]).await?;
+	SpaceBmc::set_agent(&mm, space_id, main_agent.id).await?;
+	let conv = SpaceBmc::get_latest_conv(&mm, space_id).await?;
+	let cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &conv).await?;
+
+	// -- Exec
+	let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "List the notes".into()).await?;
+	for _ in 0..10 {
+		let Some(step) = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid).await? else {
+			break;
+		};
+		resolve_stack_step(&mm, &cfile_db, step.id).await?;
+		if let RunStepStatus::Ended = run_stack_step(&aim, &mm, &cfile_db, step.id).await? {
+			break;
+		}
+	}
+
+	// -- Check
+	let mut tool_step = None;
+	for step_lite in StackStepBmc::list(&cfile_db, None, None).await? {
+		let step = StackStepBmc::get(&cfile_db, step_lite.id).await?;
+		if step.run_agent_name.as_deref() == Some("Files Tool") {
+			tool_step = Some(step);
+		}
+	}
+	let tool_step = tool_step.ok_or("Should have the Files Tool step")?;
+	assert!(tool_step.run_model.is_none());
+	let answer = MsgBmc::list(&cfile_db, None, None)
+		.await?
+		.into_iter()
+		.find(|m| m.orig_msg_id == Some(orig_msg_id))
+		.ok_or("Should have answer")?;
+	assert_eq!(answer.content.as_deref(), Some("Files: a.md b.md "));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_agent_tool_calls() -> Result<()> {
+	// -- Setup & Fixtures
+	let mm = ModelManager::new().await?;
+	let aim = AiManager::default();
+	let mut agents = seed_mock_echo_agents(&mm, &[("Search Agent", "")]).await?;
+	let search_agent = agents.pop().ok_or("Should have Search Agent")?;
+	AgentBmc::update(
+		&mm,
+		search_agent.id,
+		AgentForUpdate {
+			model: Some("fc-mock-tool-call".to_string()),
+			tools: Some(r#"["drive_search"]"#.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let space_id = seed_space(&mm, "Space Tools").await?;
+	let drive_id = seed_drive(&mm, "Drive Tools").await?;
+	SpaceBmc::attach_drive(&mm, space_id, drive_id, true).await?;
+	let dsource_id = seed_dsource(&mm, drive_id, "../../test-data").await?;
+	seed_ditem_parts(&mm, dsource_id, "/notes/sky.md", &["The sky is blue"]).await?;
+	SpaceBmc::set_agent(&mm, space_id, search_agent.id).await?;
+	let conv = SpaceBmc::get_latest_conv(&mm, space_id).await?;
+	let cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &conv).await?;
+
+	// -- Exec
+	ConvBmc::add_conv_msg(&mm, conv.id, "sky".into()).await?;
+	let step = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have step to resolve")?;
+	resolve_stack_step(&mm, &cfile_db, step.id).await?;
+	run_stack_step(&aim, &mm, &cfile_db, step.id).await?;
+
+	// -- Check
+	let step = StackStepBmc::get(&cfile_db, step.id).await?;
+	assert_eq!(
+		step.call_out.as_deref(),
+		Some(r#"[{"content":"The sky is blue","line_end":1,"line_start":1,"path":"/notes/sky.md"}]"#)
+	);
+	let tool_calls = StepToolCallBmc::list_for_step(&cfile_db, step.id).await?;
+	assert_eq!(tool_calls.len(), 1);
+	assert_eq!(tool_calls[0].fn_name, "drive_search");
+	assert_eq!(tool_calls[0].fn_arguments.as_deref(), Some(r#""sky""#));
+	assert_eq!(tool_calls[0].result, step.call_out);
+	assert!(tool_calls[0].tend.is_some());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_agent_retrieval() -> Result<()> {
+	// -- Setup & Fixtures
+	let mm = ModelManager::new().await?;
+	let aim = AiManager::default();
+	let mut agents = seed_mock_echo_agents(&mm, &[("Rag Agent", "")]).await?;
+	let rag_agent = agents.pop().ok_or("Should have Rag Agent")?;
+	AgentBmc::update(
+		&mm,
+		rag_agent.id,
+		AgentForUpdate {
+			model: Some("fc-mock-echo-prompt".to_string()),
+			prompt_tmpl: Some("{{#each context}}{{this.path}}: {{this.content}}\n{{/each}}Q: {{input}}".to_string()),
+			retrieval: Some(r#"{"limit": 5}"#.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let space_id = seed_space(&mm, "Space Rag").await?;
+	let drive_id = seed_drive(&mm, "Drive Rag").await?;
+	SpaceBmc::attach_drive(&mm, space_id, drive_id, true).await?;
+	let dsource_id = seed_dsource(&mm, drive_id, "../../test-data").await?;
+	seed_ditem_parts(&mm, dsource_id, "/notes/sky.md", &["The sky is blue", "Grass is green"]).await?;
+	SpaceBmc::set_agent(&mm, space_id, rag_agent.id).await?;
+	let conv = SpaceBmc::get_latest_conv(&mm, space_id).await?;
+	let cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &conv).await?;
+
+	// -- Exec
+	ConvBmc::add_conv_msg(&mm, conv.id, "What color is the sky?".into()).await?;
+	let step = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid)
+		.await?
+		.ok_or("Should have step to resolve")?;
+	resolve_stack_step(&mm, &cfile_db, step.id).await?;
+	run_stack_step(&aim, &mm, &cfile_db, step.id).await?;
+
+	// -- Check
+	let step = StackStepBmc::get(&cfile_db, step.id).await?;
+	assert_eq!(
+		step.call_out.as_deref(),
+		Some("/notes/sky.md: The sky is blue\nQ: What color is the sky?")
+	);
 
 	Ok(())
 }
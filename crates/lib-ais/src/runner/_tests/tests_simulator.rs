@@ -0,0 +1,75 @@
+use super::*;
+use crate::_test_support::seed_mock_echo_agents;
+use lib_core::model::agent::AgentForUpdate;
+use lib_utils::x_vec::XStringVec;
+
+pub type Result<T> = core::result::Result<T, Error>;
+pub type Error = Box<dyn std::error::Error>; // For early dev.
+
+#[tokio::test]
+async fn test_simulator_branch_chain() -> Result<()> {
+	// -- Setup & Fixtures
+	let fx_chain = r#"
+{
+	"nodes": [
+		{ "agent": {"name": "Sim Classifier"} },
+		{
+			"branch": [
+				{
+					"cond": { "input": { "json_matches": { "pointer": "/category", "value": "billing" } } },
+					"nodes": [{ "agent": {"name": "Sim Billing"} }]
+				},
+				{ "else": true, "nodes": [{ "agent": {"name": "Sim Support"} }] }
+			]
+		},
+		{ "agent": "self" }
+	]
+}
+	"#;
+	let mm = ModelManager::new().await?;
+	let mut agents = seed_mock_echo_agents(
+		&mm,
+		&[
+			("Sim Router", "router answer"),
+			("Sim Classifier", ""),
+			("Sim Billing", "billing answer"),
+			("Sim Support", "support answer"),
+		],
+	)
+	.await?;
+	agents.truncate(1);
+	let router = agents.pop().ok_or("Should have Sim Router")?;
+	AgentBmc::update(
+		&mm,
+		router.id,
+		AgentForUpdate {
+			chain: Some(fx_chain.to_string()),
+			..Default::default()
+		},
+	)
+	.await?;
+	let fx_outputs: ChainSimOutputs = serde_json::from_str(r#"{"Sim Classifier": "{\"category\": \"billing\"}"}"#)?;
+
+	// -- Exec
+	let trace = simulate_agent_chain(&mm, router.id, "Where is my invoice?".to_string(), &fx_outputs).await?;
+
+	// -- Check
+	let names: Vec<&str> = trace.steps.iter().map(|s| s.agent_name.as_str()).collect();
+	assert_eq!(names, ["Sim Classifier", "Sim Billing", "Sim Router"]);
+	assert!(trace.steps[0].canned);
+	assert_eq!(trace.steps[0].input, "Where is my invoice?");
+	// Note: Sim Billing runs its own (default) chain, nested in the branch arm 0 of the router chain.
+	let billing = &trace.steps[1];
+	assert_eq!(billing.stack_agents.x_strs(), ["Sim Router", "Sim Billing"]);
+	assert_eq!(billing.arms.len(), 1);
+	assert_eq!(billing.arms[0].agent_name, "Sim Router");
+	assert_eq!(billing.arms[0].branch_idxs, [1]);
+	assert_eq!(billing.arms[0].arm_idx, 0);
+	assert_eq!(billing.output, "billing answer");
+	assert!(!billing.canned);
+	assert_eq!(trace.steps[2].cursor.idxs, [2]);
+	assert_eq!(trace.output.as_deref(), Some("router answer"));
+	assert!(!trace.truncated);
+
+	Ok(())
+}
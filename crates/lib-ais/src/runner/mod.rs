@@ -2,8 +2,16 @@
 
 // region:    --- Modules
 
+mod retrieval;
+mod retry;
+mod run_limiter;
 mod runner;
+mod simulator;
 
+pub use retrieval::*;
+pub use retry::*;
+pub use run_limiter::*;
 pub use runner::*;
+pub use simulator::*;
 
 // endregion: --- Modules
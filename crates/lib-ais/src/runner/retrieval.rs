@@ -0,0 +1,219 @@
+//! The retrieval of the space drives content for the agent prompts (see `Agent.retrieval`).
+
+use crate::search::{search_scope_chunks, ChunksSearch, SearchScope};
+use crate::tools::fts_any_term_query;
+use crate::{Error, Result};
+use lib_core::model::agent::Agent;
+use lib_core::model::dfile::DFileBmc;
+use lib_core::model::dfile_db::chunk::ChunkBmc;
+use lib_core::model::{Id, ModelManager};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// The chain variable with the retrieved hits (JSON array of `RetrievalHit`) for the agent `prompt_tmpl`.
+/// Note: It overrides the eventual chain variable of the same name.
+pub const RETRIEVAL_CONTEXT_VAR: &str = "context";
+
+/// The prompt template of the agents with retrieval but without `prompt_tmpl`.
+pub const RETRIEVAL_DEFAULT_PROMPT_TMPL: &str = "\
+Context:
+{{#each context}}
+[{{this.dfile_id}}:{{this.chunk_id}}] {{this.path}}:{{this.line_start}}-{{this.line_end}}{{#if this.breadcrumb}} ({{this.breadcrumb}}){{/if}}
+{{this.content}}
+{{/each}}
+
+{{input}}";
+
+/// The approximate number of chars per token, to fit the hits in the `token_budget`.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// The min number of chars of the input words searched (e.g., to skip "a", "of").
+const QUERY_WORD_MIN_CHARS: usize = 3;
+
+// region:    --- RetrievalConfig
+
+/// e.g., `{"limit": 20, "token_budget": 1000}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetrievalConfig {
+	/// The max number of hits searched (the best across the dfile dbs).
+	#[serde(default = "default_limit")]
+	pub limit: i64,
+
+	/// The max number of tokens of the hits contents (approximated at 4 chars per token).
+	#[serde(default = "default_token_budget")]
+	pub token_budget: usize,
+}
+
+fn default_limit() -> i64 {
+	20
+}
+
+fn default_token_budget() -> usize {
+	1000
+}
+
+impl RetrievalConfig {
+	/// Returns the agent retrieval config, None if the agent has none.
+	pub fn from_agent(agent: &Agent) -> Result<Option<Self>> {
+		let Some(config) = agent.retrieval.as_deref().filter(|c| !c.trim().is_empty()) else {
+			return Ok(None);
+		};
+
+		let config = serde_json::from_str(config).map_err(Error::RetrievalConfigFailParse)?;
+
+		Ok(Some(config))
+	}
+}
+
+// endregion: --- RetrievalConfig
+
+// region:    --- Retrieve
+
+/// A chunk of a space drive file matching the retrieval query.
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrievalHit {
+	pub dfile_id: Id,
+	pub chunk_id: Id,
+	/// The ids of the chunk parts (in the dfile db), in the line order.
+	pub part_ids: Vec<Id>,
+	pub path: String,
+	pub line_start: i64,
+	pub line_end: i64,
+	/// The heading titles of the chunk (e.g., `Intro > Setup`).
+	pub breadcrumb: String,
+	pub content: String,
+	/// The fts rank (bm25 of the best chunk part in its dfile db, the lower the better).
+	pub rank: f64,
+}
+
+/// Search the words of the `query` in every dfile db of the space drives,
+/// and returns the chunks of the best hits (across the dfile dbs, see `search_scope_chunks`) fitting in the `token_budget`.
+pub async fn retrieve_space_hits(
+	mm: &ModelManager,
+	space_id: Id,
+	config: &RetrievalConfig,
+	query: &str,
+) -> Result<Vec<RetrievalHit>> {
+	let Some(fts_query) = fts_query_words_any(query) else {
+		return Ok(Vec::new());
+	};
+
+	let limit = usize::try_from(config.limit).unwrap_or_default();
+	let mut hits: Vec<RetrievalHit> =
+		search_scope_chunks(mm, SearchScope::Space(space_id), ChunksSearch::Fts(&fts_query), limit)
+			.await?
+			.into_iter()
+			.map(|hit| RetrievalHit {
+				dfile_id: hit.dfile_id,
+				chunk_id: hit.chunk_id,
+				part_ids: Vec::new(),
+				path: hit.path,
+				line_start: hit.line_start,
+				line_end: hit.line_end,
+				breadcrumb: hit.breadcrumb,
+				content: hit.content,
+				rank: -hit.score,
+			})
+			.collect();
+
+	// -- Trim to the budget (the hits over the remaining budget are skipped)
+	let mut budget_chars = config.token_budget * CHARS_PER_TOKEN;
+	hits.retain(|hit| {
+		let chars = hit.content.chars().count();
+		let fits = chars <= budget_chars;
+		if fits {
+			budget_chars -= chars;
+		}
+		fits
+	});
+
+	// -- Attach the part ids (of the kept hits)
+	let mut dfile_dbs = HashMap::new();
+	for hit in hits.iter_mut() {
+		let dfile_db = match dfile_dbs.entry(*hit.dfile_id) {
+			Entry::Occupied(entry) => entry.into_mut(),
+			Entry::Vacant(entry) => {
+				let dfile = DFileBmc::get(mm, hit.dfile_id).await?;
+				entry.insert(DFileBmc::get_dfile_db(mm, &dfile).await?)
+			}
+		};
+		hit.part_ids = ChunkBmc::list_part_ids(dfile_db, hit.chunk_id).await?;
+	}
+
+	Ok(hits)
+}
+
+/// Returns the fts query matching any of the words of the text (None if no words).
+/// Note: The words shorter than `QUERY_WORD_MIN_CHARS` are skipped (e.g., "a", "of").
+pub(crate) fn fts_query_words_any(text: &str) -> Option<String> {
+	let words: Vec<String> = text
+		.split(|c: char| !c.is_alphanumeric())
+		.filter(|w| w.chars().count() >= QUERY_WORD_MIN_CHARS)
+		.map(str::to_lowercase)
+		.collect();
+
+	fts_any_term_query(&words)
+}
+
+// endregion: --- Retrieve
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use lib_core::_test_support::{seed_ditem_parts, seed_drive, seed_dsource, seed_space};
+	use lib_core::model::dfile_db::part::PartBmc;
+	use lib_core::model::space::SpaceBmc;
+
+	#[tokio::test]
+	async fn test_retrieval_space_hits_ranked_budget() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let space_id = seed_space(&mm, "Space Retrieval").await?;
+		let drive_id = seed_drive(&mm, "Drive Retrieval").await?;
+		SpaceBmc::attach_drive(&mm, space_id, drive_id, true).await?;
+		let dsource_id = seed_dsource(&mm, drive_id, "../../test-data").await?;
+		seed_ditem_parts(
+			&mm,
+			dsource_id,
+			"/notes/ocean.md",
+			&[
+				"The ocean is deep",
+				"The ocean water is blue and the ocean is big",
+				"Nothing here",
+			],
+		)
+		.await?;
+		// Note: Not in the space drives, so never retrieved.
+		let other_drive_id = seed_drive(&mm, "Drive Other").await?;
+		let other_dsource_id = seed_dsource(&mm, other_drive_id, "../../test-data").await?;
+		seed_ditem_parts(&mm, other_dsource_id, "/other/ocean.md", &["The ocean is wide"]).await?;
+		let fx_config: RetrievalConfig = serde_json::from_str(r#"{"token_budget": 10}"#)?;
+
+		// -- Exec
+		let hits = retrieve_space_hits(&mm, space_id, &fx_config, "What about the ocean?").await?;
+
+		// -- Check
+		// Note: The budget is 40 chars, so the longest hit (44 chars) is skipped.
+		assert_eq!(hits.len(), 1);
+		assert_eq!(hits[0].path, "/notes/ocean.md");
+		assert_eq!(hits[0].content, "The ocean is deep");
+		assert_eq!(hits[0].line_start, 1);
+		let dfile = DFileBmc::get(&mm, hits[0].dfile_id).await?;
+		let dfile_db = DFileBmc::get_dfile_db(&mm, &dfile).await?;
+		let [part_id] = hits[0].part_ids[..] else {
+			return Err(format!("Should have one part id, but was {:?}", hits[0].part_ids).into());
+		};
+		assert_eq!(PartBmc::get(&dfile_db, part_id).await?.content, "The ocean is deep");
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,212 @@
+//! The retry policy of the failed agent runs (see `Agent.retry_policy`).
+
+use crate::{Error, Result};
+use async_openai::error::OpenAIError;
+use lib_core::model::agent::Agent;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// region:    --- ErrorKind
+
+/// The kind of a run error, to decide if it is worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+	/// Connection, timeout, or stream interruption.
+	Network,
+	/// The provider rate limited the request (e.g., http 429).
+	RateLimit,
+	/// The provider failed (e.g., http 5xx, unparsable response).
+	Server,
+	/// The request was rejected (e.g., http 4xx, bad model name).
+	Client,
+	/// Any other error (e.g., prompt template or model error).
+	Other,
+}
+
+impl Error {
+	pub fn kind(&self) -> ErrorKind {
+		match self {
+			Error::Reqwest(err) => reqwest_error_kind(err),
+			Error::AnthropicHttp { status, .. } => http_status_kind(*status),
+			Error::AnthropicFailParse(_) => ErrorKind::Server,
+			Error::AnthropicStreamError(_) => ErrorKind::Server,
+			Error::OpenaiHttp { status, .. } => http_status_kind(*status),
+			Error::OpenaiFailParse(_) | Error::OpenaiStreamError(_) => ErrorKind::Server,
+			Error::OpenAI(err) => match err {
+				OpenAIError::Reqwest(err) => reqwest_error_kind(err),
+				OpenAIError::ApiError(api_err) => {
+					let codes = [api_err.r#type.as_deref(), api_err.code.as_deref()];
+					if codes.iter().flatten().any(|c| c.contains("rate_limit")) {
+						ErrorKind::RateLimit
+					} else if codes.iter().flatten().any(|c| c.contains("server_error")) {
+						ErrorKind::Server
+					} else {
+						ErrorKind::Client
+					}
+				}
+				OpenAIError::JSONDeserialize(_) => ErrorKind::Server,
+				OpenAIError::StreamError(_) => ErrorKind::Network,
+				_ => ErrorKind::Other,
+			},
+			Error::OllamaHttp { status, .. } => http_status_kind(*status),
+			Error::OllamaFailParse(_) | Error::OllamaStreamError(_) => ErrorKind::Server,
+			// Note: From `ollama_rs` (e.g., list models), which only has the error message.
+			Error::Ollama(_) => ErrorKind::Other,
+			Error::AiModelNotFound { .. } | Error::AiProviderUnknown(_) | Error::AiModelNotImplemented(_) => {
+				ErrorKind::Client
+			}
+			_ => ErrorKind::Other,
+		}
+	}
+}
+
+fn reqwest_error_kind(err: &reqwest::Error) -> ErrorKind {
+	match err.status() {
+		Some(status) => http_status_kind(status.as_u16()),
+		None => ErrorKind::Network,
+	}
+}
+
+fn http_status_kind(status: u16) -> ErrorKind {
+	match status {
+		429 => ErrorKind::RateLimit,
+		500..=599 => ErrorKind::Server,
+		400..=499 => ErrorKind::Client,
+		_ => ErrorKind::Other,
+	}
+}
+
+// endregion: --- ErrorKind
+
+// region:    --- RetryPolicy
+
+/// e.g., `{"max_attempts": 3, "backoff_ms": 1000, "backoff_factor": 2, "retry_on": ["network", "rate_limit"]}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+	/// Total number of attempts, including the first one.
+	#[serde(default = "default_max_attempts")]
+	pub max_attempts: i64,
+
+	/// Delay before the first retry.
+	#[serde(default = "default_backoff_ms")]
+	pub backoff_ms: u64,
+
+	/// Multiplier of the delay for each next retry.
+	#[serde(default = "default_backoff_factor")]
+	pub backoff_factor: f64,
+
+	/// The error kinds to retry on.
+	#[serde(default = "default_retry_on")]
+	pub retry_on: Vec<ErrorKind>,
+}
+
+fn default_max_attempts() -> i64 {
+	3
+}
+
+fn default_backoff_ms() -> u64 {
+	1000
+}
+
+fn default_backoff_factor() -> f64 {
+	2.0
+}
+
+fn default_retry_on() -> Vec<ErrorKind> {
+	vec![ErrorKind::Network, ErrorKind::RateLimit, ErrorKind::Server]
+}
+
+impl RetryPolicy {
+	/// Returns the agent retry policy, None if the agent has none.
+	pub fn from_agent(agent: &Agent) -> Result<Option<Self>> {
+		let Some(policy) = agent.retry_policy.as_deref().filter(|p| !p.trim().is_empty()) else {
+			return Ok(None);
+		};
+
+		let policy = serde_json::from_str(policy).map_err(Error::RetryPolicyFailParse)?;
+
+		Ok(Some(policy))
+	}
+
+	/// Returns the delay before the next attempt,
+	/// or None if the failed `attempt` (starting at 1) should not be retried.
+	pub fn next_retry_delay(&self, attempt: i64, err_kind: ErrorKind) -> Option<Duration> {
+		if attempt >= self.max_attempts || !self.retry_on.contains(&err_kind) {
+			return None;
+		}
+
+		let exp = (attempt - 1).clamp(0, 16) as i32;
+		let delay_ms = self.backoff_ms as f64 * self.backoff_factor.max(1.0).powi(exp);
+
+		Some(Duration::from_millis(delay_ms as u64))
+	}
+}
+
+// endregion: --- RetryPolicy
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_retry_policy_next_retry_delay() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_policy: RetryPolicy =
+			serde_json::from_str(r#"{"max_attempts": 3, "backoff_ms": 100, "retry_on": ["rate_limit"]}"#)?;
+
+		// -- Exec & Check
+		assert_eq!(
+			fx_policy.next_retry_delay(1, ErrorKind::RateLimit),
+			Some(Duration::from_millis(100))
+		);
+		assert_eq!(
+			fx_policy.next_retry_delay(2, ErrorKind::RateLimit),
+			Some(Duration::from_millis(200))
+		);
+		assert_eq!(fx_policy.next_retry_delay(3, ErrorKind::RateLimit), None);
+		assert_eq!(fx_policy.next_retry_delay(1, ErrorKind::Client), None);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_retry_error_kind() -> Result<()> {
+		// -- Exec & Check
+		let err = crate::Error::AnthropicHttp {
+			status: 429,
+			body: "".to_string(),
+		};
+		assert_eq!(err.kind(), ErrorKind::RateLimit);
+		let err = crate::Error::AnthropicHttp {
+			status: 529,
+			body: "".to_string(),
+		};
+		assert_eq!(err.kind(), ErrorKind::Server);
+		let err = crate::Error::OpenaiHttp {
+			status: 429,
+			body: r#"{"error": {"type": "rate_limit_exceeded"}}"#.to_string(),
+		};
+		assert_eq!(err.kind(), ErrorKind::RateLimit);
+		let err = crate::Error::AiModelNotFound {
+			model: "nope".to_string(),
+		};
+		assert_eq!(err.kind(), ErrorKind::Client);
+		let err = crate::Error::OllamaHttp {
+			status: 404,
+			body: r#"{"error": "model 'nope' not found"}"#.to_string(),
+		};
+		assert_eq!(err.kind(), ErrorKind::Client);
+		assert_eq!(crate::Error::MutexPoison.kind(), ErrorKind::Other);
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,110 @@
+//! The concurrency limits of the model runs, per provider and per model (see `AiManager::with_run_limiter`).
+
+use crate::ModelTarget;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Holds the semaphores of the limited providers and models (created on first use).
+/// Note: The default has no limits.
+#[derive(Default)]
+pub struct RunLimiter {
+	provider_limits: HashMap<String, usize>,
+	model_limits: HashMap<String, usize>,
+
+	semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+/// The permits of a run, released on drop.
+pub struct RunPermits {
+	_permits: Vec<OwnedSemaphorePermit>,
+}
+
+impl RunLimiter {
+	pub fn new(provider_limits: HashMap<String, usize>, model_limits: HashMap<String, usize>) -> Self {
+		Self {
+			provider_limits,
+			model_limits,
+			semaphores: Default::default(),
+		}
+	}
+
+	/// Wait for the provider and model permits of the target (none if the target is unknown or unlimited).
+	/// Note: The provider permit is always acquired before the model one, so the waits cannot cross.
+	pub async fn acquire(&self, target: Option<&ModelTarget>) -> RunPermits {
+		let mut permits = Vec::new();
+
+		if let Some(target) = target {
+			let provider = target.kind.as_str();
+			let model_key = target.to_string();
+
+			let provider_sem = self.semaphore(provider, self.provider_limits.get(provider));
+			let model_sem = self.semaphore(&model_key, self.model_limits.get(&model_key));
+
+			for sem in [provider_sem, model_sem].into_iter().flatten() {
+				// Note: The semaphores are never closed, so acquire cannot fail.
+				if let Ok(permit) = sem.acquire_owned().await {
+					permits.push(permit);
+				}
+			}
+		}
+
+		RunPermits { _permits: permits }
+	}
+
+	fn semaphore(&self, key: &str, limit: Option<&usize>) -> Option<Arc<Semaphore>> {
+		let limit = *limit?;
+		// Note: A zero limit means no limit (rather than blocking all the runs).
+		if limit == 0 {
+			return None;
+		}
+
+		let mut semaphores = self.semaphores.lock().unwrap_or_else(|poison| poison.into_inner());
+		let sem = semaphores
+			.entry(key.to_string())
+			.or_insert_with(|| Arc::new(Semaphore::new(limit)));
+
+		Some(sem.clone())
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::AiManager;
+	use std::time::Duration;
+	use tokio::time::timeout;
+
+	#[tokio::test]
+	async fn test_run_limiter_per_model() -> Result<()> {
+		// -- Setup & Fixtures
+		let aim = AiManager::default();
+		let fx_model_limits = HashMap::from([("openai::gpt-a".to_string(), 1)]);
+		let limiter = RunLimiter::new(HashMap::new(), fx_model_limits);
+		let target_a = aim.resolve_model(None, "openai::gpt-a").await?;
+		let target_b = aim.resolve_model(None, "openai::gpt-b").await?;
+
+		// -- Exec
+		let _permits_a = limiter.acquire(Some(&target_a)).await;
+
+		// -- Check
+		let wait = Duration::from_millis(20);
+		assert!(
+			timeout(wait, limiter.acquire(Some(&target_a))).await.is_err(),
+			"gpt-a should be at its limit"
+		);
+		assert!(
+			timeout(wait, limiter.acquire(Some(&target_b))).await.is_ok(),
+			"gpt-b should not be limited"
+		);
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
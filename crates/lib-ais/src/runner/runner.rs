@@ -1,16 +1,30 @@
-use crate::chain::{resolve_agent, AgentChain, ChainCallStack, InputContent, StackItem};
-use crate::{AiManager, GenReq, GenRes};
+use crate::chain::{
+	map_item_to_input, render_chain_tmpl, resolve_agent, AgentChain, ChainCallStack, ChainVars, InputContent, MapNode,
+	StackItem,
+};
+use crate::client::{AiClient, PROVIDER_SEP};
+use crate::runner::{
+	retrieve_space_hits, RetrievalConfig, RetryPolicy, RETRIEVAL_CONTEXT_VAR, RETRIEVAL_DEFAULT_PROMPT_TMPL,
+};
+use crate::tools::{agent_tools, run_tool_call};
+use crate::{AiManager, ChatMsg, GenReq, GenRes, GenUsage, LogicToolCtx, ModelTarget, ToolCall};
 use crate::{Error, Result};
-use lib_core::model::agent::{Agent, AgentBmc};
+use futures::future::BoxFuture;
+use futures::{stream, StreamExt, TryStreamExt};
+use lib_core::event::{ConvEvent, Subscriber};
+use lib_core::model::agent::{Agent, AgentBmc, AgentKind};
 use lib_core::model::conv::ConvBmc;
-use lib_core::model::msg::MsgBmc;
+use lib_core::model::msg::{AuthorKind, MsgBmc, MSG_ERR_CANCELLED};
 use lib_core::model::stack_step::{StackStep, StackStepBmc, StackStepForUpdate};
+use lib_core::model::step_tool_call::{StepToolCallBmc, StepToolCallForCreate, StepToolCallForUpdate};
 use lib_core::model::support::prelude::SlDb;
 use lib_core::model::{Id, ModelManager};
+use lib_utils::time::now_unix_time_us;
 use lib_utils::x_string::XStr as _;
 use lib_utils::{hbs, s};
-use serde_json::json;
-use tracing::{error, warn};
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{error, info, warn};
 
 /// The `resolve_stack_step`
 /// - `CloserStep` means that the step has the flag `is_closer` and stack is empty.
@@ -42,11 +56,9 @@ pub async fn resolve_stack_step(mm: &ModelManager, cfile_db: &SlDb, step_id: Id)
 	//    (which is the next stack of the prev_stack)
 	let input = InputContent::new(prev_output);
 	let computed_stack = compute_next_stack(mm, prev_stack, input).await?;
-	let resolve_model = if let Some(agent_uid) = computed_stack.last_item().map(|si| si.agent_uid.as_str()) {
-		let agent = AgentBmc::get_by_uid(mm, agent_uid).await?;
-		agent.model
-	} else {
-		None
+	let resolve_model = match computed_stack.last_item() {
+		Some(sitem) => resolve_item_model(mm, sitem).await?,
+		None => None,
 	};
 
 	let state = if computed_stack.is_empty() {
@@ -71,12 +83,45 @@ pub async fn resolve_stack_step(mm: &ModelManager, cfile_db: &SlDb, step_id: Id)
 	Ok(state)
 }
 
+/// Returns the model of the agent to run for the stack item
+/// (None for a map node, as it runs its own agents, for a transform node, and for a `AgentKind::Logic` agent,
+/// as it runs its tool).
+/// Note: The explicit agent provider is kept with the model (e.g., `openai::gpt-4o`),
+///       so the run can be scheduled per provider and model (see `AiManager::resolve_model`).
+async fn resolve_item_model(mm: &ModelManager, sitem: &StackItem) -> Result<Option<String>> {
+	let agent = AgentBmc::get_by_uid(mm, &sitem.agent_uid).await?;
+	if matches!(agent.kind, AgentKind::Logic) {
+		return Ok(None);
+	}
+	let agent_chain = agent.get_chain()?;
+	let chain = agent_chain.scope_chain(&sitem.scope)?;
+	if chain.get_map_node(&sitem.cursor).is_some() || chain.get_transform_node(&sitem.cursor).is_some() {
+		return Ok(None);
+	}
+
+	let model = match (agent.provider.filter(|p| !p.trim().is_empty()), agent.model) {
+		(Some(provider), Some(model)) if !model.contains(PROVIDER_SEP) => {
+			Some(format!("{provider}{PROVIDER_SEP}{model}"))
+		}
+		(_, model) => model,
+	};
+	Ok(model)
+}
+
 /// The run_stack_step response status.
 /// NOTE: variant should have better names
 #[derive(Debug)]
 pub enum RunStepStatus {
 	Ended,
 	Ongoing,
+	/// The conv work got cancelled (see `ConvBmc::cancel_work`) before or while running the step.
+	Cancelled,
+	/// The step failed, and a retry step was created per the agent `RetryPolicy`,
+	/// which can run after the `delay`.
+	Retrying {
+		retry_step_id: Id,
+		delay: Duration,
+	},
 }
 
 /// Run a stack_step for the model at the step call stack location
@@ -84,9 +129,16 @@ pub enum RunStepStatus {
 /// - Branch
 ///   - if step is a closer, then,
 ///   - if step is not a closer, then, run the agent model, and create next step
+/// - The agent run is aborted (e.g., active stream dropped) on `ConvEvent::ConvWorkCancel` for the conv.
 pub async fn run_stack_step(aim: &AiManager, mm: &ModelManager, cfile_db: &SlDb, step_id: Id) -> Result<RunStepStatus> {
-	StackStepBmc::set_run_tstart(cfile_db, step_id).await?;
+	// Note: Subscribe first, so that a cancel while running cannot be missed.
+	let mut conv_sub: Subscriber<ConvEvent> = mm.hub().subscriber()?;
+
 	let step = StackStepBmc::get(cfile_db, step_id).await?;
+	if step.run_tcancel.is_some() {
+		return Ok(RunStepStatus::Cancelled);
+	}
+	StackStepBmc::set_run_tstart(cfile_db, step_id).await?;
 
 	// If closer, create the answer Msg
 	let state = if step.closer {
@@ -94,13 +146,25 @@ pub async fn run_stack_step(aim: &AiManager, mm: &ModelManager, cfile_db: &SlDb,
 		let prev_out = prev_out.ok_or(Error::PrevStepHasNoOuput(step_id))?;
 
 		MsgBmc::create_agent_answer(cfile_db, step, prev_out).await?;
+		StackStepBmc::set_run_tend(cfile_db, step_id).await?;
 		RunStepStatus::Ended
 	}
 	// if not a closer, run agent, and create next step
 	else {
-		match run_stack_step_agent(aim, mm, cfile_db, &step).await {
+		let conv_uid = MsgBmc::get_conv_uid(cfile_db, step.orig_msg_id).await?;
+		let conv = ConvBmc::get_by_uid(mm, &conv_uid).await?;
+
+		let run_res = tokio::select! {
+			res = run_stack_step_agent(aim, mm, cfile_db, &step) => res,
+			_ = wait_for_cancel(&mut conv_sub, conv.id) => {
+				// Note: The step and the answer msg were already updated by the cancel.
+				return Ok(RunStepStatus::Cancelled);
+			}
+		};
+
+		match run_res {
 			// -- if we have run success
-			Ok((agent, res)) => {
+			Ok((agent, target, res)) => {
 				// update the step with success
 				StackStepBmc::update_run_end_success(
 					cfile_db,
@@ -109,17 +173,27 @@ pub async fn run_stack_step(aim: &AiManager, mm: &ModelManager, cfile_db: &SlDb,
 						call_out: Some(res.response),
 						run_agent_uid: Some(agent.uid),
 						run_agent_name: Some(agent.name),
+						run_model: target.map(|t| t.to_string()),
+						usage_prompt_tokens: res.usage.prompt_tokens,
+						usage_completion_tokens: res.usage.completion_tokens,
 						..Default::default()
 					},
 				)
 				.await?;
 
+				// if cancelled just before the end of the run, we do not go further
+				if StackStepBmc::get(cfile_db, step_id).await?.run_tcancel.is_some() {
+					return Ok(RunStepStatus::Cancelled);
+				}
+
 				// create the next step
 				StackStepBmc::create_next_from_step(cfile_db, step_id).await?;
 
 				// return ongon
 				RunStepStatus::Ongoing
 			}
+			// -- if cancelled while running (see `run_agent_model_stream`)
+			Err(Error::StepRunCancelled { .. }) => return Ok(RunStepStatus::Cancelled),
 			Err(err) => {
 				error!("Fail to run step {step_id} cause: {err}");
 				StackStepBmc::update_run_end_fail(
@@ -132,6 +206,16 @@ pub async fn run_stack_step(aim: &AiManager, mm: &ModelManager, cfile_db: &SlDb,
 				)
 				.await?;
 
+				// -- Retry if the agent retry policy allows it
+				match create_policy_retry(mm, cfile_db, &step, &err).await {
+					Ok(Some((retry_step_id, delay))) => {
+						info!("Step {step_id} will be retried as step {retry_step_id} in {delay:?}");
+						return Ok(RunStepStatus::Retrying { retry_step_id, delay });
+					}
+					Ok(None) => (),
+					Err(retry_err) => error!("Fail to create retry for step {step_id} cause: {retry_err}"),
+				}
+
 				return Err(err);
 			}
 		}
@@ -139,17 +223,22 @@ pub async fn run_stack_step(aim: &AiManager, mm: &ModelManager, cfile_db: &SlDb,
 	Ok(state)
 }
 
+/// Run the stack step last item (see `run_stack_item`).
+/// Note: The target is None when the item is a map or transform node.
 pub async fn run_stack_step_agent(
 	aim: &AiManager,
 	mm: &ModelManager,
 	cfile_db: &SlDb,
 	step: &StackStep,
-) -> Result<(Agent, GenRes)> {
+) -> Result<(Agent, Option<ModelTarget>, GenRes)> {
 	let step_id = step.id;
 
 	// -- get the call stack
 	let mut stack = ChainCallStack::from_json(step.call_stack.as_ref().ok_or(Error::StackStepNotFound(step_id))?)?;
 
+	// Note: Must be computed before the pop, as it looks at the whole stack.
+	let is_last_gen = is_last_generation(mm, &stack).await?;
+
 	let Some(sitem) = stack.pop_item() else {
 		// TODO: Might need to handle differently (return error)
 		warn!("run_stack_step_agent - nothing to be ran, no stack item in stack - abort, skip");
@@ -157,72 +246,520 @@ pub async fn run_stack_step_agent(
 	};
 
 	// -- Resolve
-	let agent_uid = sitem.agent_uid;
-	let (_, prev_output) = get_prev_stack_and_output(mm, cfile_db, step).await?;
-	let input = InputContent::new(prev_output);
+	let input = match sitem.input.clone() {
+		Some(input) => input,
+		None => get_prev_stack_and_output(mm, cfile_db, step).await?.1,
+	};
+
+	// -- Exec (the last generation is streamed into the pending answer msg)
+	run_stack_item(aim, mm, cfile_db, step, sitem, input, is_last_gen).await
+}
+
+/// Run the node at the stack item cursor, either the agent model (or tool for a `AgentKind::Logic` agent),
+/// the map node sub-chain per item, or the transform node.
+/// - `stream` streams the agent generation into the pending answer msg (see `run_agent_model_stream`).
+async fn run_stack_item(
+	aim: &AiManager,
+	mm: &ModelManager,
+	cfile_db: &SlDb,
+	step: &StackStep,
+	sitem: StackItem,
+	input: String,
+	stream: bool,
+) -> Result<(Agent, Option<ModelTarget>, GenRes)> {
+	let agent = AgentBmc::get_by_uid(mm, &sitem.agent_uid).await?;
+
+	// -- Map node
+	let chain = agent.get_chain()?;
+	if let Some(map_node) = chain.scope_chain(&sitem.scope)?.get_map_node(&sitem.cursor) {
+		let res = run_map_node(aim, mm, cfile_db, step, map_node, &sitem, input).await?;
+		return Ok((agent, None, res));
+	}
+
+	// -- Transform node (no model, no usage)
+	if let Some(transform_node) = chain.scope_chain(&sitem.scope)?.get_transform_node(&sitem.cursor) {
+		let res = GenRes {
+			response: transform_node.apply(&input, &sitem.vars)?,
+			..Default::default()
+		};
+		return Ok((agent, None, res));
+	}
+
+	// -- Logic agent
+	if matches!(agent.kind, AgentKind::Logic) {
+		let res = run_logic_agent(aim, mm, cfile_db, &agent, step.orig_msg_id, input).await?;
+		return Ok((agent, None, res));
+	}
+
+	// -- Agent node
+	let prompt = render_agent_prompt(mm, cfile_db, &agent, step.orig_msg_id, input, &sitem.vars).await?;
+	let history = get_agent_history(cfile_db, &agent, step.orig_msg_id).await?;
+	let with_tools = !agent_tools(&agent, aim.logic_tools())?.is_empty();
+	let (target, res) = if with_tools {
+		run_agent_model_tools(aim, mm, cfile_db, &agent, step, prompt, history).await?
+	} else if stream {
+		run_agent_model_stream(aim, mm, cfile_db, &agent, prompt, history, step).await?
+	} else {
+		run_agent_model(aim, &agent, prompt, history).await?
+	};
+
+	Ok((agent, Some(target), res))
+}
 
-	let agent = AgentBmc::get_by_uid(mm, &agent_uid).await?;
-	let res = run_agent_model(aim, &agent, input).await?;
+/// Run the map node sub-chain once per item (at most `concurrency` at the same time),
+/// and returns the outputs gathered in a JSON array (in the items order), with the summed usage.
+/// - Each item agent run waits for its run permits (see `AiManager::run_limiter`),
+///   as the map step itself has no model to be limited by.
+/// - A cancel of the step stops the item runs (`Error::StepRunCancelled`).
+/// Note: The item runs are not persisted as steps, and the first failing item fails the map.
+async fn run_map_node(
+	aim: &AiManager,
+	mm: &ModelManager,
+	cfile_db: &SlDb,
+	step: &StackStep,
+	map_node: &MapNode,
+	sitem: &StackItem,
+	input: String,
+) -> Result<GenRes> {
+	let items = map_node.resolve_items(&InputContent::new(input), &sitem.vars)?;
+
+	// -- The item runs start at the map sub-chain, with the map chain vars
+	let mut scope = sitem.scope.clone();
+	scope.push(sitem.cursor.idxs.clone());
+	let item_runs = items.into_iter().map(|item| {
+		let item_sitem = StackItem::new_at_start(&sitem.agent_uid)
+			.with_vars(sitem.vars.clone())
+			.with_input(Some(map_item_to_input(item)))
+			.with_scope(scope.clone());
+		run_map_item(aim, mm, cfile_db, step, item_sitem)
+	});
+	let item_ress: Vec<GenRes> = stream::iter(item_runs).buffered(map_node.concurrency()).try_collect().await?;
+
+	// -- Gather the outputs
+	let mut usage = GenUsage::default();
+	let mut outputs = Vec::new();
+	for item_res in item_ress {
+		usage.add(&item_res.usage);
+		outputs.push(InputContent::new(item_res.response).into_value());
+	}
 
-	Ok((agent, res))
+	Ok(GenRes {
+		response: Value::Array(outputs).to_string(),
+		usage,
+		..Default::default()
+	})
 }
 
-/// Run the agent model for a given input
+/// Run a map item, from its start stack item to the end of the map sub-chain, and returns its last output.
+/// Note: Boxed, as a map sub-chain can have map nodes.
+fn run_map_item<'a>(
+	aim: &'a AiManager,
+	mm: &'a ModelManager,
+	cfile_db: &'a SlDb,
+	step: &'a StackStep,
+	sitem: StackItem,
+) -> BoxFuture<'a, Result<GenRes>> {
+	Box::pin(async move {
+		let mut output = sitem.input.clone().unwrap_or_default();
+		let mut usage = GenUsage::default();
+		let mut stack = ChainCallStack { items: vec![sitem] };
+
+		loop {
+			stack = compute_next_stack(mm, stack, InputContent::new(&output)).await?;
+			let Some(sitem) = stack.last_item().cloned() else {
+				break;
+			};
+
+			// -- Stop on cancel (the map step run might be long)
+			if StackStepBmc::get(cfile_db, step.id).await?.run_tcancel.is_some() {
+				return Err(Error::StepRunCancelled { step_id: step.id });
+			}
+
+			// -- Wait for the run permits of the item model
+			let target = match resolve_item_model(mm, &sitem).await? {
+				// Note: The run will fail with the proper error.
+				Some(model) => aim.resolve_model(None, &model).await.ok(),
+				None => None,
+			};
+			let _run_permits = aim.run_limiter().acquire(target.as_ref()).await;
+
+			let input = sitem.input.clone().unwrap_or(output);
+			let (_, _, res) = run_stack_item(aim, mm, cfile_db, step, sitem, input, false).await?;
+			usage.add(&res.usage);
+			output = res.response;
+		}
+
+		Ok(GenRes {
+			response: output,
+			usage,
+			..Default::default()
+		})
+	})
+}
+
+/// Run the `logic_tool` of the `AgentKind::Logic` agent (see `AiManager::logic_tools`),
+/// in the space of the orig msg conversation.
+/// - The input is the tool params (a JSON string for a text input).
+/// - The response is the tool JSON result (no usage, as no model runs).
+async fn run_logic_agent(
+	aim: &AiManager,
+	mm: &ModelManager,
+	cfile_db: &SlDb,
+	agent: &Agent,
+	orig_msg_id: Id,
+	input: String,
+) -> Result<GenRes> {
+	let tool_name = agent.logic_tool.x_non_empty_str().ok_or_else(|| Error::LogicAgentHasNoTool {
+		agent_id: agent.id,
+		agent_name: agent.name.to_string(),
+	})?;
+	let tool = aim.logic_tools().get(tool_name).ok_or_else(|| Error::LogicToolNotFound {
+		name: tool_name.to_string(),
+	})?;
+
+	let ctx = LogicToolCtx {
+		mm,
+		space_id: get_conv_space_id(mm, cfile_db, orig_msg_id).await?,
+	};
+
+	let params = InputContent::new(input).into_value();
+	let output = tool.run(&ctx, params).await?;
+
+	Ok(GenRes {
+		response: output.to_string(),
+		..Default::default()
+	})
+}
+
+/// Run the agent model for a given prompt (see `render_prompt_tmpl`)
+/// - `history` are the previous conversation messages (in order) sent before the input.
+/// - Returns the resolved `ModelTarget` along with the `GenRes`.
 /// TODO: needs to remove pub
-async fn run_agent_model(aim: &AiManager, agent: &Agent, input: InputContent) -> Result<GenRes> {
+async fn run_agent_model(
+	aim: &AiManager,
+	agent: &Agent,
+	prompt: String,
+	history: Vec<ChatMsg>,
+) -> Result<(ModelTarget, GenRes)> {
+	let (ai_client, target, gen_req) = prep_agent_gen(aim, agent, prompt, history).await?;
+
+	let gen_res = ai_client.gen(&target.model, gen_req).await?;
+
+	Ok((target, gen_res))
+}
+
+/// The max number of model generations of a step run with tools (i.e., the tool call rounds and the answer).
+const AGENT_TOOL_ROUNDS_MAX: usize = 8;
+
+/// Run the agent model with its tools (see `Agent.tools`), until the model answers without tool calls.
+/// - Each tool call is recorded as a `StepToolCall` of the step, and its result is sent back to the model.
+/// - The usage is summed over the generations.
+/// - Note: Not streamed, as the tool calls come before the answer.
+async fn run_agent_model_tools(
+	aim: &AiManager,
+	mm: &ModelManager,
+	cfile_db: &SlDb,
+	agent: &Agent,
+	step: &StackStep,
+	prompt: String,
+	history: Vec<ChatMsg>,
+) -> Result<(ModelTarget, GenRes)> {
+	let (ai_client, target, mut gen_req) = prep_agent_gen(aim, agent, prompt, history).await?;
+	let ctx = LogicToolCtx {
+		mm,
+		space_id: get_conv_space_id(mm, cfile_db, step.orig_msg_id).await?,
+	};
+
+	let mut usage = GenUsage::default();
+	for _ in 0..AGENT_TOOL_ROUNDS_MAX {
+		let res = ai_client.gen(&target.model, gen_req.clone()).await?;
+		usage.add(&res.usage);
+		if res.tool_calls.is_empty() {
+			return Ok((target, GenRes { usage, ..res }));
+		}
+
+		gen_req
+			.messages
+			.push(ChatMsg::assistant_tool_calls(res.response, res.tool_calls.clone()));
+		for tool_call in res.tool_calls {
+			let content = run_step_tool_call(aim, cfile_db, &ctx, step.id, &tool_call).await?;
+			gen_req.messages.push(ChatMsg::tool_result(tool_call.call_id, content));
+		}
+	}
+
+	Err(Error::AgentToolRoundsOverLimit {
+		agent_name: agent.name.to_string(),
+		limit: AGENT_TOOL_ROUNDS_MAX,
+	})
+}
+
+/// Run a tool call of the step model, recorded as a `StepToolCall`, and returns the content for the model.
+/// Note: A failing tool call does not fail the step, the error is sent to the model (which can recover).
+async fn run_step_tool_call(
+	aim: &AiManager,
+	cfile_db: &SlDb,
+	ctx: &LogicToolCtx<'_>,
+	step_id: Id,
+	tool_call: &ToolCall,
+) -> Result<String> {
+	let tool_call_c = StepToolCallForCreate {
+		step_id,
+		call_id: Some(tool_call.call_id.to_string()),
+		fn_name: tool_call.fn_name.to_string(),
+		fn_arguments: Some(tool_call.fn_arguments.to_string()),
+	};
+	let tool_call_id = StepToolCallBmc::create_started(cfile_db, tool_call_c).await?;
+
+	let (content, tool_call_u) = match run_tool_call(aim.logic_tools(), ctx, tool_call).await {
+		Ok(result) => {
+			let result = result.to_string();
+			let tool_call_u = StepToolCallForUpdate {
+				result: Some(result.to_string()),
+				..Default::default()
+			};
+			(result, tool_call_u)
+		}
+		Err(err) => {
+			warn!("Tool call {} of step {step_id} failed cause: {err}", tool_call.fn_name);
+			let tool_call_u = StepToolCallForUpdate {
+				err: Some(err.to_string()),
+				..Default::default()
+			};
+			(format!("Error: {err}"), tool_call_u)
+		}
+	};
+	StepToolCallBmc::update_end(cfile_db, tool_call_id, tool_call_u).await?;
+
+	Ok(content)
+}
+
+/// Same as `run_agent_model`, but streams the generation into the pending agent answer msg of the step orig msg.
+/// - Each chunk is appended to the msg content and published as `ConvEvent::ConvMsgChunk`.
+/// - On fail, the pending msg gets the error (and gets reused by the retry, see `MsgBmc::create_agent_pending`).
+/// - Note: The closer step completes the pending msg (see `MsgBmc::create_agent_answer`).
+async fn run_agent_model_stream(
+	aim: &AiManager,
+	mm: &ModelManager,
+	cfile_db: &SlDb,
+	agent: &Agent,
+	prompt: String,
+	history: Vec<ChatMsg>,
+	step: &StackStep,
+) -> Result<(ModelTarget, GenRes)> {
+	let (ai_client, target, gen_req) = prep_agent_gen(aim, agent, prompt, history).await?;
+
+	let conv_uid = MsgBmc::get_conv_uid(cfile_db, step.orig_msg_id).await?;
+	let conv = ConvBmc::get_by_uid(mm, &conv_uid).await?;
+	let msg_id = MsgBmc::create_agent_pending(cfile_db, step.orig_msg_id).await?;
+
+	// Note: A cancel landing after the run start check, but before the pending msg creation,
+	//       did not see this msg (see `ConvBmc::cancel_work`), so it gets closed as cancelled here.
+	if StackStepBmc::get(cfile_db, step.id).await?.run_tcancel.is_some() {
+		MsgBmc::set_pending_err(cfile_db, msg_id, MSG_ERR_CANCELLED).await?;
+		return Err(Error::StepRunCancelled { step_id: step.id });
+	}
+
+	let model = target.model.clone();
+	let res = async move {
+		let mut stream = ai_client.gen_stream(&model, gen_req).await?;
+		let mut response = String::new();
+		let mut usage = GenUsage::default();
+		while let Some(chunks) = stream.next().await {
+			let mut delta = String::new();
+			for chunk in chunks? {
+				delta.push_str(&chunk.response);
+				if let Some(chunk_usage) = chunk.usage {
+					usage.add(&chunk_usage);
+				}
+			}
+			if delta.is_empty() {
+				continue;
+			}
+			MsgBmc::append_content(cfile_db, msg_id, &delta).await?;
+			response.push_str(&delta);
+			mm.hub()
+				.publish(ConvEvent::ConvMsgChunk {
+					conv_id: conv.id,
+					msg_id,
+					delta,
+				})
+				.await;
+		}
+		Ok::<_, Error>(GenRes {
+			response,
+			usage,
+			..Default::default()
+		})
+	}
+	.await;
+
+	match res {
+		Ok(res) => Ok((target, res)),
+		Err(err) => {
+			MsgBmc::set_pending_err(cfile_db, msg_id, err.to_string()).await?;
+			Err(err)
+		}
+	}
+}
+
+/// Resolve the ai client and model, and build the `GenReq` for the agent.
+async fn prep_agent_gen(
+	aim: &AiManager,
+	agent: &Agent,
+	prompt: String,
+	history: Vec<ChatMsg>,
+) -> Result<(Box<dyn AiClient + Send>, ModelTarget, GenReq)> {
 	// -- Get the model
 	let model = agent.model.as_ref().ok_or_else(|| Error::AgentHasNoModel {
 		agent_id: agent.id,
 		agent_name: agent.name.to_string(),
 	})?;
 
-	// -- Get the ai client for the agent model
-	let ai_model = agent.model.as_deref().unwrap_or_default();
-	let ai_client = aim.get_client_for_model(ai_model).await?;
-
-	// -- Resolve the agent prompt
-	let prompt = render_prompt_tmpl(agent, input)?;
+	// -- Get the ai client for the agent provider/model
+	let (ai_client, target) = aim.get_client_for_model(agent.provider.as_deref(), model).await?;
 
-	// -- Exec the GenReq
+	// -- Build the GenReq
+	let mut messages = history;
+	messages.push(ChatMsg::user(prompt));
 	let gen_req = GenReq {
-		prompt,
+		messages,
 		inst: agent.inst.clone(),
 		out_format: agent.out_format.clone(),
+		tools: agent_tools(agent, aim.logic_tools())?,
 	};
-	let gen_res = ai_client.gen(model, gen_req).await?;
 
-	Ok(gen_res)
+	Ok((ai_client, target, gen_req))
 }
 
-fn render_prompt_tmpl(agent: &Agent, input: InputContent) -> Result<String> {
-	if let Some(prompt_tmpl) = agent.prompt_tmpl.x_non_empty_str() {
-		let data = match input {
-			InputContent::Text(text) => json!({"input": text}),
-			InputContent::Json(value) => json!({"input": value}),
-		};
-		let res = hbs::render(prompt_tmpl, data).map_err(Error::FailToHbsRenderPrompt)?;
-		Ok(res)
-	} else {
-		Ok(input.to_string())
+/// Render the agent prompt (see `render_prompt_tmpl`), with the retrieved space drives content
+/// as the `context` variable when the agent has a retrieval config (see `RetrievalConfig`).
+/// Note: The input is the retrieval query, and an agent without `prompt_tmpl` gets the default retrieval one.
+async fn render_agent_prompt(
+	mm: &ModelManager,
+	cfile_db: &SlDb,
+	agent: &Agent,
+	orig_msg_id: Id,
+	input: String,
+	vars: &ChainVars,
+) -> Result<String> {
+	let Some(retrieval) = RetrievalConfig::from_agent(agent)? else {
+		return render_prompt_tmpl(agent, InputContent::new(input), vars);
+	};
+
+	let space_id = get_conv_space_id(mm, cfile_db, orig_msg_id).await?;
+	let hits = retrieve_space_hits(mm, space_id, &retrieval, &input).await?;
+	let mut vars = vars.clone();
+	vars.insert(
+		RETRIEVAL_CONTEXT_VAR.to_string(),
+		serde_json::to_string(&hits).map_err(Error::RetrievalFailSerializeHits)?,
+	);
+
+	let prompt_tmpl = agent.prompt_tmpl.x_non_empty_str().unwrap_or(RETRIEVAL_DEFAULT_PROMPT_TMPL);
+	render_chain_tmpl(prompt_tmpl, InputContent::new(input), &vars).map_err(Error::FailToHbsRenderPrompt)
+}
+
+/// Render the agent prompt template with the `input` and the chain variables (see `render_chain_tmpl`).
+pub(super) fn render_prompt_tmpl(agent: &Agent, input: InputContent, vars: &ChainVars) -> Result<String> {
+	match agent.prompt_tmpl.x_non_empty_str() {
+		Some(prompt_tmpl) => render_chain_tmpl(prompt_tmpl, input, vars).map_err(Error::FailToHbsRenderPrompt),
+		None => Ok(input.to_string()),
 	}
 }
 
 // region:    --- Support
 
-async fn compute_next_stack(
+/// Returns the space of the conversation of the orig msg (e.g., for the drives of the tools).
+async fn get_conv_space_id(mm: &ModelManager, cfile_db: &SlDb, orig_msg_id: Id) -> Result<Id> {
+	let conv_uid = MsgBmc::get_conv_uid(cfile_db, orig_msg_id).await?;
+	let conv = ConvBmc::get_by_uid(mm, &conv_uid).await?;
+
+	Ok(conv.space_id)
+}
+
+/// Returns the conversation messages before the orig message, per the agent `history_window`.
+async fn get_agent_history(cfile_db: &SlDb, agent: &Agent, orig_msg_id: Id) -> Result<Vec<ChatMsg>> {
+	let window = agent.history_window.unwrap_or(0);
+	if window <= 0 {
+		return Ok(Vec::new());
+	}
+
+	let msgs = MsgBmc::list_history_before(cfile_db, orig_msg_id, window).await?;
+	let history = msgs
+		.into_iter()
+		.filter_map(|msg| {
+			let content = msg.content?;
+			match msg.author_kind? {
+				AuthorKind::User => Some(ChatMsg::user(content)),
+				AuthorKind::Agent => Some(ChatMsg::assistant(content)),
+			}
+		})
+		.collect();
+
+	Ok(history)
+}
+
+/// Create the retry step of a failed step, if the retry policy of the step agent allows it.
+/// Returns the retry step id, with the delay before it can run.
+async fn create_policy_retry(
+	mm: &ModelManager,
+	cfile_db: &SlDb,
+	step: &StackStep,
+	err: &Error,
+) -> Result<Option<(Id, Duration)>> {
+	let stack = ChainCallStack::from_json(step.call_stack.as_ref().ok_or(Error::StackStepNotFound(step.id))?)?;
+	let Some(sitem) = stack.last_item() else {
+		return Ok(None);
+	};
+	let agent = AgentBmc::get_by_uid(mm, &sitem.agent_uid).await?;
+
+	let Some(policy) = RetryPolicy::from_agent(&agent)? else {
+		return Ok(None);
+	};
+	let Some(delay) = policy.next_retry_delay(step.attempt, err.kind()) else {
+		return Ok(None);
+	};
+
+	let run_tafter = now_unix_time_us() + delay.as_micros().min(i64::MAX as u128) as i64;
+	let retry_step_id = StackStepBmc::create_retry_from_step(cfile_db, step.id, Some(run_tafter.into())).await?;
+
+	Ok(Some((retry_step_id, delay)))
+}
+
+/// Returns when a `ConvEvent::ConvWorkCancel` for this conv is received
+/// (never returns if the subscriber fails).
+async fn wait_for_cancel(conv_sub: &mut Subscriber<ConvEvent>, conv_id: Id) {
+	while let Ok(evt) = conv_sub.next().await {
+		if matches!(evt, ConvEvent::ConvWorkCancel { conv_id: cancel_conv_id } if cancel_conv_id == conv_id) {
+			return;
+		}
+	}
+	std::future::pending::<()>().await
+}
+
+/// Returns true if the generation of the stack last item is the last one for this orig msg,
+/// meaning that no stack item has a node after its cursor.
+/// Note: Structural, so when there is a branch after, it is not considered the last (even if no arm match).
+async fn is_last_generation(mm: &ModelManager, stack: &ChainCallStack) -> Result<bool> {
+	for sitem in stack.items.iter() {
+		let agent = AgentBmc::get_by_uid(mm, &sitem.agent_uid).await?;
+		if agent.get_chain()?.scope_chain(&sitem.scope)?.has_next_node(&sitem.cursor) {
+			return Ok(false);
+		}
+	}
+
+	Ok(true)
+}
+
+/// The max number of stack items walked by `compute_next_stack` (e.g., nested agent chains without any agent to run).
+/// Note: The loop nodes do not count, as their iterations are bounded in the cursor (see `LoopNode`).
+const COMPUTE_STACK_MAX_ITEMS: usize = 10;
+
+pub(super) async fn compute_next_stack(
 	mm: &ModelManager,
 	mut stack: ChainCallStack,
 	input: InputContent,
 ) -> Result<ChainCallStack> {
-	let mut loop_failsafe = 0;
-	loop {
-		// -- failsafe_count
-		loop_failsafe += 1;
-		if loop_failsafe > 10 {
-			warn!("compute_next_stack loop over 10 - stop looop");
-			break;
-		}
-
+	for _ in 0..COMPUTE_STACK_MAX_ITEMS {
 		// -- Pop the last item
 		let Some(sitem) = stack.pop_item() else {
 			return Ok(stack);
@@ -231,34 +768,68 @@ async fn compute_next_stack(
 		// -- Get the chain, base_agent, cursor
 		let agent_uid = sitem.agent_uid;
 		let cursor = sitem.cursor;
+		let mut vars = sitem.vars;
+		let scope = sitem.scope;
 		let agent = AgentBmc::get_by_uid(mm, &agent_uid).await?;
-		let chain = agent.get_chain()?;
+		let agent_chain = agent.get_chain()?;
+		let chain = agent_chain.scope_chain(&scope)?;
+
+		// -- The item input is the output of the node at cursor, or the chain input when at start
+		let chain_input = sitem.input.filter(|_| cursor.idxs.is_empty());
+		let item_input = chain_input.as_deref().map(InputContent::new).unwrap_or_else(|| input.clone());
+		if let Some(name) = chain.get_name_output(&cursor) {
+			vars.insert(name.to_string(), item_input.to_string());
+		}
 
-		let next_agent_cursor = chain.next_agent_cursor(&cursor, &input);
+		let next_agent_cursor = chain.next_agent_cursor(&cursor, &item_input);
 
 		if let Some(next_agent_cursor) = next_agent_cursor {
+			// -- A map or transform node runs as a step of this agent (see `run_stack_item`)
+			if chain.get_map_node(&next_agent_cursor).is_some()
+				|| chain.get_transform_node(&next_agent_cursor).is_some()
+			{
+				let sitem = StackItem::new(&agent_uid, next_agent_cursor)
+					.with_vars(vars)
+					.with_input(chain_input)
+					.with_scope(scope);
+				stack.push_item(sitem);
+				return Ok(stack);
+			}
+
 			let next_agent_node = chain
 				.get_agent_node(&next_agent_cursor)
 				.expect("FATAL no AGENT NODE FOR CURSOR");
 			let next_agent = resolve_agent(mm, agent.id, next_agent_node).await?;
 
+			// -- Bind the next node input
+			let node_input = next_agent_node.resolve_input(&vars)?;
+			if let Some(name) = next_agent_node.name_input.as_ref() {
+				let val = node_input.clone().unwrap_or_else(|| item_input.to_string());
+				vars.insert(name.to_string(), val);
+			}
+
 			if next_agent.id == agent.id {
-				stack.push_item(StackItem::new(&next_agent.uid, next_agent_cursor));
+				// Note: At the chain start, the chain input is the run input (not the previous output).
+				let sitem = StackItem::new(&next_agent.uid, next_agent_cursor)
+					.with_vars(vars)
+					.with_input(node_input.or(chain_input))
+					.with_scope(scope);
+				stack.push_item(sitem);
 				// we return early
 				return Ok(stack);
 			} else {
-				// we first add the nexxt agent cursor for this input
-				if let Some(next_cursor) = chain.next_agent_cursor(&cursor, &input) {
-					stack.push_item(StackItem::new(&agent_uid, next_cursor))
-				}
-				// Then, we add a new stack item for the new agent (init, empty cursor)
-				stack.push_item(StackItem::new_at_start(&next_agent.uid))
+				// we first add the next agent cursor for this input
+				stack.push_item(StackItem::new(&agent_uid, next_agent_cursor).with_vars(vars).with_scope(scope));
+				// Then, we add a new stack item for the new agent (init, empty cursor, its own vars)
+				let chain_input = node_input.unwrap_or_else(|| item_input.to_string());
+				stack.push_item(StackItem::new_at_start(&next_agent.uid).with_input(Some(chain_input)))
 			}
 		}
 	}
 
-	// TODO FIXME
-	Ok(stack)
+	Err(Error::ChainStackOverLimit {
+		limit: COMPUTE_STACK_MAX_ITEMS,
+	})
 }
 
 /// will eget the stack from prev step or build a new one from the msg orig
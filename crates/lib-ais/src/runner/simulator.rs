@@ -0,0 +1,275 @@
+//! The chain simulator, which walks an agent chain like the runner (see `compute_next_stack`),
+//! but with canned outputs per agent, and without any step, message, or real model call.
+
+use crate::chain::{map_item_to_input, AgentChain as _, ChainCallStack, ChainCursor, InputContent, StackItem};
+use crate::client::{AiClient, FcClient, FC_MODEL_MOCK_ECHO_PROMPT};
+use crate::runner::runner::{compute_next_stack, render_prompt_tmpl};
+use crate::{GenReq, Result};
+use futures::future::BoxFuture;
+use lib_core::model::agent::{Agent, AgentBmc};
+use lib_core::model::{Id, ModelManager};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{serde_as, OneOrMany};
+use std::collections::HashMap;
+
+/// The max number of steps of a simulation (the trace is then `truncated`).
+pub const SIM_STEPS_MAX: usize = 50;
+
+// region:    --- Types
+
+/// The canned outputs by agent name (or uid).
+/// - One output, or a list of outputs, one per visit of the agent (the last one repeating).
+/// - The agents without canned output are mocked through the `FcClient`
+///   (their model when a `fc-mock-...` one, otherwise the echo of the rendered prompt).
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ChainSimOutputs(#[serde_as(as = "HashMap<_, OneOrMany<_>>")] pub HashMap<String, Vec<String>>);
+
+/// The trace of a chain simulation (see `simulate_agent_chain`).
+#[derive(Debug, Serialize)]
+pub struct ChainSimTrace {
+	/// The visited nodes, in the run order (the map items nodes after their map node).
+	pub steps: Vec<ChainSimStep>,
+	/// The final output (i.e., the answer), None if truncated.
+	pub output: Option<String>,
+	/// True if the simulation stopped at `SIM_STEPS_MAX`.
+	pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainSimStep {
+	pub agent_uid: String,
+	pub agent_name: String,
+	pub kind: ChainSimStepKind,
+	/// The cursor in the chain of the stack item (see `scope`).
+	pub cursor: ChainCursor,
+	/// The map node idxs down to the sub-chain of the cursor (see `StackItem::scope`).
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub scope: Vec<Vec<usize>>,
+	/// The names of the agents of the call stack (outer first, the last one being the item agent).
+	pub stack_agents: Vec<String>,
+	/// The branch arms matched along the call stack (outer first).
+	pub arms: Vec<ChainSimArm>,
+	pub input: String,
+	pub output: String,
+	/// True if the output is a canned one (see `ChainSimOutputs`).
+	pub canned: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainSimStepKind {
+	Agent,
+	Map,
+	Transform,
+}
+
+/// A matched branch arm, in the chain of `agent_name`.
+#[derive(Debug, Serialize)]
+pub struct ChainSimArm {
+	pub agent_name: String,
+	pub branch_idxs: Vec<usize>,
+	pub arm_idx: usize,
+}
+
+// endregion: --- Types
+
+/// Simulate the chain of an agent for an `input`, and returns the trace of the visited nodes.
+/// Note: Nothing gets persisted, and no real model gets called (see `ChainSimOutputs`).
+pub async fn simulate_agent_chain(
+	mm: &ModelManager,
+	agent_id: Id,
+	input: String,
+	outputs: &ChainSimOutputs,
+) -> Result<ChainSimTrace> {
+	let agent = AgentBmc::get(mm, agent_id).await?;
+	let mut sim = Sim {
+		mm,
+		outputs,
+		fc_client: FcClient::default(),
+		visits: HashMap::new(),
+		steps: Vec::new(),
+		truncated: false,
+	};
+
+	let stack = ChainCallStack::new_at_agent(agent.uid);
+	let output = sim_stack(&mut sim, stack, input).await?;
+
+	Ok(ChainSimTrace {
+		steps: sim.steps,
+		output: (!sim.truncated).then_some(output),
+		truncated: sim.truncated,
+	})
+}
+
+// region:    --- Support
+
+struct Sim<'a> {
+	mm: &'a ModelManager,
+	outputs: &'a ChainSimOutputs,
+	fc_client: FcClient,
+	/// The number of visits by agent uid (for the canned outputs lists).
+	visits: HashMap<String, usize>,
+	steps: Vec<ChainSimStep>,
+	truncated: bool,
+}
+
+/// Walk the stack to its end (like `run_map_item`), and returns the last output.
+/// Note: Boxed, as a map sub-chain can have map nodes.
+fn sim_stack<'a>(sim: &'a mut Sim<'_>, mut stack: ChainCallStack, input: String) -> BoxFuture<'a, Result<String>> {
+	Box::pin(async move {
+		let mut output = input;
+
+		loop {
+			stack = compute_next_stack(sim.mm, stack, InputContent::new(&output)).await?;
+			let Some(sitem) = stack.last_item().cloned() else {
+				break;
+			};
+			if sim.truncated || sim.steps.len() >= SIM_STEPS_MAX {
+				sim.truncated = true;
+				break;
+			}
+
+			let agent = AgentBmc::get_by_uid(sim.mm, &sitem.agent_uid).await?;
+			let (stack_agents, arms) = sim_stack_agents_and_arms(sim.mm, &stack).await?;
+			let input = sitem.input.clone().unwrap_or(output);
+			let step = ChainSimStep {
+				agent_uid: agent.uid.to_string(),
+				agent_name: agent.name.to_string(),
+				kind: ChainSimStepKind::Agent,
+				cursor: sitem.cursor.clone(),
+				scope: sitem.scope.clone(),
+				stack_agents,
+				arms,
+				input: input.to_string(),
+				output: String::new(),
+				canned: false,
+			};
+
+			let agent_chain = agent.get_chain()?;
+			let chain = agent_chain.scope_chain(&sitem.scope)?;
+			output = if let Some(transform_node) = chain.get_transform_node(&sitem.cursor) {
+				let output = transform_node.apply(&input, &sitem.vars)?;
+				sim.steps.push(ChainSimStep {
+					kind: ChainSimStepKind::Transform,
+					output: output.to_string(),
+					..step
+				});
+				output
+			} else if chain.get_map_node(&sitem.cursor).is_some() {
+				// Note: The map step is pushed first, so the item steps come after it.
+				let step_idx = sim.steps.len();
+				sim.steps.push(ChainSimStep {
+					kind: ChainSimStepKind::Map,
+					..step
+				});
+				let output = sim_map_node(sim, &agent, &sitem, input).await?;
+				sim.steps[step_idx].output = output.to_string();
+				output
+			} else {
+				let (output, canned) = sim_agent_output(sim, &agent, &sitem, input).await?;
+				sim.steps.push(ChainSimStep {
+					output: output.to_string(),
+					canned,
+					..step
+				});
+				output
+			};
+		}
+
+		Ok(output)
+	})
+}
+
+/// Walk the map node sub-chain per item (in sequence), and returns the outputs as a JSON array.
+async fn sim_map_node(sim: &mut Sim<'_>, agent: &Agent, sitem: &StackItem, input: String) -> Result<String> {
+	let chain = agent.get_chain()?;
+	let Some(map_node) = chain.scope_chain(&sitem.scope)?.get_map_node(&sitem.cursor) else {
+		return Ok(input);
+	};
+	let items = map_node.resolve_items(&InputContent::new(input), &sitem.vars)?;
+
+	let mut scope = sitem.scope.clone();
+	scope.push(sitem.cursor.idxs.clone());
+	let mut outputs = Vec::new();
+	for item in items {
+		let item_input = map_item_to_input(item);
+		let item_sitem = StackItem::new_at_start(&sitem.agent_uid)
+			.with_vars(sitem.vars.clone())
+			.with_input(Some(item_input.to_string()))
+			.with_scope(scope.clone());
+		let stack = ChainCallStack {
+			items: vec![item_sitem],
+		};
+		let output = sim_stack(sim, stack, item_input).await?;
+		outputs.push(InputContent::new(output).into_value());
+	}
+
+	Ok(Value::Array(outputs).to_string())
+}
+
+/// Returns the agent output, canned or mocked through the `FcClient`, with true if canned.
+async fn sim_agent_output(
+	sim: &mut Sim<'_>,
+	agent: &Agent,
+	sitem: &StackItem,
+	input: String,
+) -> Result<(String, bool)> {
+	let visit = sim.visits.entry(agent.uid.to_string()).or_default();
+	*visit += 1;
+	let visit = *visit;
+
+	let canned = sim.outputs.0.get(&agent.name).or_else(|| sim.outputs.0.get(&agent.uid));
+	if let Some(output) = canned.and_then(|outputs| outputs.get(visit - 1).or(outputs.last())) {
+		return Ok((output.to_string(), true));
+	}
+
+	let fc_models = sim.fc_client.list_models().await?;
+	let model = agent
+		.model
+		.as_deref()
+		.filter(|model| fc_models.iter().any(|m| m == model))
+		.unwrap_or(FC_MODEL_MOCK_ECHO_PROMPT);
+	let prompt = render_prompt_tmpl(agent, InputContent::new(input), &sitem.vars)?;
+	let gen_req = GenReq {
+		inst: agent.inst.clone(),
+		..GenReq::from(prompt.as_str())
+	};
+	let res = sim.fc_client.gen(model, gen_req).await?;
+
+	Ok((res.response, false))
+}
+
+/// Returns the agent names of the stack items, with the branch arms matched by their cursors.
+async fn sim_stack_agents_and_arms(
+	mm: &ModelManager,
+	stack: &ChainCallStack,
+) -> Result<(Vec<String>, Vec<ChainSimArm>)> {
+	let mut names = Vec::new();
+	let mut arms = Vec::new();
+	for sitem in stack.items.iter() {
+		let agent = AgentBmc::get_by_uid(mm, &sitem.agent_uid).await?;
+		let chain = agent.get_chain()?;
+		for (branch_idxs, arm_idx) in chain.scope_chain(&sitem.scope)?.cursor_arms(&sitem.cursor) {
+			arms.push(ChainSimArm {
+				agent_name: agent.name.to_string(),
+				branch_idxs,
+				arm_idx,
+			});
+		}
+		names.push(agent.name);
+	}
+
+	Ok((names, arms))
+}
+
+// endregion: --- Support
+
+// region:    --- Tests
+
+#[cfg(test)]
+#[path = "_tests/tests_simulator.rs"]
+mod tests;
+
+// endregion: --- Tests
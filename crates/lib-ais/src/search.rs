@@ -0,0 +1,406 @@
+//! The search of the drive chunks, fanned out across the dfile dbs of a space or a drive
+//! (see `search_scope_chunks`, used by `drive_search`, the retrieval, and the drive logic tools).
+
+use crate::runner::fts_query_words_any;
+use crate::{AiManager, Error, Result};
+use lib_core::model::dfile::DFileBmc;
+use lib_core::model::dfile_db::chunk::ChunkBmc;
+use lib_core::model::dfile_db::ditem_ref::DItemRefBmc;
+use lib_core::model::ditem::{DItem, DItemBmc};
+use lib_core::model::support::prelude::SlDb;
+use lib_core::model::{Id, ModelManager};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The default max number of hits of `drive_search`.
+pub const DRIVE_SEARCH_LIMIT_DEFAULT: usize = 20;
+
+// region:    --- Types
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriveSearchMode {
+	/// The chunks of the fts hits (bm25) of the query words.
+	Fts,
+	/// The chunks most similar to the query vector (needs the `AiManager::embed_model`).
+	Similar,
+	/// The fts and similar hits fused by reciprocal rank fusion
+	/// (only the fts hits when no `AiManager::embed_model`).
+	#[default]
+	Hybrid,
+}
+
+/// The ditems searched, the ones of the space drives, or of a drive.
+/// Note: A dfile db can have the chunks of ditems of other drives, which are never returned.
+#[derive(Debug, Clone, Copy)]
+pub enum SearchScope {
+	Space(Id),
+	Drive(Id),
+}
+
+/// A chunk of a drive file matching the search.
+#[derive(Debug, Clone, Serialize)]
+pub struct DriveSearchHit {
+	pub dfile_id: Id,
+	pub ditem_id: Id,
+	pub chunk_id: Id,
+	pub path: String,
+	pub line_start: i64,
+	pub line_end: i64,
+	pub breadcrumb: String,
+	pub content: String,
+	/// The higher the better (fts: minus bm25, similar: cosine similarity, hybrid: fusion score).
+	pub score: f64,
+	/// The rank (from 1) in the fts hits (across the dfile dbs).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub fts_rank: Option<usize>,
+	/// The rank (from 1) in the similar hits (across the dfile dbs).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub vec_rank: Option<usize>,
+}
+
+/// The search of a `search_scope_chunks`.
+pub(crate) enum ChunksSearch<'a> {
+	/// The chunks of the fts hits (bm25) of the fts query (ranked by their best part).
+	Fts(&'a str),
+	/// The chunks most similar to the query vector.
+	Similar(&'a QueryVec<'a>),
+	/// The fts and similar hits fused by reciprocal rank fusion.
+	Hybrid(&'a str, &'a QueryVec<'a>),
+}
+
+pub(crate) struct QueryVec<'a> {
+	pub model: &'a str,
+	pub vec: Vec<f32>,
+}
+
+// endregion: --- Types
+
+/// Search the `query` in every dfile db of the drive, and returns the best `limit` hits across them.
+pub async fn drive_search(
+	mm: &ModelManager,
+	aim: &AiManager,
+	drive_id: Id,
+	query: &str,
+	mode: DriveSearchMode,
+	limit: usize,
+) -> Result<Vec<DriveSearchHit>> {
+	let fts_query = match mode {
+		DriveSearchMode::Similar => None,
+		DriveSearchMode::Fts | DriveSearchMode::Hybrid => fts_query_words_any(query),
+	};
+
+	// -- Embed the query (when needed and possible)
+	let query_vec = match (mode, aim.embed_model()) {
+		(DriveSearchMode::Fts, _) | (DriveSearchMode::Hybrid, None) => None,
+		(DriveSearchMode::Similar, None) => return Err(Error::DriveSearchNoEmbedModel),
+		(_, Some(model)) => {
+			let vec = aim.embed(model, vec![query.to_string()]).await?.pop().unwrap_or_default();
+			Some(QueryVec { model, vec })
+		}
+	};
+
+	let search = match (fts_query.as_deref(), query_vec.as_ref()) {
+		(None, None) => return Ok(Vec::new()),
+		(Some(fts_query), None) => ChunksSearch::Fts(fts_query),
+		(None, Some(query_vec)) => ChunksSearch::Similar(query_vec),
+		(Some(fts_query), Some(query_vec)) => ChunksSearch::Hybrid(fts_query, query_vec),
+	};
+
+	search_scope_chunks(mm, SearchScope::Drive(drive_id), search, limit).await
+}
+
+/// Run the search in every dfile db of the scope, and returns the best `limit` hits across them,
+/// with their ditem path.
+/// - The fts hits are merged by their rank in their dfile db, as the bm25 values are not comparable across dbs
+///   (the same rank hits in the dfile order).
+/// - The similar hits are merged by similarity.
+/// - The hybrid hits are the fts and similar hits (merged as above, `limit` of each) fused by reciprocal rank fusion.
+pub(crate) async fn search_scope_chunks(
+	mm: &ModelManager,
+	scope: SearchScope,
+	search: ChunksSearch<'_>,
+	limit: usize,
+) -> Result<Vec<DriveSearchHit>> {
+	let hits = match search {
+		ChunksSearch::Fts(fts_query) => search_scope_dbs(mm, scope, DbSearch::Fts(fts_query), limit).await?,
+		ChunksSearch::Similar(query_vec) => search_scope_dbs(mm, scope, DbSearch::Similar(query_vec), limit).await?,
+		ChunksSearch::Hybrid(fts_query, query_vec) => {
+			let fts_hits = search_scope_dbs(mm, scope, DbSearch::Fts(fts_query), limit).await?;
+			let similar_hits = search_scope_dbs(mm, scope, DbSearch::Similar(query_vec), limit).await?;
+			fuse_hits(fts_hits, similar_hits, limit)
+		}
+	};
+
+	Ok(hits)
+}
+
+// region:    --- Support
+
+/// The reciprocal rank fusion constant of the hybrid search (score = sum of `1 / (RRF_K + rank)`).
+const RRF_K: f64 = 60.;
+
+enum DbSearch<'a> {
+	Fts(&'a str),
+	Similar(&'a QueryVec<'a>),
+}
+
+struct DbHit {
+	chunk_id: Id,
+	ditem_ref_id: i64,
+	line_start: i64,
+	line_end: i64,
+	breadcrumb: String,
+	content: String,
+	score: f64,
+}
+
+/// Returns the best `limit` hits of the search across the dfile dbs of the scope,
+/// with their rank (from 1) across them (`fts_rank` or `vec_rank`).
+async fn search_scope_dbs(
+	mm: &ModelManager,
+	scope: SearchScope,
+	search: DbSearch<'_>,
+	limit: usize,
+) -> Result<Vec<DriveSearchHit>> {
+	let (ditems, dfiles) = match scope {
+		SearchScope::Space(space_id) => (
+			DItemBmc::list_for_space(mm, space_id).await?,
+			DFileBmc::list_for_space(mm, space_id).await?,
+		),
+		SearchScope::Drive(drive_id) => (
+			DItemBmc::list_for_drive(mm, drive_id).await?,
+			DFileBmc::list_for_drive(mm, drive_id).await?,
+		),
+	};
+	let ditems_by_uid: HashMap<&str, &DItem> = ditems.iter().map(|ditem| (ditem.uid.as_str(), ditem)).collect();
+
+	// -- Search each dfile db (the hits with their rank in their db)
+	let mut hits: Vec<(usize, DriveSearchHit)> = Vec::new();
+	for dfile in dfiles {
+		let dfile_db = DFileBmc::get_dfile_db(mm, &dfile).await?;
+		let db_hits = search_dfile_db(&dfile_db, &search, limit).await?;
+
+		let mut ditems_by_ref_id: HashMap<i64, Option<&DItem>> = HashMap::new();
+		for (db_rank, db_hit) in db_hits.into_iter().enumerate() {
+			let ditem = match ditems_by_ref_id.get(&db_hit.ditem_ref_id) {
+				Some(ditem) => *ditem,
+				None => {
+					let ditem_ref = DItemRefBmc::get(&dfile_db, db_hit.ditem_ref_id.into()).await?;
+					let ditem = ditems_by_uid.get(ditem_ref.ditem_uid.as_str()).copied();
+					ditems_by_ref_id.insert(db_hit.ditem_ref_id, ditem);
+					ditem
+				}
+			};
+			if let Some(ditem) = ditem {
+				let hit = DriveSearchHit {
+					dfile_id: dfile.id,
+					ditem_id: ditem.id,
+					chunk_id: db_hit.chunk_id,
+					path: ditem.file_path.to_string(),
+					line_start: db_hit.line_start,
+					line_end: db_hit.line_end,
+					breadcrumb: db_hit.breadcrumb,
+					content: db_hit.content,
+					score: db_hit.score,
+					fts_rank: None,
+					vec_rank: None,
+				};
+				hits.push((db_rank, hit));
+			}
+		}
+	}
+
+	// -- Merge across the dfile dbs
+	// Note: Stable sorts, so the equal hits stay in the dfile order.
+	match search {
+		DbSearch::Fts(_) => hits.sort_by_key(|(db_rank, _)| *db_rank),
+		DbSearch::Similar(_) => hits.sort_by(|(_, a), (_, b)| b.score.total_cmp(&a.score)),
+	}
+	hits.truncate(limit);
+
+	let hits = hits
+		.into_iter()
+		.enumerate()
+		.map(|(idx, (_, mut hit))| {
+			match search {
+				DbSearch::Fts(_) => hit.fts_rank = Some(idx + 1),
+				DbSearch::Similar(_) => hit.vec_rank = Some(idx + 1),
+			}
+			hit
+		})
+		.collect();
+
+	Ok(hits)
+}
+
+/// Returns the best `limit` hits by reciprocal rank fusion of the fts and similar hits ranks.
+fn fuse_hits(fts_hits: Vec<DriveSearchHit>, similar_hits: Vec<DriveSearchHit>, limit: usize) -> Vec<DriveSearchHit> {
+	let mut hits: HashMap<(i64, i64), DriveSearchHit> = HashMap::new();
+	for hit in fts_hits.into_iter().chain(similar_hits) {
+		let (fts_rank, vec_rank) = (hit.fts_rank, hit.vec_rank);
+		let rank = fts_rank.or(vec_rank).unwrap_or_default();
+		let fused_hit = hits
+			.entry((*hit.dfile_id, *hit.chunk_id))
+			.or_insert(DriveSearchHit { score: 0., ..hit });
+		fused_hit.fts_rank = fused_hit.fts_rank.or(fts_rank);
+		fused_hit.vec_rank = fused_hit.vec_rank.or(vec_rank);
+		fused_hit.score += rrf_score(rank);
+	}
+
+	let mut hits: Vec<DriveSearchHit> = hits.into_values().collect();
+	hits.sort_by(|a, b| {
+		b.score
+			.total_cmp(&a.score)
+			.then(a.dfile_id.cmp(&b.dfile_id))
+			.then(a.chunk_id.cmp(&b.chunk_id))
+	});
+	hits.truncate(limit);
+
+	hits
+}
+
+fn rrf_score(rank: usize) -> f64 {
+	1. / (RRF_K + rank as f64)
+}
+
+/// Returns the best `limit` hits of the search in a dfile db, best first.
+async fn search_dfile_db(db: &SlDb, search: &DbSearch<'_>, limit: usize) -> Result<Vec<DbHit>> {
+	let hits = match search {
+		DbSearch::Fts(fts_query) => {
+			let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+			ChunkBmc::content_search_ranked(db, fts_query, limit)
+				.await?
+				.into_iter()
+				.map(|hit| DbHit {
+					chunk_id: hit.id,
+					ditem_ref_id: hit.ditem_ref_id,
+					line_start: hit.line_start,
+					line_end: hit.line_end,
+					breadcrumb: hit.breadcrumb,
+					content: hit.content,
+					score: -hit.rank,
+				})
+				.collect()
+		}
+
+		DbSearch::Similar(query_vec) => ChunkBmc::similar_search(db, query_vec.model, &query_vec.vec, limit)
+			.await?
+			.into_iter()
+			.map(|hit| DbHit {
+				chunk_id: hit.id,
+				ditem_ref_id: hit.ditem_ref_id,
+				line_start: hit.line_start,
+				line_end: hit.line_end,
+				breadcrumb: hit.breadcrumb,
+				content: hit.content,
+				score: hit.similarity,
+			})
+			.collect(),
+	};
+
+	Ok(hits)
+}
+
+// endregion: --- Support
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use lib_core::_test_support::{seed_ditem_parts, seed_drive, seed_dsource};
+	use lib_core::model::dfile_db::part_vec::{PartVecBmc, PartVecForCreate};
+
+	const FX_EMBED_MODEL: &str = "fc::fc-mock-embed";
+
+	/// Seed a drive with two dsources (so, two dfile dbs), with the chunk vectors of the mock embed model.
+	async fn seed_drive_chunks(mm: &ModelManager, aim: &AiManager) -> Result<Id> {
+		let drive_id = seed_drive(mm, "Drive Search").await?;
+		let dsource_a_id = seed_dsource(mm, drive_id, "../../test-data").await?;
+		seed_ditem_parts(
+			mm,
+			dsource_a_id,
+			"/a/sky.md",
+			&["The sky is blue today", "Grass is green"],
+		)
+		.await?;
+		let dsource_b_id = seed_dsource(mm, drive_id, "../../test-data/sub").await?;
+		seed_ditem_parts(mm, dsource_b_id, "/b/sea.md", &["The sea is blue", "Sand is warm"]).await?;
+
+		for dfile in DFileBmc::list_for_drive(mm, drive_id).await? {
+			let dfile_db = DFileBmc::get_dfile_db(mm, &dfile).await?;
+			let chunks = PartVecBmc::list_chunks_without_vec(&dfile_db, FX_EMBED_MODEL, 100).await?;
+			let inputs = chunks.iter().map(|chunk| chunk.embed_input()).collect();
+			let vecs = aim.embed(FX_EMBED_MODEL, inputs).await?;
+			for (chunk, vec) in chunks.iter().zip(vecs) {
+				PartVecBmc::create(&dfile_db, PartVecForCreate::new(*chunk.id, FX_EMBED_MODEL, &vec)).await?;
+			}
+		}
+
+		Ok(drive_id)
+	}
+
+	#[tokio::test]
+	async fn test_search_drive_modes() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let aim = AiManager::default().with_embed_model(FX_EMBED_MODEL);
+		let drive_id = seed_drive_chunks(&mm, &aim).await?;
+
+		// -- Exec
+		let fts_hits = drive_search(&mm, &aim, drive_id, "blue", DriveSearchMode::Fts, 10).await?;
+		let similar_hits = drive_search(&mm, &aim, drive_id, "the sea is blue", DriveSearchMode::Similar, 1).await?;
+		let hybrid_hits = drive_search(&mm, &aim, drive_id, "blue sky", DriveSearchMode::Hybrid, 10).await?;
+
+		// -- Check
+		// fts, across the two dfile dbs
+		let mut paths: Vec<&str> = fts_hits.iter().map(|hit| hit.path.as_str()).collect();
+		paths.sort();
+		assert_eq!(paths, ["/a/sky.md", "/b/sea.md"]);
+		let fts_ranks: Vec<Option<usize>> = fts_hits.iter().map(|hit| hit.fts_rank).collect();
+		assert_eq!(fts_ranks, [Some(1), Some(2)]);
+		assert!(fts_hits.iter().all(|hit| hit.vec_rank.is_none()));
+
+		// similar
+		assert_eq!(similar_hits.len(), 1);
+		assert_eq!(similar_hits[0].content, "The sea is blue");
+		assert!(
+			similar_hits[0].score > 0.99,
+			"Should be same vector, but was {}",
+			similar_hits[0].score
+		);
+
+		// hybrid, the sky chunk is first in both the fts and similar hits (across the dfile dbs)
+		let first = hybrid_hits.first().ok_or("Should have hybrid hits")?;
+		assert_eq!(first.content, "The sky is blue today");
+		assert_eq!(first.fts_rank, Some(1));
+		assert_eq!(first.vec_rank, Some(1));
+		assert_eq!(hybrid_hits.len(), 4);
+		assert!(hybrid_hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_search_drive_similar_no_embed_model() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let drive_id = seed_drive(&mm, "Drive Search No Embed").await?;
+		// Note: No `with_embed_model`, so no embed model.
+		let aim = AiManager::default();
+
+		// -- Exec
+		let res = drive_search(&mm, &aim, drive_id, "blue", DriveSearchMode::Similar, 10).await;
+
+		// -- Check
+		assert!(matches!(res, Err(crate::Error::DriveSearchNoEmbedModel)));
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,45 @@
+//! The tools the agent models can call (see `Agent.tools`), which are the logic tools of the registry.
+
+use crate::{Error, LogicToolCtx, LogicToolRegistry, Result, Tool, ToolCall};
+use lib_core::model::agent::Agent;
+use lib_utils::x_string::XStr as _;
+use serde_json::Value;
+
+/// Returns the tools declared by the agent (the `Agent.tools` JSON array of tool names).
+pub fn agent_tools(agent: &Agent, registry: &LogicToolRegistry) -> Result<Vec<Tool>> {
+	let Some(tools_json) = agent.tools.x_non_empty_str() else {
+		return Ok(Vec::new());
+	};
+	let names: Vec<String> = serde_json::from_str(tools_json).map_err(|err| Error::AgentToolsInvalid {
+		agent_id: agent.id,
+		agent_name: agent.name.to_string(),
+		cause: err.to_string(),
+	})?;
+
+	names
+		.iter()
+		.map(|name| {
+			let tool = registry
+				.get(name)
+				.ok_or_else(|| Error::LogicToolNotFound { name: name.to_string() })?;
+			Ok(Tool {
+				fn_name: tool.name().to_string(),
+				fn_description: tool.description().to_string(),
+				params: tool.params_schema(),
+			})
+		})
+		.collect()
+}
+
+/// Run the tool call of a model with the registry tool, and returns the tool JSON result.
+pub async fn run_tool_call(
+	registry: &LogicToolRegistry,
+	ctx: &LogicToolCtx<'_>,
+	tool_call: &ToolCall,
+) -> Result<Value> {
+	let tool = registry.get(&tool_call.fn_name).ok_or_else(|| Error::LogicToolNotFound {
+		name: tool_call.fn_name.to_string(),
+	})?;
+
+	tool.run(ctx, tool_call.fn_arguments.clone()).await
+}
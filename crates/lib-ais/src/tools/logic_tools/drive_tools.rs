@@ -0,0 +1,418 @@
+//! The logic tools on the files of the space drives (the ditems of their dsources, with their dfile parts).
+
+use crate::search::{search_scope_chunks, ChunksSearch, SearchScope};
+use crate::tools::logic_tools::{LogicTool, LogicToolCtx};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use lib_core::model::dfile::DFileBmc;
+use lib_core::model::dfile_db::ditem_ref::{DItemRefBmc, DItemRefFilter};
+use lib_core::model::dfile_db::part::{Part, PartBmc};
+use lib_core::model::ditem::{DItem, DItemBmc};
+use lib_core::model::ModelManager;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// The default max number of hits returned by `drive_search`.
+const SEARCH_DEFAULT_LIMIT: usize = 20;
+
+/// The max number of chars of the `summarize_files` excerpts.
+const SUMMARY_EXCERPT_MAX_CHARS: usize = 300;
+
+// region:    --- Params & Results
+
+/// The `list_files` and `summarize_files` params, e.g., `{"name_contain": "report", "topics": ["finance"]}`
+#[derive(Debug, Default, Deserialize)]
+pub struct FilesParams {
+	/// Only the files with a name containing this text (case insensitive).
+	pub name_contain: Option<String>,
+	/// Only the files with some content matching any of these topics (all files if empty).
+	#[serde(default)]
+	pub topics: Vec<String>,
+}
+
+/// The `drive_search` params, e.g., `{"query": "sky color", "limit": 10}` (or the query as text input).
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchParams {
+	pub query: String,
+	pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileInfo {
+	pub path: String,
+	pub name: String,
+	pub size: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileSummary {
+	pub path: String,
+	pub name: String,
+	pub titles: Vec<String>,
+	/// The beginning of the file content (without the titles).
+	pub excerpt: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+	pub path: String,
+	pub line_start: i64,
+	pub line_end: i64,
+	pub content: String,
+}
+
+// endregion: --- Params & Results
+
+// region:    --- Tools
+
+pub struct ListFilesTool;
+
+#[async_trait]
+impl LogicTool for ListFilesTool {
+	fn name(&self) -> &'static str {
+		"list_files"
+	}
+
+	fn description(&self) -> &'static str {
+		"List the files of the space drives (path, name, size), optionally filtered by name and topics."
+	}
+
+	fn params_schema(&self) -> Value {
+		files_params_schema()
+	}
+
+	async fn run(&self, ctx: &LogicToolCtx<'_>, params: Value) -> Result<Value> {
+		let params: FilesParams = parse_params(self.name(), params)?;
+
+		let ditems = list_space_files(ctx, &params).await?;
+		let files: Vec<FileInfo> = ditems
+			.into_iter()
+			.map(|ditem| FileInfo {
+				name: file_name(&ditem).to_string(),
+				size: ditem.file_size,
+				path: ditem.file_path,
+			})
+			.collect();
+
+		to_result_value(files)
+	}
+}
+
+pub struct SummarizeFilesTool;
+
+#[async_trait]
+impl LogicTool for SummarizeFilesTool {
+	fn name(&self) -> &'static str {
+		"summarize_files"
+	}
+
+	fn description(&self) -> &'static str {
+		"Summarize the files of the space drives (titles and beginning), optionally filtered by name and topics."
+	}
+
+	fn params_schema(&self) -> Value {
+		files_params_schema()
+	}
+
+	async fn run(&self, ctx: &LogicToolCtx<'_>, params: Value) -> Result<Value> {
+		let params: FilesParams = parse_params(self.name(), params)?;
+
+		let ditems = list_space_files(ctx, &params).await?;
+		let mut summaries = Vec::new();
+		for ditem in ditems {
+			let parts = list_ditem_parts(ctx.mm, &ditem).await?;
+			let (titles, contents): (Vec<Part>, Vec<Part>) = parts.into_iter().partition(|part| part.is_title);
+			let excerpt: String = contents
+				.iter()
+				.map(|part| part.content.trim())
+				.collect::<Vec<_>>()
+				.join("\n")
+				.chars()
+				.take(SUMMARY_EXCERPT_MAX_CHARS)
+				.collect();
+
+			summaries.push(FileSummary {
+				name: file_name(&ditem).to_string(),
+				path: ditem.file_path,
+				titles: titles.into_iter().map(|part| part.content.trim().to_string()).collect(),
+				excerpt,
+			});
+		}
+
+		to_result_value(summaries)
+	}
+}
+
+pub struct DriveSearchTool;
+
+#[async_trait]
+impl LogicTool for DriveSearchTool {
+	fn name(&self) -> &'static str {
+		"drive_search"
+	}
+
+	fn description(&self) -> &'static str {
+		"Search the content of the files of the space drives, and returns the matching sections (with their lines)."
+	}
+
+	fn params_schema(&self) -> Value {
+		json!({
+			"type": "object",
+			"properties": {
+				"query": { "type": "string", "description": "The words to search (all must match)" },
+				"limit": { "type": "integer", "description": "The max number of sections (default 20)" }
+			},
+			"required": ["query"]
+		})
+	}
+
+	async fn run(&self, ctx: &LogicToolCtx<'_>, params: Value) -> Result<Value> {
+		let params: SearchParams = match params {
+			Value::String(query) => SearchParams { query, limit: None },
+			params => parse_params(self.name(), params)?,
+		};
+
+		let Some(fts_query) = fts_all_words_query(&params.query) else {
+			return to_result_value(Vec::<SearchHit>::new());
+		};
+		let limit = params.limit.unwrap_or(SEARCH_DEFAULT_LIMIT);
+		let scope = SearchScope::Space(ctx.space_id);
+		let mut hits: Vec<SearchHit> = search_scope_chunks(ctx.mm, scope, ChunksSearch::Fts(&fts_query), limit)
+			.await?
+			.into_iter()
+			.map(|hit| SearchHit {
+				path: hit.path,
+				line_start: hit.line_start,
+				line_end: hit.line_end,
+				content: hit.content,
+			})
+			.collect();
+
+		// Note: The best hits are kept by rank, and returned in the file and line order.
+		hits.sort_by(|a, b| a.path.cmp(&b.path).then(a.line_start.cmp(&b.line_start)));
+
+		to_result_value(hits)
+	}
+}
+
+// endregion: --- Tools
+
+// region:    --- Support
+
+fn files_params_schema() -> Value {
+	json!({
+		"type": "object",
+		"properties": {
+			"name_contain": { "type": "string", "description": "Only the files with a name containing this text" },
+			"topics": {
+				"type": "array",
+				"items": { "type": "string" },
+				"description": "Only the files with some content matching any of these topics"
+			}
+		}
+	})
+}
+
+/// Returns the space files for the `name_contain` and `topics` params.
+async fn list_space_files(ctx: &LogicToolCtx<'_>, params: &FilesParams) -> Result<Vec<DItem>> {
+	let mut ditems = DItemBmc::list_for_space(ctx.mm, ctx.space_id).await?;
+
+	if let Some(name_contain) = params.name_contain.as_deref().map(str::to_lowercase).filter(|n| !n.is_empty()) {
+		ditems.retain(|ditem| file_name(ditem).to_lowercase().contains(&name_contain));
+	}
+
+	if let Some(fts_query) = fts_any_term_query(&params.topics) {
+		// Note: All the hits are needed to filter the files.
+		let scope = SearchScope::Space(ctx.space_id);
+		let hit_ids: HashSet<i64> = search_scope_chunks(ctx.mm, scope, ChunksSearch::Fts(&fts_query), usize::MAX)
+			.await?
+			.into_iter()
+			.map(|hit| *hit.ditem_id)
+			.collect();
+		ditems.retain(|ditem| hit_ids.contains(&*ditem.id));
+	}
+
+	Ok(ditems)
+}
+
+/// Returns the parts of a ditem, in the line order (empty if not processed yet).
+async fn list_ditem_parts(mm: &ModelManager, ditem: &DItem) -> Result<Vec<Part>> {
+	let Some(dfile_id) = ditem.dfile_id else {
+		return Ok(Vec::new());
+	};
+	let dfile = DFileBmc::get(mm, dfile_id).await?;
+	let dfile_db = DFileBmc::get_dfile_db(mm, &dfile).await?;
+
+	let filter = DItemRefFilter {
+		ditem_uid: Some(ditem.uid.as_str().into()),
+		..Default::default()
+	};
+	let Some(ditem_ref) = DItemRefBmc::first(&dfile_db, Some(vec![filter]), None).await? else {
+		return Ok(Vec::new());
+	};
+
+	Ok(PartBmc::list_for_ditem_ref(&dfile_db, *ditem_ref.id).await?)
+}
+
+fn file_name(ditem: &DItem) -> &str {
+	ditem.file_path.rsplit(['/', '\\']).next().unwrap_or(&ditem.file_path)
+}
+
+/// Returns the fts query matching any of the terms (None if no terms).
+pub(crate) fn fts_any_term_query(terms: &[String]) -> Option<String> {
+	let terms: Vec<String> = terms.iter().filter_map(|t| fts_quote(t)).collect();
+	(!terms.is_empty()).then(|| terms.join(" OR "))
+}
+
+/// Returns the fts query matching all the words of the text (None if no words).
+fn fts_all_words_query(text: &str) -> Option<String> {
+	let words: Vec<String> = text.split_whitespace().filter_map(fts_quote).collect();
+	(!words.is_empty()).then(|| words.join(" "))
+}
+
+/// Quote the term as a fts string (so that the fts syntax chars are not interpreted).
+fn fts_quote(term: &str) -> Option<String> {
+	let term = term.trim();
+	(!term.is_empty()).then(|| format!("\"{}\"", term.replace('"', "\"\"")))
+}
+
+/// Parse the params object (or default params, when the input is not an object, e.g., some text).
+fn parse_params<T: DeserializeOwned + Default>(tool: &str, params: Value) -> Result<T> {
+	match params {
+		Value::Object(_) => serde_json::from_value(params).map_err(|err| Error::LogicToolParamsInvalid {
+			tool: tool.to_string(),
+			cause: err.to_string(),
+		}),
+		_ => Ok(T::default()),
+	}
+}
+
+fn to_result_value(res: impl Serialize) -> Result<Value> {
+	serde_json::to_value(res).map_err(Error::LogicToolFailSerializeResult)
+}
+
+// endregion: --- Support
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use lib_core::_test_support::{seed_ditem_parts, seed_drive, seed_dsource, seed_space};
+	use lib_core::model::space::SpaceBmc;
+	use serde_json::json;
+
+	/// Returns the space id and the dsource id of its files.
+	async fn seed_space_files(mm: &ModelManager) -> Result<(lib_core::model::Id, lib_core::model::Id)> {
+		let space_id = seed_space(mm, "Space Tools").await?;
+		let drive_id = seed_drive(mm, "Drive Tools").await?;
+		SpaceBmc::attach_drive(mm, space_id, drive_id, true).await?;
+		let dsource_id = seed_dsource(mm, drive_id, "../../test-data").await?;
+		seed_ditem_parts(
+			mm,
+			dsource_id,
+			"/docs/sky-colors.md",
+			&["# Sky Colors", "The sky is blue because of the Rayleigh scattering."],
+		)
+		.await?;
+		seed_ditem_parts(
+			mm,
+			dsource_id,
+			"/docs/time-dilation.md",
+			&["# Time Dilation", "Time runs slower near a massive object."],
+		)
+		.await?;
+		Ok((space_id, dsource_id))
+	}
+
+	#[tokio::test]
+	async fn test_logic_tools_drive_files() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let (space_id, _) = seed_space_files(&mm).await?;
+		let ctx = LogicToolCtx { mm: &mm, space_id };
+
+		// -- Exec
+		let all_files = ListFilesTool.run(&ctx, json!("List the files")).await?;
+		let topic_files = ListFilesTool.run(&ctx, json!({"topics": ["rayleigh", "unknown"]})).await?;
+		let name_files = ListFilesTool.run(&ctx, json!({"name_contain": "TIME"})).await?;
+		let summaries = SummarizeFilesTool.run(&ctx, json!({"name_contain": "sky"})).await?;
+
+		// -- Check
+		let paths = |files: &Value| -> Vec<String> {
+			files
+				.as_array()
+				.map(|files| files.iter().filter_map(|f| f["path"].as_str()).map(String::from).collect())
+				.unwrap_or_default()
+		};
+		assert_eq!(paths(&all_files), ["/docs/sky-colors.md", "/docs/time-dilation.md"]);
+		assert_eq!(paths(&topic_files), ["/docs/sky-colors.md"]);
+		assert_eq!(paths(&name_files), ["/docs/time-dilation.md"]);
+		assert_eq!(summaries[0]["name"], "sky-colors.md");
+		assert_eq!(summaries[0]["titles"], json!(["# Sky Colors"]));
+		assert_eq!(
+			summaries[0]["excerpt"],
+			"The sky is blue because of the Rayleigh scattering."
+		);
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_logic_tools_drive_search() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let (space_id, _) = seed_space_files(&mm).await?;
+		let ctx = LogicToolCtx { mm: &mm, space_id };
+
+		// -- Exec
+		let hits = DriveSearchTool.run(&ctx, json!("slower object")).await?;
+		let no_hits = DriveSearchTool.run(&ctx, json!({"query": "slower \"sky"})).await?;
+
+		// -- Check
+		assert_eq!(
+			hits,
+			json!([{
+				"path": "/docs/time-dilation.md",
+				"line_start": 2,
+				"line_end": 2,
+				"content": "Time runs slower near a massive object."
+			}])
+		);
+		assert_eq!(no_hits, json!([]));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_logic_tools_drive_search_limit_ranked() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let (space_id, dsource_id) = seed_space_files(&mm).await?;
+		// Note: First in the path order, but a weaker match (longer line).
+		seed_ditem_parts(
+			&mm,
+			dsource_id,
+			"/docs/a-notes.md",
+			&["Some long notes about stars, planets, moons, comets, and one massive black hole far away."],
+		)
+		.await?;
+		let ctx = LogicToolCtx { mm: &mm, space_id };
+
+		// -- Exec
+		let hits = DriveSearchTool.run(&ctx, json!({"query": "massive", "limit": 1})).await?;
+
+		// -- Check
+		assert_eq!(hits.as_array().map(Vec::len), Some(1));
+		assert_eq!(hits[0]["path"], "/docs/time-dilation.md");
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
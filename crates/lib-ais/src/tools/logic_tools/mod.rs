@@ -0,0 +1,74 @@
+//! The built-in tools run by the `AgentKind::Logic` agents (see `Agent.logic_tool`),
+//! in place of an AI model generation.
+
+// region:    --- Modules
+
+mod drive_tools;
+
+pub use drive_tools::*;
+
+// endregion: --- Modules
+
+use crate::Result;
+use async_trait::async_trait;
+use lib_core::model::{Id, ModelManager};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The context of a logic tool run.
+pub struct LogicToolCtx<'a> {
+	pub mm: &'a ModelManager,
+	/// The space of the conversation the tool runs for (e.g., to scope the drives).
+	pub space_id: Id,
+}
+
+#[async_trait]
+pub trait LogicTool: Send + Sync {
+	/// The tool name, as set in the agent `logic_tool` (e.g., `list_files`).
+	fn name(&self) -> &'static str;
+
+	/// The tool description, for the agent models calling the tool (see `Agent.tools`).
+	fn description(&self) -> &'static str;
+
+	/// The JSON schema of the params, for the agent models calling the tool.
+	fn params_schema(&self) -> Value;
+
+	/// Run the tool with the node input as params (a JSON string value for a text input),
+	/// and returns the JSON result (which becomes the node output).
+	async fn run(&self, ctx: &LogicToolCtx<'_>, params: Value) -> Result<Value>;
+}
+
+/// The logic tools by name, with the built-in ones by default.
+#[derive(Clone)]
+pub struct LogicToolRegistry {
+	tools: HashMap<&'static str, Arc<dyn LogicTool>>,
+}
+
+impl Default for LogicToolRegistry {
+	fn default() -> Self {
+		let mut registry = Self { tools: HashMap::new() };
+		registry.register(ListFilesTool);
+		registry.register(SummarizeFilesTool);
+		registry.register(DriveSearchTool);
+		registry
+	}
+}
+
+impl LogicToolRegistry {
+	/// Register a tool (replacing the eventual tool with the same name).
+	pub fn register(&mut self, tool: impl LogicTool + 'static) {
+		self.tools.insert(tool.name(), Arc::new(tool));
+	}
+
+	pub fn get(&self, name: &str) -> Option<&dyn LogicTool> {
+		self.tools.get(name).map(|tool| tool.as_ref())
+	}
+
+	/// Returns the registered tool names (sorted).
+	pub fn names(&self) -> Vec<&'static str> {
+		let mut names: Vec<&'static str> = self.tools.keys().copied().collect();
+		names.sort();
+		names
+	}
+}
@@ -1,5 +1,9 @@
 // region:    --- Modules
 
 mod ai_tools;
+mod logic_tools;
+
+pub use ai_tools::*;
+pub use logic_tools::*;
 
 // endregion: --- Modules
@@ -1,4 +1,4 @@
-use crate::Result;
+use crate::{Result, Tool, ToolCall};
 use futures::Stream;
 use lib_core::model::agent::OutFormat;
 use lib_core::model::conv::ConvMsg;
@@ -8,19 +8,35 @@ use std::pin::Pin;
 // region:    --- GenReq
 
 #[cfg_attr(feature = "for-ts", derive(schemars::JsonSchema))]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct GenReq {
-	pub prompt: String,
+	/// The ordered conversation messages (the last one being the one to answer).
+	pub messages: Vec<ChatMsg>,
 	pub inst: Option<String>,
 	pub out_format: Option<OutFormat>,
+	/// The tools the model can call (see `GenRes.tool_calls`).
+	#[serde(default)]
+	pub tools: Vec<Tool>,
+}
+
+impl GenReq {
+	/// Returns the content of the last `User` message, if any.
+	pub fn last_user_content(&self) -> Option<&str> {
+		self.messages
+			.iter()
+			.rev()
+			.find(|m| matches!(m.role, ChatRole::User))
+			.map(|m| m.content.as_str())
+	}
 }
 
 impl From<&str> for GenReq {
 	fn from(val: &str) -> Self {
 		Self {
-			prompt: val.to_string(),
+			messages: vec![ChatMsg::user(val)],
 			inst: None,
 			out_format: None,
+			tools: Vec::new(),
 		}
 	}
 }
@@ -28,9 +44,10 @@ impl From<&str> for GenReq {
 impl From<String> for GenReq {
 	fn from(val: String) -> Self {
 		Self {
-			prompt: val,
+			messages: vec![ChatMsg::user(val)],
 			inst: None,
 			out_format: None,
+			tools: Vec::new(),
 		}
 	}
 }
@@ -38,20 +55,117 @@ impl From<String> for GenReq {
 impl From<&String> for GenReq {
 	fn from(val: &String) -> Self {
 		Self {
-			prompt: val.to_string(),
+			messages: vec![ChatMsg::user(val)],
 			inst: None,
 			out_format: None,
+			tools: Vec::new(),
 		}
 	}
 }
 
 // endregion: --- GenReq
 
-// region:    --- GenRes
+// region:    --- ChatMsg
+
+/// The role of a `ChatMsg`.
+/// Note: The system instruction is not a message, but the `GenReq.inst`.
+#[cfg_attr(feature = "for-ts", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatRole {
+	User,
+	Assistant,
+	/// The result of a tool call (see `ChatMsg.tool_call_id`).
+	Tool,
+}
 
+#[cfg_attr(feature = "for-ts", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMsg {
+	pub role: ChatRole,
+	pub content: String,
+
+	/// The tool calls of an `Assistant` message.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub tool_calls: Vec<ToolCall>,
+	/// The call id of a `Tool` message.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tool_call_id: Option<String>,
+}
+
+impl ChatMsg {
+	pub fn user(content: impl Into<String>) -> Self {
+		Self {
+			role: ChatRole::User,
+			content: content.into(),
+			tool_calls: Vec::new(),
+			tool_call_id: None,
+		}
+	}
+
+	pub fn assistant(content: impl Into<String>) -> Self {
+		Self {
+			role: ChatRole::Assistant,
+			content: content.into(),
+			tool_calls: Vec::new(),
+			tool_call_id: None,
+		}
+	}
+
+	/// The assistant message with the tool calls of a `GenRes` (sent back with the tool results).
+	pub fn assistant_tool_calls(content: impl Into<String>, tool_calls: Vec<ToolCall>) -> Self {
+		Self {
+			tool_calls,
+			..Self::assistant(content)
+		}
+	}
+
+	/// The result of the tool call `call_id` (e.g., the tool JSON result, or the error).
+	pub fn tool_result(call_id: impl Into<String>, content: impl Into<String>) -> Self {
+		Self {
+			role: ChatRole::Tool,
+			content: content.into(),
+			tool_calls: Vec::new(),
+			tool_call_id: Some(call_id.into()),
+		}
+	}
+}
+
+// endregion: --- ChatMsg
+
+// region:    --- GenRes
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GenRes {
 	pub response: String,
+
+	/// The tool calls requested by the model (empty when the response is the final answer).
+	#[serde(default)]
+	pub tool_calls: Vec<ToolCall>,
+
+	/// The token usage, as reported by the provider.
+	#[serde(default)]
+	pub usage: GenUsage,
+}
+
+/// Token counts as reported by the provider (None when not reported).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenUsage {
+	pub prompt_tokens: Option<i64>,
+	pub completion_tokens: Option<i64>,
+}
+
+impl GenUsage {
+	/// Adds the counts of another usage (a count stays None if reported by neither).
+	pub fn add(&mut self, other: &GenUsage) {
+		fn add_count(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+			match (a, b) {
+				(None, None) => None,
+				(a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+			}
+		}
+		self.prompt_tokens = add_count(self.prompt_tokens, other.prompt_tokens);
+		self.completion_tokens = add_count(self.completion_tokens, other.completion_tokens);
+	}
 }
 
 impl GenRes {
@@ -64,12 +178,15 @@ impl GenRes {
 
 // region:    --- GenResStream
 
-pub type GenResStream = Pin<Box<dyn Stream<Item = Result<GenResChunks>>>>;
+pub type GenResStream = Pin<Box<dyn Stream<Item = Result<GenResChunks>> + Send>>;
 
 pub type GenResChunks = Vec<GenResChunk>;
 
+#[derive(Debug, Clone, Default)]
 pub struct GenResChunk {
 	pub response: String,
+	/// The token usage, on the chunks reporting it (to be summed, see `GenUsage::add`).
+	pub usage: Option<GenUsage>,
 }
 
 // endregion: --- GenResStream
@@ -1,8 +1,22 @@
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[allow(unused)] // For early development.
+/// A tool the model can call (sent with the `GenReq`).
+#[cfg_attr(feature = "for-ts", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
-	fn_name: String,
-	fn_description: String,
-	params: Value,
+	pub fn_name: String,
+	pub fn_description: String,
+	/// The JSON schema of the arguments (e.g., `{"type": "object", "properties": {...}}`).
+	pub params: Value,
+}
+
+/// A tool call of the model (in the `GenRes`), mapped from the provider response.
+#[cfg_attr(feature = "for-ts", derive(schemars::JsonSchema))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+	/// The provider call id, to send back with the tool result (generated when the provider has none).
+	pub call_id: String,
+	pub fn_name: String,
+	pub fn_arguments: Value,
 }
@@ -1,3 +1,9 @@
+use crate::model::dfile::DFileBmc;
+use crate::model::dfile_db::chunk::{ChunkBmc, ChunkForCreate};
+use crate::model::dfile_db::ditem_ref::DItemRefBmc;
+use crate::model::dfile_db::part::{PartBmc, PartForCreate};
+use crate::model::ditem::{DItemBmc, DItemForCreate, DItemForUpdate};
+use crate::model::ditem_dsource::{DItemDSourceBmc, DItemDSourceForCreate};
 use crate::model::drive::{DriveBmc, DriveForCreate};
 use crate::model::dsource::DSourceForCreate;
 use crate::model::space::{SpaceBmc, SpaceForCreate};
@@ -56,3 +62,53 @@ pub async fn seed_dsource(mm: &ModelManager, drive_id: Id, rref: &str) -> Result
 
 	Ok(id)
 }
+
+/// Seed a ditem of the dsource with its dfile parts and chunks, one per content (the `#` prefixed ones are titles).
+/// Note: The file does not need to exist, as the parts are not computed from it.
+pub async fn seed_ditem_parts(mm: &ModelManager, dsource_id: Id, file_path: &str, contents: &[&str]) -> Result<Id> {
+	let folder_path = file_path.rsplit_once('/').map(|(folder, _)| folder).unwrap_or_default();
+	let ditem_c = DItemForCreate {
+		file_path: file_path.to_string(),
+		file_mtime: 0.into(),
+		file_size: contents.iter().map(|c| c.len() as i64).sum(),
+		file_ext: "md".to_string(),
+		folder_path: format!("{folder_path}/"),
+	};
+	let ditem_id = DItemBmc::create(mm, ditem_c).await?;
+	DItemDSourceBmc::create(mm, DItemDSourceForCreate { ditem_id, dsource_id }).await?;
+
+	// -- Attach the dfile, and create the parts and chunks
+	let dfile = DFileBmc::get_or_create_for_dsource(mm, dsource_id).await?;
+	let ditem_u = DItemForUpdate {
+		dfile_id: Some(*dfile.id),
+		..Default::default()
+	};
+	DItemBmc::update(mm, ditem_id, ditem_u).await?;
+	let ditem = DItemBmc::get(mm, ditem_id).await?;
+
+	let dfile_db = DFileBmc::get_dfile_db(mm, &dfile).await?;
+	let ditem_ref = DItemRefBmc::get_or_create_for_ditem_uid(&dfile_db, &ditem.uid).await?;
+	for (idx, content) in contents.iter().enumerate() {
+		let line_num = idx as i64 + 1;
+		let part_c = PartForCreate {
+			ditem_ref_id: *ditem_ref.id,
+			is_title: content.starts_with('#'),
+			level: 0,
+			group: 0,
+			line_num,
+			content: content.to_string(),
+		};
+		PartBmc::create(&dfile_db, part_c).await?;
+		let chunk_c = ChunkForCreate {
+			ditem_ref_id: *ditem_ref.id,
+			group: 0,
+			line_start: line_num,
+			line_end: line_num,
+			breadcrumb: String::new(),
+			content: content.to_string(),
+		};
+		ChunkBmc::create(&dfile_db, chunk_c).await?;
+	}
+
+	Ok(ditem_id)
+}
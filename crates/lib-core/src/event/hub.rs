@@ -1,14 +1,15 @@
 use crate::event::{ConvEvent, DSourceEvent, ModelEvent};
 use crate::event::{Error, Result};
-use std::sync::Arc;
-use tokio::sync::broadcast::{self, Receiver, Sender};
-use tracing::error;
+use flume::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 
 /// A construct to manage all the message queues of this lib-core subsystem.
 /// - All queues are initialized during Hub initialization.
 /// - It follows a Multi Producer Multi Consumer (MPMC) pattern,
 ///   allowing for multiple subscribers.
 /// - Queues are currently implemented using the Flume MPMC crate.
+///   Each subscriber has its own unbounded channel, so that no event gets dropped
+///   (e.g., a `ConvEvent::ConvWorkCancel` behind many `ConvEvent::ConvMsgChunk`).
 /// - The Hub is designed to be cloneable, as all of its states are in an Arc<...Inner>.
 /// - This construct is well-suited for use as an App State.
 #[derive(Clone, Default)]
@@ -23,9 +24,9 @@ pub struct Hub {
 ///       (with a negligible increase in code size).
 #[derive(Default)]
 struct HubInner {
-	dsource_queue: Queue<DSourceEvent>,
-	conv_queue: Queue<ConvEvent>,
-	model_queue: Queue<ModelEvent>,
+	dsource_queue: Arc<Queue<DSourceEvent>>,
+	conv_queue: Arc<Queue<ConvEvent>>,
+	model_queue: Arc<Queue<ModelEvent>>,
 }
 
 // public Hub functions.
@@ -35,9 +36,10 @@ impl Hub {
 	#[allow(private_bounds)] // ok, GetQueue is just API ergonomics mechanics
 	pub async fn publish<M>(&self, msg: M)
 	where
+		M: Clone,
 		Hub: GetQueue<M>,
 	{
-		publish_with_tx(&self.get_tx(), msg).await;
+		self.get_queue().publish(msg);
 	}
 
 	#[allow(private_bounds)] // ok, GetQueue is just internal mechanics
@@ -46,34 +48,18 @@ impl Hub {
 		E: Clone,
 		Hub: GetQueue<E>,
 	{
-		let rx = self.get_rx();
+		let rx = self.get_queue().subscribe();
 		Ok(Subscriber::new(rx))
 	}
 
 	#[allow(private_bounds)] // ok, GetQueue is just internal mechanics
 	pub fn publisher<M>(&self) -> Result<Publisher<M>>
 	where
+		M: Clone,
 		Hub: GetQueue<M>,
 	{
-		let tx = self.get_tx();
-		Ok(Publisher::new(tx))
-	}
-}
-
-// private hub functions
-impl Hub {
-	fn get_rx<M>(&self) -> Receiver<M>
-	where
-		Hub: GetQueue<M>,
-	{
-		self.get_queue().tx.subscribe()
-	}
-
-	fn get_tx<M>(&self) -> Sender<M>
-	where
-		Hub: GetQueue<M>,
-	{
-		self.get_queue().tx.clone()
+		let queue = self.get_queue().clone();
+		Ok(Publisher::new(queue))
 	}
 }
 
@@ -89,7 +75,7 @@ impl<E: Clone> Subscriber<E> {
 	}
 
 	pub async fn next(&mut self) -> Result<E> {
-		let m = self.rx.recv().await.map_err(|re| Error::Receive(re.to_string()))?;
+		let m = self.rx.recv_async().await.map_err(|re| Error::Receive(re.to_string()))?;
 		Ok(m)
 	}
 }
@@ -100,23 +86,16 @@ impl<E: Clone> Subscriber<E> {
 
 #[derive(Clone)]
 pub struct Publisher<E> {
-	tx: Sender<E>,
+	queue: Arc<Queue<E>>,
 }
 
-impl<E> Publisher<E> {
-	fn new(tx: Sender<E>) -> Self {
-		Publisher { tx }
+impl<E: Clone> Publisher<E> {
+	fn new(queue: Arc<Queue<E>>) -> Self {
+		Publisher { queue }
 	}
 
 	pub async fn publish(&self, evt: E) {
-		publish_with_tx(&self.tx, evt).await
-	}
-}
-
-/// publish support function to be use in Publisher and Hub
-async fn publish_with_tx<E>(tx: &Sender<E>, evt: E) {
-	if let Err(err) = tx.send(evt).map_err(|se| Error::Send(se.to_string())) {
-		error!("FAIL - Hub publish failed. Cause: {err}");
+		self.queue.publish(evt)
 	}
 }
 
@@ -124,22 +103,36 @@ async fn publish_with_tx<E>(tx: &Sender<E>, evt: E) {
 
 // region:    --- Queue
 
+/// The senders of the subscriber channels of one event type.
 struct Queue<E> {
-	tx: Sender<E>,
-	rx: Receiver<E>, // to make sure it does not close
+	txs: Mutex<Vec<Sender<E>>>,
 }
 
-/// impl default
-// impl Default
-impl<E: Clone> Default for Queue<E> {
+impl<E> Default for Queue<E> {
 	fn default() -> Self {
-		let (tx, rx) = broadcast::channel::<E>(16);
-		Queue { tx, rx }
+		Queue {
+			txs: Default::default(),
+		}
+	}
+}
+
+impl<E: Clone> Queue<E> {
+	fn subscribe(&self) -> Receiver<E> {
+		let (tx, rx) = flume::unbounded();
+		self.txs.lock().unwrap_or_else(|poison| poison.into_inner()).push(tx);
+		rx
+	}
+
+	/// Send the event to all of the subscribers.
+	/// Note: The dropped subscribers get removed (their send fails).
+	fn publish(&self, evt: E) {
+		let mut txs = self.txs.lock().unwrap_or_else(|poison| poison.into_inner());
+		txs.retain(|tx| tx.send(evt.clone()).is_ok());
 	}
 }
 
 trait GetQueue<M> {
-	fn get_queue(&self) -> &Queue<M>;
+	fn get_queue(&self) -> &Arc<Queue<M>>;
 }
 
 // endregion: --- Queue
@@ -147,21 +140,62 @@ trait GetQueue<M> {
 // region:    --- GetQueue Implementations
 
 impl GetQueue<DSourceEvent> for Hub {
-	fn get_queue(&self) -> &Queue<DSourceEvent> {
+	fn get_queue(&self) -> &Arc<Queue<DSourceEvent>> {
 		&self.inner.dsource_queue
 	}
 }
 
 impl GetQueue<ConvEvent> for Hub {
-	fn get_queue(&self) -> &Queue<ConvEvent> {
+	fn get_queue(&self) -> &Arc<Queue<ConvEvent>> {
 		&self.inner.conv_queue
 	}
 }
 
 impl GetQueue<ModelEvent> for Hub {
-	fn get_queue(&self) -> &Queue<ModelEvent> {
+	fn get_queue(&self) -> &Arc<Queue<ModelEvent>> {
 		&self.inner.model_queue
 	}
 }
 
 // endregion: --- GetQueue Implementations
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::model::Id;
+
+	#[tokio::test]
+	async fn test_hub_subscriber_no_dropped_events() -> Result<()> {
+		// -- Setup & Fixtures
+		let hub = Hub::default();
+		let mut sub: Subscriber<ConvEvent> = hub.subscriber()?;
+		let fx_conv_id = Id::from(1);
+		let fx_chunk_count = 1000;
+
+		// -- Exec
+		for _ in 0..fx_chunk_count {
+			hub.publish(ConvEvent::ConvMsgChunk {
+				conv_id: fx_conv_id,
+				msg_id: Id::from(2),
+				delta: "chunk ".to_string(),
+			})
+			.await;
+		}
+		hub.publish(ConvEvent::ConvWorkCancel { conv_id: fx_conv_id }).await;
+
+		// -- Check
+		for _ in 0..fx_chunk_count {
+			assert!(matches!(sub.next().await?, ConvEvent::ConvMsgChunk { .. }));
+		}
+		assert!(matches!(sub.next().await?, ConvEvent::ConvWorkCancel { conv_id } if conv_id == fx_conv_id));
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
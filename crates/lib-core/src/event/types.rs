@@ -17,8 +17,22 @@ pub enum DSourceEvent {
 #[derive(Debug, Serialize, Clone)]
 #[serde(tag = "type", content = "data")]
 pub enum ConvEvent {
-	ConvWorkNew { conv_id: Id },
-	ConvWorkDone { conv_id: Id },
+	ConvWorkNew {
+		conv_id: Id,
+	},
+	ConvWorkDone {
+		conv_id: Id,
+	},
+	/// The conv work was cancelled (in-flight runs must abort).
+	ConvWorkCancel {
+		conv_id: Id,
+	},
+	/// A content delta appended to a msg being generated (see `Msg.done_time`).
+	ConvMsgChunk {
+		conv_id: Id,
+		msg_id: Id,
+		delta: String,
+	},
 }
 
 impl ConvEvent {
@@ -26,6 +40,8 @@ impl ConvEvent {
 		match self {
 			Self::ConvWorkNew { .. } => "conv_work_new",
 			Self::ConvWorkDone { .. } => "conv_work_done",
+			Self::ConvWorkCancel { .. } => "conv_work_cancel",
+			Self::ConvMsgChunk { .. } => "conv_msg_chunk",
 		}
 	}
 }
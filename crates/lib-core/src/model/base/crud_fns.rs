@@ -11,8 +11,9 @@ use crate::model::{Error, Result};
 use modql::field::{HasSeaFields, SeaFields};
 use modql::filter::{FilterGroups, ListOptions};
 use modql::FromSqliteRow;
+use rusqlite::Transaction;
 use sea_query::{Condition, Expr, Query, SqliteQueryBuilder};
-use sea_query_rusqlite::RusqliteBinder;
+use sea_query_rusqlite::{RusqliteBinder, RusqliteValues};
 
 pub async fn create<MC, E>(db: &SlDb, data: E) -> Result<Id>
 where
@@ -31,7 +32,42 @@ where
 ///
 /// Lower create from the fields themselves.
 /// Note: This function will call the `prep_fields_for_create` on the fields.
-pub async fn create_with_fields<MC>(db: &SlDb, mut fields: SeaFields) -> Result<Id>
+pub async fn create_with_fields<MC>(db: &SlDb, fields: SeaFields) -> Result<Id>
+where
+	MC: DbBmc,
+{
+	// -- Exec query
+	let (sql, values) = create_sql::<MC>(fields)?;
+	let id = db.exec_returning_num(&sql, &*values.as_params())?;
+
+	// -- Publish Model Event
+	MC::publish_create_event(db, id.into()).await;
+
+	Ok(id.into())
+}
+
+/// Create in the transaction of a `SlDb::exec_tx`.
+/// Note: The create event is not published, as the transaction is not committed yet
+///       (see `DbBmc::publish_create_event`).
+pub fn create_tx<MC, E>(tx: &Transaction, data: E) -> Result<Id>
+where
+	MC: DbBmc,
+	E: HasSeaFields,
+{
+	let fields = data.not_none_sea_fields();
+
+	// -- Exec query
+	let (sql, values) = create_sql::<MC>(fields)?;
+	let id = tx
+		.query_row(&sql, &*values.as_params(), |r| r.get::<_, i64>(0))
+		.map_err(crate::model::store::Error::from)?;
+
+	Ok(id.into())
+}
+
+/// Returns the insert sql (returning the id) of the fields.
+/// Note: This function will call the `prep_fields_for_create` on the fields.
+fn create_sql<MC>(mut fields: SeaFields) -> Result<(String, RusqliteValues)>
 where
 	MC: DbBmc,
 {
@@ -44,14 +80,7 @@ where
 	query.into_table(MC::table_ref()).columns(columns).values(sea_values)?;
 	query.returning_col(CommonIden::Id);
 
-	// -- Exec query
-	let (sql, values) = query.build_rusqlite(SqliteQueryBuilder);
-	let id = db.exec_returning_num(&sql, &*values.as_params())?;
-
-	// -- Publish Model Event
-	MC::publish_create_event(db, id.into()).await;
-
-	Ok(id.into())
+	Ok(query.build_rusqlite(SqliteQueryBuilder))
 }
 
 pub async fn get<MC, E>(db: &SlDb, id: Id) -> Result<E>
@@ -6,6 +6,8 @@
 //!
 //! - `cfile_ref` - which is a ref to the main-db `cfile`
 //! - `msg`       - message from the user, AI, or logic (app logic)
+//! - `stack_step`     - the chain steps run for a message
+//! - `step_tool_call` - the tool calls of the agent model while running a step
 
 //! NOTE: Might want to have the modules below only for the (in crate::model) scope
 //!       but right now, the ais runner needs to have precise control
@@ -16,5 +18,6 @@
 pub mod conv_ref;
 pub mod msg;
 pub mod stack_step;
+pub mod step_tool_call;
 
 // endregion: --- Modules
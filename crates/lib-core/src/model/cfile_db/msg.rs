@@ -1,9 +1,14 @@
 use crate::model::conv::ConvMsg;
 use crate::model::stack_step::StackStep;
 use crate::model::support::prelude::*;
+use lib_utils::time::now;
+use modql::field::SeaField;
 
 // region:    --- Types
 
+/// The `Msg.err` of the agent answers of cancelled runs.
+pub const MSG_ERR_CANCELLED: &str = "Cancelled";
+
 #[cfg_attr(feature = "for-ts", derive(schemars::JsonSchema))]
 #[derive(Debug, Clone, SeaFieldValue, FromSqliteValue, Serialize, Deserialize)]
 pub enum AuthorKind {
@@ -12,6 +17,7 @@ pub enum AuthorKind {
 }
 
 #[derive(Debug, Clone, Fields, FromSqliteRow, Serialize, Deserialize)]
+#[modql(names_as_consts)]
 pub struct Msg {
 	pub id: Id,
 	pub uid: String,
@@ -23,6 +29,12 @@ pub struct Msg {
 
 	pub content: Option<String>,
 
+	/// For agent answers, when the answer started to be generated.
+	pub start_time: Option<UnixTimeUs>,
+	/// For agent answers, when the answer got completed (None while generating).
+	pub done_time: Option<UnixTimeUs>,
+	pub err: Option<String>,
+
 	pub ctime: UnixTimeUs,
 	pub mtime: UnixTimeUs,
 }
@@ -35,6 +47,10 @@ pub struct MsgForCreate {
 	pub author_kind: AuthorKind,
 
 	pub content: Option<String>,
+
+	pub start_time: Option<UnixTimeUs>,
+	pub done_time: Option<UnixTimeUs>,
+	pub err: Option<String>,
 }
 
 impl MsgForCreate {
@@ -50,6 +66,9 @@ impl MsgForCreate {
 			orig_msg_id: None,
 			author_kind: AuthorKind::User,
 			content,
+			start_time: None,
+			done_time: None,
+			err: None,
 		}
 	}
 }
@@ -90,6 +109,26 @@ WHERE msg.id = ?1
 		Ok(conv_uid)
 	}
 
+	/// Returns the last `window` messages (with content) of the conversation before the `msg_id` message,
+	/// in chronological order.
+	pub async fn list_history_before(db: &SlDb, msg_id: Id, window: i64) -> Result<Vec<Msg>> {
+		let sql = r#"
+SELECT h.*
+  FROM msg h
+  JOIN msg m ON h.conv_ref_id = m.conv_ref_id
+ WHERE m.id = ?1
+   AND h.id < m.id
+   AND h.content IS NOT NULL
+   AND h.err IS NULL
+ ORDER BY h.id DESC
+ LIMIT ?2
+		"#;
+		let mut msgs: Vec<Msg> = db.fetch_all(sql, (msg_id, window))?;
+		msgs.reverse();
+
+		Ok(msgs)
+	}
+
 	pub async fn create_user_question(db: &SlDb, msg_c: MsgForCreate) -> Result<Id> {
 		// check that author_kind is User
 		if !matches!(msg_c.author_kind, AuthorKind::User) {
@@ -99,18 +138,147 @@ WHERE msg.id = ?1
 		base::create::<Self, _>(db, msg_c).await
 	}
 
+	/// Create the agent answer for the closer step.
+	/// If a pending answer (being streamed) exists for the orig msg, it gets completed with the content.
 	pub async fn create_agent_answer(db: &SlDb, closer_step: StackStep, content: impl Into<String>) -> Result<Id> {
+		if let Some(pending_msg) = Self::first_pending_answer(db, closer_step.orig_msg_id).await? {
+			let fields = vec![
+				SeaField::siden(Msg::CONTENT, content.into()),
+				SeaField::siden(Msg::DONE_TIME, now()),
+			];
+			base::update_with_fields::<Self>(db, pending_msg.id, fields.into()).await?;
+			return Ok(pending_msg.id);
+		}
+
 		let orig_msg = Self::get(db, closer_step.orig_msg_id).await?;
+		let now = now();
 
 		let msg_c = MsgForCreate {
 			conv_ref_id: orig_msg.conv_ref_id,
 			orig_msg_id: Some(orig_msg.id),
 			author_kind: AuthorKind::Agent,
 			content: Some(content.into()),
+			start_time: Some(now),
+			done_time: Some(now),
+			err: None,
 		};
 
 		base::create::<Self, _>(db, msg_c).await
 	}
+
+	/// Close the orig msg with a cancelled agent answer.
+	/// If a pending answer exists, it gets completed as cancelled (keeping the content received so far).
+	pub async fn create_agent_cancelled(db: &SlDb, orig_msg_id: Id) -> Result<Id> {
+		if let Some(pending_msg) = Self::first_pending_answer(db, orig_msg_id).await? {
+			Self::set_pending_err(db, pending_msg.id, MSG_ERR_CANCELLED).await?;
+			return Ok(pending_msg.id);
+		}
+
+		let orig_msg = Self::get(db, orig_msg_id).await?;
+		let now = now();
+
+		let msg_c = MsgForCreate {
+			conv_ref_id: orig_msg.conv_ref_id,
+			orig_msg_id: Some(orig_msg.id),
+			author_kind: AuthorKind::Agent,
+			content: None,
+			start_time: Some(now),
+			done_time: Some(now),
+			err: Some(MSG_ERR_CANCELLED.to_string()),
+		};
+
+		base::create::<Self, _>(db, msg_c).await
+	}
+
+	// region:    --- Pending Answer
+
+	/// Create a pending agent answer (empty content, no `done_time`) for the orig msg,
+	/// or return the existing pending or failed one (reset as pending),
+	/// so that a retried generation does not add another answer.
+	pub async fn create_agent_pending(db: &SlDb, orig_msg_id: Id) -> Result<Id> {
+		if let Some(answer_msg) = Self::first_pending_or_failed_answer(db, orig_msg_id).await? {
+			let sql = r#"
+UPDATE msg
+   SET content = '',
+       err = NULL,
+       done_time = NULL,
+       start_time = ?2,
+       mtime = ?2
+ WHERE id = ?1
+			"#;
+			db.exec(sql, (answer_msg.id, now()))?;
+			return Ok(answer_msg.id);
+		}
+
+		let orig_msg = Self::get(db, orig_msg_id).await?;
+
+		let msg_c = MsgForCreate {
+			conv_ref_id: orig_msg.conv_ref_id,
+			orig_msg_id: Some(orig_msg.id),
+			author_kind: AuthorKind::Agent,
+			content: Some(String::new()),
+			start_time: Some(now()),
+			done_time: None,
+			err: None,
+		};
+
+		base::create::<Self, _>(db, msg_c).await
+	}
+
+	/// Returns the agent answer of the orig msg that is not done yet, if any.
+	pub async fn first_pending_answer(db: &SlDb, orig_msg_id: Id) -> Result<Option<Msg>> {
+		let sql = r#"
+SELECT *
+  FROM msg
+ WHERE orig_msg_id = ?1
+   AND author_kind = 'Agent'
+   AND done_time IS NULL
+ ORDER BY id
+ LIMIT 1
+		"#;
+		let msg = db.fetch_first(sql, (orig_msg_id,))?;
+
+		Ok(msg)
+	}
+
+	/// Returns the agent answer of the orig msg that is not done yet, or that failed, if any.
+	async fn first_pending_or_failed_answer(db: &SlDb, orig_msg_id: Id) -> Result<Option<Msg>> {
+		let sql = r#"
+SELECT *
+  FROM msg
+ WHERE orig_msg_id = ?1
+   AND author_kind = 'Agent'
+   AND (done_time IS NULL OR err IS NOT NULL)
+ ORDER BY id
+ LIMIT 1
+		"#;
+		let msg = db.fetch_first(sql, (orig_msg_id,))?;
+
+		Ok(msg)
+	}
+
+	/// Append a content delta to a (pending) msg.
+	pub async fn append_content(db: &SlDb, msg_id: Id, delta: &str) -> Result<()> {
+		let sql = r#"
+UPDATE msg
+   SET content = COALESCE(content, '') || ?2,
+       mtime = ?3
+ WHERE id = ?1
+		"#;
+		db.exec(sql, (msg_id, delta, now()))?;
+
+		Ok(())
+	}
+
+	/// Complete a pending msg with an error (the content received so far is kept).
+	pub async fn set_pending_err(db: &SlDb, msg_id: Id, err: impl Into<String>) -> Result<()> {
+		let fields = vec![SeaField::siden(Msg::ERR, err.into()), SeaField::siden(Msg::DONE_TIME, now())];
+		base::update_with_fields::<Self>(db, msg_id, fields.into()).await?;
+
+		Ok(())
+	}
+
+	// endregion: --- Pending Answer
 }
 
 // endregion: --- Bmc
@@ -4,6 +4,9 @@ use crate::model::support::prelude::*;
 use lib_utils::time::now;
 use modql::field::{HasSeaFields, SeaField};
 
+/// The `call_err` of the orphan steps failed on recovery (see `OrphanStepPolicy::Fail`).
+pub const STEP_ERR_INTERRUPTED: &str = "Interrupted";
+
 // region:    --- Types
 
 #[derive(Debug, Clone, Fields, FromSqliteRow, Serialize, Deserialize)]
@@ -18,6 +21,9 @@ pub struct StackStep {
 	pub prev_step_id: Option<Id>,
 	pub closer: bool,
 
+	pub retry_of_step_id: Option<Id>,
+	pub attempt: i64,
+
 	// -- Call Ctx
 	pub call_stack: Option<String>,
 	pub call_out: Option<String>,
@@ -30,9 +36,15 @@ pub struct StackStep {
 	pub run_agent_uid: Option<String>,
 	pub run_agent_name: Option<String>,
 
+	pub run_tafter: Option<UnixTimeUs>,
 	pub run_tstart: Option<UnixTimeUs>,
 	pub run_tend: Option<UnixTimeUs>,
 	pub run_terr: Option<UnixTimeUs>,
+	pub run_tcancel: Option<UnixTimeUs>,
+	pub run_model: Option<String>,
+
+	pub usage_prompt_tokens: Option<i64>,
+	pub usage_completion_tokens: Option<i64>,
 
 	pub ctime: UnixTimeUs,
 	pub mtime: UnixTimeUs,
@@ -49,6 +61,9 @@ pub struct StackStepLite {
 	pub first_step_id: Option<Id>,
 	pub prev_step_id: Option<Id>,
 	pub closer: bool,
+
+	pub retry_of_step_id: Option<Id>,
+	pub attempt: i64,
 }
 
 #[derive(Debug, Clone, Fields, FromSqliteRow, Serialize, Deserialize)]
@@ -77,11 +92,53 @@ impl StackStepForCreate {
 	}
 }
 
+/// The clone of a failed step for a new attempt.
+/// Note: The resolve properties are copied, so the retry step is ready to run.
+#[derive(Debug, Clone, Fields)]
+pub struct StackStepForRetry {
+	pub orig_msg_id: Id,
+	pub first_step_id: Option<Id>,
+	pub prev_step_id: Option<Id>,
+	pub closer: bool,
+
+	pub retry_of_step_id: Id,
+	pub attempt: i64,
+
+	pub call_stack: Option<String>,
+	pub resolve_tstart: Option<UnixTimeUs>,
+	pub resolve_tend: Option<UnixTimeUs>,
+	pub resolve_model: Option<String>,
+
+	pub run_tafter: Option<UnixTimeUs>,
+}
+
+impl StackStepForRetry {
+	pub fn from_failed_step(step: StackStep, run_tafter: Option<UnixTimeUs>) -> Self {
+		Self {
+			orig_msg_id: step.orig_msg_id,
+			first_step_id: step.first_step_id,
+			prev_step_id: step.prev_step_id,
+			closer: step.closer,
+			retry_of_step_id: step.id,
+			attempt: step.attempt + 1,
+			call_stack: step.call_stack,
+			// Note: A step failed before its resolve ended (e.g., orphan) gets resolved again.
+			resolve_tstart: step.resolve_tend.and(step.resolve_tstart),
+			resolve_tend: step.resolve_tend,
+			resolve_model: step.resolve_model,
+			run_tafter,
+		}
+	}
+}
+
 #[derive(Debug, Clone, Default, Fields, FromSqliteRow, Serialize, Deserialize)]
 pub struct StackStepForUpdate {
 	pub resolve_model: Option<String>,
 	pub run_agent_uid: Option<String>,
 	pub run_agent_name: Option<String>,
+	pub run_model: Option<String>,
+	pub usage_prompt_tokens: Option<i64>,
+	pub usage_completion_tokens: Option<i64>,
 	pub call_stack: Option<String>,
 	pub call_out: Option<String>,
 	pub call_err: Option<String>,
@@ -103,6 +160,39 @@ pub struct StackStepFilter {
 	pub run_tstart: Option<OpValsInt64>,
 }
 
+/// The run usage of the steps, aggregated per conversation, agent and model.
+#[derive(Debug, Clone, FromSqliteRow, Serialize)]
+pub struct StepUsage {
+	pub conv_uid: String,
+	pub agent_uid: Option<String>,
+	pub agent_name: Option<String>,
+	pub model: Option<String>,
+
+	pub step_count: i64,
+	pub prompt_tokens: i64,
+	pub completion_tokens: i64,
+	/// Sum of the `run_tend - run_tstart` of the steps.
+	pub run_duration_us: i64,
+}
+
+/// Time range on the step `run_tend` (`from` inclusive, `to` exclusive).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UsageRange {
+	pub from: Option<UnixTimeUs>,
+	pub to: Option<UnixTimeUs>,
+}
+
+/// What to do with the orphan steps on startup,
+/// i.e., the steps taken to be resolved or ran, but never finished (e.g., app quit mid-run).
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum OrphanStepPolicy {
+	/// Clear the start time, so the step gets resolved or ran again.
+	#[default]
+	Reset,
+	/// Mark the step as failed (`call_err` "Interrupted"), so it can be retried by the user.
+	Fail,
+}
+
 // endregion: --- Types
 
 // region:    --- Bmc
@@ -142,6 +232,27 @@ impl StackStepBmc {
 		Ok(next_step_id)
 	}
 
+	/// Create a retry step (clone of the failed step), returning its id.
+	/// - `run_tafter` is the eventual time before which the retry step should not be ran.
+	/// - Fails if the step did not fail, or already has a retry.
+	pub async fn create_retry_from_step(db: &SlDb, step_id: Id, run_tafter: Option<UnixTimeUs>) -> Result<Id> {
+		let step = Self::get(db, step_id).await?;
+		if step.run_terr.is_none() {
+			return Err(StackStepError::CannotRetryStepNotFailed { step_id }.into());
+		}
+
+		let sql = "SELECT id FROM stack_step WHERE retry_of_step_id = ?1 LIMIT 1";
+		let retry_id: Option<Id> = db.exec_returning_as_optional(sql, (step_id,))?;
+		if let Some(retry_step_id) = retry_id {
+			return Err(StackStepError::CannotRetryStepAlreadyRetried { step_id, retry_step_id }.into());
+		}
+
+		let retry_step_c = StackStepForRetry::from_failed_step(step, run_tafter);
+		let retry_step_id = base::create::<Self, _>(db, retry_step_c).await?;
+
+		Ok(retry_step_id)
+	}
+
 	// region:    --- Update Methods
 
 	pub async fn update_resolve_success(
@@ -204,8 +315,80 @@ impl StackStepBmc {
 		Ok(())
 	}
 
+	/// Mark all the unfinished steps of a conversation as cancelled.
+	/// Returns the ids of the orig msgs of the cancelled steps.
+	pub async fn cancel_unfinished_for_conv(db: &SlDb, conv_uid: &str) -> Result<Vec<Id>> {
+		let where_unfinished = r#"
+ WHERE run_tend IS NULL
+   AND run_terr IS NULL
+   AND run_tcancel IS NULL
+   AND orig_msg_id IN (SELECT m.id
+                         FROM msg m
+                         JOIN conv_ref c ON m.conv_ref_id = c.id
+                        WHERE c.conv_uid = ?1)
+		"#;
+
+		let sql = format!("SELECT * FROM stack_step {where_unfinished} ORDER BY orig_msg_id, id");
+		let steps: Vec<StackStep> = db.fetch_all(&sql, (conv_uid,))?;
+		let mut orig_msg_ids: Vec<Id> = steps.into_iter().map(|s| s.orig_msg_id).collect();
+		orig_msg_ids.dedup();
+
+		let sql = format!("UPDATE stack_step SET run_tcancel = ?2, mtime = ?2 {where_unfinished}");
+		db.exec(&sql, (conv_uid, now()))?;
+
+		Ok(orig_msg_ids)
+	}
+
 	// endregion: --- Update "T-States"
 
+	// region:    --- Orphans
+
+	/// Returns the steps started to be resolved or ran, but never finished (nor cancelled).
+	pub async fn list_orphans(db: &SlDb) -> Result<Vec<StackStep>> {
+		let sql = r#"
+SELECT *
+  FROM stack_step
+ WHERE run_tcancel IS NULL
+   AND ((resolve_tstart IS NOT NULL AND resolve_tend IS NULL)
+        OR (run_tstart IS NOT NULL AND run_tend IS NULL AND run_terr IS NULL))
+ ORDER BY id
+		"#;
+		let steps = db.fetch_all(sql, ())?;
+
+		Ok(steps)
+	}
+
+	/// Recover an orphan step (see `list_orphans`) according to the policy.
+	pub async fn recover_orphan(db: &SlDb, step: &StackStep, policy: OrphanStepPolicy) -> Result<()> {
+		match policy {
+			OrphanStepPolicy::Reset => {
+				let col = if step.resolve_tend.is_none() {
+					StackStep::RESOLVE_TSTART
+				} else {
+					StackStep::RUN_TSTART
+				};
+				let sql = format!("UPDATE stack_step SET {col} = NULL, mtime = ?2 WHERE id = ?1");
+				db.exec(&sql, (step.id, now()))?;
+			}
+			OrphanStepPolicy::Fail => {
+				let step_u = StackStepForUpdate {
+					call_err: Some(STEP_ERR_INTERRUPTED.to_string()),
+					..Default::default()
+				};
+				Self::update_run_end_fail(db, step.id, step_u).await?;
+
+				// Note: The answer being streamed when interrupted is closed with the error.
+				if let Some(pending_msg) = MsgBmc::first_pending_answer(db, step.orig_msg_id).await? {
+					MsgBmc::set_pending_err(db, pending_msg.id, STEP_ERR_INTERRUPTED).await?;
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	// endregion: --- Orphans
+
 	/// This will get the next StackStep to be taken to work on (e.g., `started == null`)
 	pub async fn seek_next_to_resolve_for_conv(db: &SlDb, conv_uid: &str) -> Result<Option<StackStep>> {
 		let sql = r#"
@@ -215,6 +398,7 @@ SELECT s.id
   JOIN conv_ref c ON m.conv_ref_id = c.id
  WHERE c.conv_uid = ?
    AND s.resolve_tstart IS NULL
+   AND s.run_tcancel IS NULL
  ORDER BY s.id ASC
  LIMIT 1;
 		"#;
@@ -235,13 +419,15 @@ SELECT s.*
   FROM stack_step s
   JOIN msg ON s.orig_msg_id = msg.id
   JOIN conv_ref ON msg.conv_ref_id = conv_ref.id
- WHERE conv_ref.conv_uid = ?
+ WHERE conv_ref.conv_uid = ?1
    AND s.resolve_tend IS NOT NULL
-   AND s.run_tstart IS NULL;
+   AND s.run_tstart IS NULL
+   AND s.run_tcancel IS NULL
+   AND (s.run_tafter IS NULL OR s.run_tafter <= ?2)
  ORDER BY s.id ASC
  LIMIT 1;
 		"#;
-		let res: Option<Id> = db.exec_returning_as_optional(sql, (conv_uid,))?;
+		let res: Option<Id> = db.exec_returning_as_optional(sql, (conv_uid, now()))?;
 
 		if let Some(stack_id) = res {
 			let step = Self::get(db, stack_id).await?;
@@ -251,6 +437,33 @@ SELECT s.*
 		}
 	}
 
+	/// Returns the run usage of the ran steps of a conversation, per agent and model.
+	pub async fn usage_for_conv(db: &SlDb, conv_uid: &str, range: &UsageRange) -> Result<Vec<StepUsage>> {
+		let sql = r#"
+SELECT c.conv_uid,
+       s.run_agent_uid AS agent_uid,
+       s.run_agent_name AS agent_name,
+       s.run_model AS model,
+       COUNT(s.id) AS step_count,
+       COALESCE(SUM(s.usage_prompt_tokens), 0) AS prompt_tokens,
+       COALESCE(SUM(s.usage_completion_tokens), 0) AS completion_tokens,
+       COALESCE(SUM(s.run_tend - s.run_tstart), 0) AS run_duration_us
+  FROM stack_step s
+  JOIN msg m ON s.orig_msg_id = m.id
+  JOIN conv_ref c ON m.conv_ref_id = c.id
+ WHERE c.conv_uid = ?1
+   AND s.closer = 0
+   AND s.run_tend IS NOT NULL
+   AND (?2 IS NULL OR s.run_tend >= ?2)
+   AND (?3 IS NULL OR s.run_tend < ?3)
+ GROUP BY c.conv_uid, s.run_agent_uid, s.run_agent_name, s.run_model
+ ORDER BY s.run_agent_name, s.run_model
+		"#;
+		let usages = db.fetch_all(sql, (conv_uid, range.from, range.to))?;
+
+		Ok(usages)
+	}
+
 	pub async fn get_prev_step_call_out(db: &SlDb, step_id: Id) -> Result<Option<String>> {
 		let sql = r#"
 SELECT ss2.call_out
@@ -265,3 +478,25 @@ WHERE ss1.id = ?;
 }
 
 // endregion: --- Bmc
+
+// region:    --- StackStepError
+
+#[derive(Debug, Serialize)]
+pub enum StackStepError {
+	CannotRetryStepNotFailed { step_id: Id },
+	CannotRetryStepAlreadyRetried { step_id: Id, retry_step_id: Id },
+}
+
+// region:    --- Error Boilerplate
+
+impl core::fmt::Display for StackStepError {
+	fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+		write!(fmt, "{self:?}")
+	}
+}
+
+impl std::error::Error for StackStepError {}
+
+// endregion: --- Error Boilerplate
+
+// endregion: --- StackStepError
@@ -0,0 +1,101 @@
+use crate::model::support::prelude::*;
+use lib_utils::time::now;
+use modql::field::{HasSeaFields, SeaField};
+
+// region:    --- Types
+
+/// A tool call of the agent model while running a `StackStep` (see `Agent.tools`),
+/// with its tool result or error.
+#[derive(Debug, Clone, Fields, FromSqliteRow, Serialize, Deserialize)]
+#[modql(names_as_consts)]
+pub struct StepToolCall {
+	pub id: Id,
+
+	pub step_id: Id,
+
+	/// The provider call id (matching the tool result to the call).
+	pub call_id: Option<String>,
+	pub fn_name: String,
+	/// JSON, as generated by the model.
+	pub fn_arguments: Option<String>,
+
+	/// JSON, the tool result (if success).
+	pub result: Option<String>,
+	pub err: Option<String>,
+
+	pub tstart: Option<UnixTimeUs>,
+	pub tend: Option<UnixTimeUs>,
+
+	pub ctime: UnixTimeUs,
+	pub mtime: UnixTimeUs,
+}
+
+#[derive(Debug, Clone, Fields)]
+pub struct StepToolCallForCreate {
+	pub step_id: Id,
+	pub call_id: Option<String>,
+	pub fn_name: String,
+	pub fn_arguments: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Fields)]
+pub struct StepToolCallForUpdate {
+	pub result: Option<String>,
+	pub err: Option<String>,
+}
+
+#[derive(FilterNodes, Default, Deserialize)]
+pub struct StepToolCallFilter {
+	pub id: Option<OpValsInt64>,
+
+	pub step_id: Option<OpValsInt64>,
+	pub fn_name: Option<OpValsString>,
+}
+
+// endregion: --- Types
+
+// region:    --- Bmc
+
+pub struct StepToolCallBmc;
+
+impl DbBmc for StepToolCallBmc {
+	const TABLE: &'static str = "step_tool_call";
+
+	fn has_uid() -> bool {
+		false
+	}
+}
+
+generate_sldb_crud_fns!(
+	Bmc: StepToolCallBmc,
+	ForGet: StepToolCall,
+	ForList: StepToolCall,
+	Filter: StepToolCallFilter,
+);
+
+impl StepToolCallBmc {
+	/// Create the tool call, with its `tstart` set to now (i.e., the tool is about to run).
+	pub async fn create_started(db: &SlDb, tool_call_c: StepToolCallForCreate) -> Result<Id> {
+		let fields = tool_call_c.not_none_sea_fields().append_siden(StepToolCall::TSTART, now());
+		base::create_with_fields::<Self>(db, fields).await
+	}
+
+	/// Set the tool result or error, with the `tend`.
+	pub async fn update_end(db: &SlDb, tool_call_id: Id, tool_call_u: StepToolCallForUpdate) -> Result<()> {
+		let fields = tool_call_u.not_none_sea_fields().append_siden(StepToolCall::TEND, now());
+		base::update_with_fields::<Self>(db, tool_call_id, fields).await?;
+
+		Ok(())
+	}
+
+	/// Returns the tool calls of a step, in the call order.
+	pub async fn list_for_step(db: &SlDb, step_id: Id) -> Result<Vec<StepToolCall>> {
+		let filter = StepToolCallFilter {
+			step_id: Some((*step_id).into()),
+			..Default::default()
+		};
+		Self::list(db, Some(vec![filter]), None).await
+	}
+}
+
+// endregion: --- Bmc
@@ -0,0 +1,224 @@
+use crate::model::dfile_db::part_vec::{cosine_similarity, vec_from_blob};
+use crate::model::store;
+use crate::model::support::prelude::*;
+use lib_utils::f;
+use rusqlite::{named_params, Transaction};
+
+/// The separator of the `breadcrumb` titles.
+pub const BREADCRUMB_SEP: &str = " > ";
+
+// region:    --- Types
+
+#[derive(Debug, Clone, Fields, FromSqliteRow, Serialize, Deserialize)]
+pub struct Chunk {
+	pub id: Id,
+
+	pub ditem_ref_id: i64,
+
+	pub group: i64,
+	pub line_start: i64,
+	pub line_end: i64,
+	/// The heading titles, outer first, joined with `BREADCRUMB_SEP` (e.g., `Intro > Setup`).
+	pub breadcrumb: String,
+	pub content: String,
+
+	pub ctime: UnixTimeUs,
+	pub mtime: UnixTimeUs,
+}
+
+impl Chunk {
+	/// Returns the text embedded for the chunk vectors, the breadcrumb (if any) line and the content.
+	pub fn embed_input(&self) -> String {
+		if self.breadcrumb.is_empty() {
+			self.content.to_string()
+		} else {
+			format!("{}\n{}", self.breadcrumb, self.content)
+		}
+	}
+}
+
+#[derive(Debug, Clone, Fields, FromSqliteRow, Serialize, Deserialize)]
+pub struct ChunkForCreate {
+	pub ditem_ref_id: i64,
+
+	pub group: i64,
+	pub line_start: i64,
+	pub line_end: i64,
+	pub breadcrumb: String,
+	pub content: String,
+}
+
+/// A chunk with parts matching a content search, with the fts rank of its best part (bm25, the lower the better).
+#[derive(Debug, Clone, FromSqliteRow, Serialize)]
+pub struct ChunkHit {
+	pub id: Id,
+
+	pub ditem_ref_id: i64,
+
+	pub line_start: i64,
+	pub line_end: i64,
+	pub breadcrumb: String,
+	pub content: String,
+
+	pub rank: f64,
+}
+
+/// A chunk similar to a query vector, with its cosine similarity (the higher the better).
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkSimilarHit {
+	pub id: Id,
+
+	pub ditem_ref_id: i64,
+
+	pub line_start: i64,
+	pub line_end: i64,
+	pub breadcrumb: String,
+	pub content: String,
+
+	pub similarity: f64,
+}
+
+#[derive(FromSqliteRow)]
+struct PartVecRow {
+	chunk_id: i64,
+	vec: Vec<u8>,
+}
+
+#[derive(FromSqliteRow)]
+struct PartIdRow {
+	id: i64,
+}
+
+// endregion: --- Types
+
+// region:    --- Bmc
+
+pub struct ChunkBmc;
+
+impl DbBmc for ChunkBmc {
+	const TABLE: &'static str = "chunk";
+
+	fn has_uid() -> bool {
+		false
+	}
+}
+
+generate_sldb_crud_fns!(
+	Bmc: ChunkBmc,
+	ForGet: Chunk,
+	ForCreate: ChunkForCreate,
+);
+
+impl ChunkBmc {
+	/// Returns the best `limit` chunks with parts matching the fts `search`, ordered by the rank of their
+	/// best part (best first).
+	/// Note: A part line in the overlap of two chunks matches both.
+	pub async fn content_search_ranked(db: &SlDb, search: &str, limit: i64) -> Result<Vec<ChunkHit>> {
+		// Note: `MATERIALIZED`, as `bm25` cannot be used once the part hits are flattened into the join.
+		let sql = r#"
+WITH     part_hit AS MATERIALIZED (
+           SELECT part.ditem_ref_id, part.line_num, bm25(part_fts) AS rank
+           FROM   part_fts
+           JOIN   part ON part_fts.rowid = part.id
+           WHERE  part_fts MATCH :search)
+SELECT   chunk.id, chunk.ditem_ref_id, chunk.line_start, chunk.line_end, chunk.breadcrumb, chunk.content,
+         MIN(part_hit.rank) AS rank
+FROM     part_hit
+JOIN     chunk ON chunk.ditem_ref_id = part_hit.ditem_ref_id
+                  AND part_hit.line_num BETWEEN chunk.line_start AND chunk.line_end
+GROUP BY chunk.id
+ORDER BY rank, chunk.id
+LIMIT    :limit;
+"#;
+
+		let entities: Vec<ChunkHit> = db.fetch_all(sql, named_params! { ":search": search, ":limit": limit })?;
+
+		Ok(entities)
+	}
+
+	/// Returns the `k` chunks most similar to the `query_vec` (brute-force cosine similarity),
+	/// among the chunks with a vector of the embed `model`.
+	pub async fn similar_search(db: &SlDb, model: &str, query_vec: &[f32], k: usize) -> Result<Vec<ChunkSimilarHit>> {
+		let sql = "SELECT chunk_id, vec FROM part_vec WHERE model = :model AND dim = :dim;";
+		let rows: Vec<PartVecRow> =
+			db.fetch_all(sql, named_params! { ":model": model, ":dim": query_vec.len() as i64 })?;
+
+		let mut sims: Vec<(i64, f32)> = rows
+			.into_iter()
+			.map(|row| (row.chunk_id, cosine_similarity(query_vec, &vec_from_blob(&row.vec))))
+			.collect();
+		sims.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+		sims.truncate(k);
+
+		// -- Get the chunks
+		let mut hits = Vec::with_capacity(sims.len());
+		for (chunk_id, similarity) in sims {
+			let chunk = Self::get(db, chunk_id.into()).await?;
+			hits.push(ChunkSimilarHit {
+				id: chunk.id,
+				ditem_ref_id: chunk.ditem_ref_id,
+				line_start: chunk.line_start,
+				line_end: chunk.line_end,
+				breadcrumb: chunk.breadcrumb,
+				content: chunk.content,
+				similarity: similarity.into(),
+			});
+		}
+
+		Ok(hits)
+	}
+
+	/// Delete the chunks of a ditem_ref, with their vectors (see `part_vec`),
+	/// in the transaction of a `SlDb::exec_tx`.
+	pub fn delete_for_ditem_ref_tx(tx: &Transaction, ditem_ref_id: i64) -> Result<usize> {
+		tx.execute(
+			"DELETE FROM part_vec WHERE chunk_id IN (SELECT id FROM chunk WHERE ditem_ref_id = :ditem_ref_id);",
+			&[(":ditem_ref_id", &ditem_ref_id)],
+		)
+		.map_err(store::Error::from)?;
+		let count = tx
+			.execute(
+				"DELETE FROM chunk WHERE ditem_ref_id = :ditem_ref_id;",
+				&[(":ditem_ref_id", &ditem_ref_id)],
+			)
+			.map_err(store::Error::from)?;
+
+		Ok(count)
+	}
+
+	/// Returns the ids of the parts of a chunk (the parts of its ditem_ref in its lines), in the line order.
+	pub async fn list_part_ids(db: &SlDb, chunk_id: Id) -> Result<Vec<Id>> {
+		let sql = r#"
+SELECT   part.id
+FROM     chunk
+JOIN     part ON part.ditem_ref_id = chunk.ditem_ref_id
+                 AND part.line_num BETWEEN chunk.line_start AND chunk.line_end
+WHERE    chunk.id = :chunk_id
+ORDER BY part.line_num, part.id;
+"#;
+		let rows: Vec<PartIdRow> = db.fetch_all(sql, named_params! { ":chunk_id": chunk_id })?;
+
+		Ok(rows.into_iter().map(|row| row.id.into()).collect())
+	}
+
+	/// Returns the chunks of a ditem_ref, in the line order.
+	pub async fn list_for_ditem_ref(db: &SlDb, ditem_ref_id: i64) -> Result<Vec<Chunk>> {
+		let columns: Vec<String> = Chunk::field_names().iter().map(|n| f!(r#""chunk"."{n}""#)).collect();
+		let columns = columns.join(",");
+
+		let sql = format!(
+			r#"
+SELECT   {columns}
+FROM     chunk
+WHERE    chunk.ditem_ref_id = :ditem_ref_id
+ORDER BY chunk.line_start;
+"#
+		);
+
+		let entities: Vec<Chunk> = db.fetch_all(&sql, &[(":ditem_ref_id", &ditem_ref_id)])?;
+
+		Ok(entities)
+	}
+}
+
+// endregion: --- Bmc
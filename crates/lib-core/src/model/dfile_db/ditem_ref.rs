@@ -7,7 +7,7 @@ use lib_utils::time::UnixTimeUs;
 pub struct DItemRef {
 	pub id: Id,
 
-	ditem_uid: String,
+	pub ditem_uid: String,
 
 	pub ctime: UnixTimeUs,
 	pub mtime: UnixTimeUs,
@@ -4,10 +4,14 @@
 //!
 //! - `ditem_ref`: Which is a pointer to the main_db `ditem`. Typically a file.
 //! - `part`: Which is a part of a of a ditem (i.e. file).
+//! - `chunk`: Which is consecutive parts of a ditem heading group (for retrieval and embeddings).
+//! - `part_vec`: Which is the embedding vector of the parts of a chunk, per embed model.
 
 // region:    --- Modules
 
+pub mod chunk;
 pub mod ditem_ref;
 pub mod part;
+pub mod part_vec;
 
 // endregion: --- Modules
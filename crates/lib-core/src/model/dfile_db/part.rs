@@ -1,6 +1,8 @@
+use crate::model::store;
 use crate::model::support::prelude::*;
 use lib_utils::f;
 use modql::field::HasFields;
+use rusqlite::{named_params, Transaction};
 
 // region:    --- Types
 
@@ -31,6 +33,20 @@ pub struct PartForCreate {
 	pub content: String,
 }
 
+/// A part matching a content search, with its fts rank (bm25, the lower the better).
+#[derive(Debug, Clone, FromSqliteRow, Serialize)]
+pub struct PartHit {
+	pub id: Id,
+
+	pub ditem_ref_id: i64,
+
+	pub is_title: bool,
+	pub line_num: i64,
+	pub content: String,
+
+	pub rank: f64,
+}
+
 // endregion: --- Types
 
 // region:    --- Bmc
@@ -65,6 +81,54 @@ WHERE part_fts MATCH :search;
 
 		Ok(entities)
 	}
+
+	/// Returns the best `limit` parts matching the fts `search`, ordered by rank (best first).
+	pub async fn content_search_ranked(db: &SlDb, search: &str, limit: i64) -> Result<Vec<PartHit>> {
+		let sql = r#"
+SELECT   part.id, part.ditem_ref_id, part.is_title, part.line_num, part.content, bm25(part_fts) AS rank
+FROM     part_fts 
+JOIN     part ON part_fts.rowid = part.id 
+WHERE    part_fts MATCH :search
+ORDER BY rank
+LIMIT    :limit;
+"#;
+
+		let entities: Vec<PartHit> = db.fetch_all(sql, named_params! { ":search": search, ":limit": limit })?;
+
+		Ok(entities)
+	}
+
+	/// Delete the parts of a ditem_ref, in the transaction of a `SlDb::exec_tx`.
+	/// Note: The `part_fts` rows are deleted by the `part_ad` trigger.
+	pub fn delete_for_ditem_ref_tx(tx: &Transaction, ditem_ref_id: i64) -> Result<usize> {
+		let count = tx
+			.execute(
+				"DELETE FROM part WHERE ditem_ref_id = :ditem_ref_id;",
+				&[(":ditem_ref_id", &ditem_ref_id)],
+			)
+			.map_err(store::Error::from)?;
+
+		Ok(count)
+	}
+
+	/// Returns the parts of a ditem_ref, in the line order.
+	pub async fn list_for_ditem_ref(db: &SlDb, ditem_ref_id: i64) -> Result<Vec<Part>> {
+		let columns: Vec<String> = Part::field_names().iter().map(|n| f!(r#""part"."{n}""#)).collect();
+		let columns = columns.join(",");
+
+		let sql = format!(
+			r#"
+SELECT   {columns}
+FROM     part 
+WHERE    part.ditem_ref_id = :ditem_ref_id
+ORDER BY part.line_num;
+"#
+		);
+
+		let entities: Vec<Part> = db.fetch_all(&sql, &[(":ditem_ref_id", &ditem_ref_id)])?;
+
+		Ok(entities)
+	}
 }
 
 // endregion: --- Bmc
@@ -0,0 +1,124 @@
+use crate::model::dfile_db::chunk::Chunk;
+use crate::model::support::prelude::*;
+use lib_utils::f;
+use rusqlite::named_params;
+
+// region:    --- Types
+
+/// The embedding vector of a chunk for an embed model.
+#[derive(Debug, Clone, Fields, FromSqliteRow, Serialize, Deserialize)]
+pub struct PartVec {
+	pub id: Id,
+
+	pub chunk_id: i64,
+
+	pub model: String,
+	pub dim: i64,
+	/// The `dim` f32 values, little endian (see `PartVec::values`).
+	pub vec: Vec<u8>,
+
+	pub ctime: UnixTimeUs,
+	pub mtime: UnixTimeUs,
+}
+
+#[derive(Debug, Clone, Fields, FromSqliteRow, Serialize, Deserialize)]
+pub struct PartVecForCreate {
+	pub chunk_id: i64,
+
+	pub model: String,
+	pub dim: i64,
+	pub vec: Vec<u8>,
+}
+
+impl PartVec {
+	pub fn values(&self) -> Vec<f32> {
+		vec_from_blob(&self.vec)
+	}
+}
+
+impl PartVecForCreate {
+	/// e.g., `PartVecForCreate::new(chunk_id, "ollama::nomic-embed-text", &vec)`
+	pub fn new(chunk_id: i64, model: impl Into<String>, values: &[f32]) -> Self {
+		Self {
+			chunk_id,
+			model: model.into(),
+			dim: values.len() as i64,
+			vec: vec_to_blob(values),
+		}
+	}
+}
+
+// endregion: --- Types
+
+// region:    --- Bmc
+
+pub struct PartVecBmc;
+
+impl DbBmc for PartVecBmc {
+	const TABLE: &'static str = "part_vec";
+
+	fn has_uid() -> bool {
+		false
+	}
+}
+
+generate_sldb_crud_fns!(
+	Bmc: PartVecBmc,
+	ForGet: PartVec,
+	ForCreate: PartVecForCreate,
+);
+
+impl PartVecBmc {
+	/// Returns the first `limit` chunks (by id) without a vector for the `model`.
+	pub async fn list_chunks_without_vec(db: &SlDb, model: &str, limit: i64) -> Result<Vec<Chunk>> {
+		let columns: Vec<String> = Chunk::field_names().iter().map(|n| f!(r#""chunk"."{n}""#)).collect();
+		let columns = columns.join(",");
+
+		let sql = format!(
+			r#"
+SELECT    {columns}
+FROM      chunk
+LEFT JOIN part_vec ON part_vec.chunk_id = chunk.id AND part_vec.model = :model
+WHERE     part_vec.id IS NULL
+ORDER BY  chunk.id
+LIMIT     :limit;
+"#
+		);
+
+		let entities: Vec<Chunk> = db.fetch_all(&sql, named_params! { ":model": model, ":limit": limit })?;
+
+		Ok(entities)
+	}
+}
+
+// endregion: --- Bmc
+
+// region:    --- Support
+
+pub fn vec_to_blob(values: &[f32]) -> Vec<u8> {
+	values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub fn vec_from_blob(blob: &[u8]) -> Vec<f32> {
+	blob.chunks_exact(4)
+		.map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+		.collect()
+}
+
+/// Returns the cosine similarity of two vectors (0 if not of the same dim, or if one is a zero vector).
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+	if a.len() != b.len() {
+		return 0.;
+	}
+
+	let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+	let norm_a = a.iter().map(|v| v * v).sum::<f32>().sqrt();
+	let norm_b = b.iter().map(|v| v * v).sum::<f32>().sqrt();
+	if norm_a == 0. || norm_b == 0. {
+		return 0.;
+	}
+
+	dot / (norm_a * norm_b)
+}
+
+// endregion: --- Support
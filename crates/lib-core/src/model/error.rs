@@ -4,6 +4,7 @@ use crate::model::ditem::DItemError;
 use crate::model::dsource::DSourceError;
 use crate::model::msg::MsgError;
 use crate::model::space::SpaceError;
+use crate::model::stack_step::StackStepError;
 use crate::model::{store, Id};
 use derive_more::From;
 use serde::Serialize;
@@ -54,6 +55,8 @@ pub enum Error {
 	// -- cfile_db Entities
 	#[from]
 	Msg(MsgError),
+	#[from]
+	StackStep(StackStepError),
 
 	// -- Space
 	NoSpaceFound,
@@ -2,6 +2,9 @@ use crate::model::support::prelude::*;
 use derive_more::Display;
 use modql::field::SeaFieldValue;
 use modql::FromSqliteValue;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 
 // region:    --- Types
 
@@ -38,6 +41,24 @@ pub struct Agent {
 	pub chain: Option<String>,
 
 	pub out_format: Option<OutFormat>,
+
+	/// Number of previous conversation messages sent with the agent request (None or 0 for none).
+	pub history_window: Option<i64>,
+
+	/// JSON of the retry policy for the failed runs (None for no retry).
+	/// e.g., `{"max_attempts": 3, "backoff_ms": 1000, "retry_on": ["network", "rate_limit", "server"]}`
+	pub retry_policy: Option<String>,
+
+	/// JSON array of the names of the tools the model can call while running (e.g., `["drive_search"]`).
+	/// Note: The tools are the logic tools (see `lib_ais::LogicToolRegistry`).
+	pub tools: Option<String>,
+
+	/// JSON of the retrieval of the space drives content for the prompt (None for no retrieval).
+	/// e.g., `{"limit": 20, "token_budget": 1000}` (see `lib_ais::runner::RetrievalConfig`).
+	pub retrieval: Option<String>,
+
+	/// The built-in tool run by a `AgentKind::Logic` agent (e.g., `list_files`).
+	pub logic_tool: Option<String>,
 }
 
 impl Agent {}
@@ -64,6 +85,8 @@ pub struct AgentForCreate {
 	pub inst: Option<String>,
 	pub prompt_tmpl: Option<String>,
 	pub out_format: Option<OutFormat>,
+
+	pub logic_tool: Option<String>,
 }
 
 impl AgentForCreate {
@@ -78,6 +101,7 @@ impl AgentForCreate {
 			inst: None,
 			prompt_tmpl: None,
 			out_format: None,
+			logic_tool: None,
 		}
 	}
 }
@@ -96,6 +120,11 @@ pub struct AgentForUpdate {
 	pub prompt_tmpl: Option<String>,
 	pub chain: Option<String>,
 	pub out_format: Option<OutFormat>,
+	pub history_window: Option<i64>,
+	pub retry_policy: Option<String>,
+	pub tools: Option<String>,
+	pub retrieval: Option<String>,
+	pub logic_tool: Option<String>,
 }
 
 #[derive(FilterNodes, Default, Deserialize)]
@@ -112,6 +141,16 @@ pub struct AgentFilter {
 
 // endregion: --- Types
 
+// region:    --- AgentChainCheck
+
+/// The check of the agent chain json, run by `AgentBmc::update` before saving a chain
+/// (set with `ModelManager::with_agent_chain_check`, e.g., `lib_ais::agent_chain_check()`).
+/// Note: A hook, as the chain types are in `lib-ais` (which depends on `lib-core`).
+pub type AgentChainCheck =
+	Arc<dyn Fn(ModelManager, Id, String) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync>;
+
+// endregion: --- AgentChainCheck
+
 // region:    --- AgentBmc
 
 pub struct AgentBmc;
@@ -125,21 +164,30 @@ gen_mm_crud_fns!(
 	ForGet: Agent,
 	ForGetByUid: Agent,
 	ForCreate: AgentForCreate,
-	ForUpdate: AgentForUpdate,
 );
 
 /// NOTE: Right now, just custom filter and first fo the filter Ai, but this my change later.
 impl AgentBmc {
+	/// Note: The chain (if any) gets checked first with the `ModelManager` agent chain check (if set).
+	pub async fn update(mm: &ModelManager, id: Id, agent_u: AgentForUpdate) -> Result<()> {
+		if let (Some(chain), Some(check)) = (agent_u.chain.as_ref(), mm.agent_chain_check()) {
+			check(mm.clone(), id, chain.to_string()).await?;
+		}
+		base::update::<Self, _>(mm.main_db(), id, agent_u).await
+	}
+
+	/// Returns the first agent with this name, of any kind (so that chains can reference `Logic` agents),
+	/// the `AgentKind::Ai` ones first.
 	pub async fn first_by_name(mm: &ModelManager, name: &str) -> Result<Option<Agent>> {
-		AgentBmc::first(
-			mm,
-			Some(vec![AgentFilter {
-				name: Some(name.to_string().into()),
-				..Default::default()
-			}]),
-			None,
-		)
-		.await
+		let filter = AgentFilter {
+			name: Some(name.to_string().into()),
+			..Default::default()
+		};
+		let list_options = ListOptions {
+			order_bys: Some("kind".into()),
+			..Default::default()
+		};
+		base::first::<Self, _, _>(mm.main_db(), Some(vec![filter]), Some(list_options)).await
 	}
 	pub async fn list(
 		mm: &ModelManager,
@@ -198,7 +246,14 @@ pub type AgentResult<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, Serialize)]
 pub enum AgentError {
-	SystemAgentNotFound { name: String },
+	SystemAgentNotFound {
+		name: String,
+	},
+	/// The `diagnostics` are the ones of the agent chain check (e.g., `lib_ais::ChainDiagnostic` list).
+	ChainInvalid {
+		agent_id: Id,
+		diagnostics: serde_json::Value,
+	},
 }
 
 // region:    --- Error Boilerplate
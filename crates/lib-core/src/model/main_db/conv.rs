@@ -2,7 +2,9 @@ use crate::event::ConvEvent;
 use crate::model::cfile::CFileBmc;
 use crate::model::cfile_db::conv_ref::ConvRefBmc;
 use crate::model::cfile_db::msg::{Msg, MsgBmc, MsgFilter, MsgForCreate};
-use crate::model::stack_step::{StackStep, StackStepBmc, StackStepFilter, StackStepLite};
+use crate::model::stack_step::{
+	OrphanStepPolicy, StackStep, StackStepBmc, StackStepFilter, StackStepLite, StepUsage, UsageRange,
+};
 use crate::model::support::prelude::*;
 use derive_more::From;
 use lib_utils::time::now;
@@ -80,6 +82,7 @@ impl DbBmc for ConvBmc {
 gen_mm_crud_fns!(
 	Bmc: ConvBmc,
 	ForGet: Conv,
+	ForGetByUid: Conv,
 	ForCreate: ConvForCreate,
 	ForUpdate: ConvForUpdate,
 	ForList: Conv,
@@ -245,6 +248,52 @@ WHERE orig_msg_id IN (
 		Ok(step)
 	}
 
+	/// Returns the run usage of this conversation, per agent and model, for the given time range.
+	pub async fn usage_report(mm: &ModelManager, conv_id: Id, range: &UsageRange) -> Result<Vec<StepUsage>> {
+		let conv = ConvBmc::get(mm, conv_id).await?;
+		// Note: No cfile yet means no steps yet.
+		if conv.cfile_id.is_none() {
+			return Ok(Vec::new());
+		}
+		let cfile_db = CFileBmc::getc_cfile_db_for_conv(mm, &conv).await?;
+
+		StackStepBmc::usage_for_conv(&cfile_db, &conv.uid, range).await
+	}
+
+	/// Retry a failed step of this conversation, by cloning it into a new step queued for work.
+	/// The failed step stays in the step history. Returns the retry step id.
+	pub async fn retry_step(mm: &ModelManager, conv_id: Id, step_id: Id) -> Result<Id> {
+		let cfile_db = ConvBmc::getc_cfile_db_for_conv_id(mm, conv_id).await?;
+		let retry_step_id = StackStepBmc::create_retry_from_step(&cfile_db, step_id, None).await?;
+
+		Self::touch_work_tnew(mm, conv_id).await?;
+
+		Ok(retry_step_id)
+	}
+
+	/// Recover the orphan steps of all the cfile dbs (e.g., steps interrupted by an app quit),
+	/// according to the policy. Returns the ids of the convs with recovered steps.
+	/// Note: The caller is responsible to touch the work of those convs.
+	pub async fn recover_orphan_steps(mm: &ModelManager, policy: OrphanStepPolicy) -> Result<Vec<Id>> {
+		let mut conv_ids: Vec<Id> = Vec::new();
+
+		for cfile in CFileBmc::list(mm, None, None).await? {
+			let cfile_db = mm.cfile_db(&cfile.uid).await?;
+
+			for step in StackStepBmc::list_orphans(&cfile_db).await? {
+				StackStepBmc::recover_orphan(&cfile_db, &step, policy).await?;
+
+				let conv_uid = MsgBmc::get_conv_uid(&cfile_db, step.orig_msg_id).await?;
+				let conv = Self::get_by_uid(mm, &conv_uid).await?;
+				if !conv_ids.contains(&conv.id) {
+					conv_ids.push(conv.id);
+				}
+			}
+		}
+
+		Ok(conv_ids)
+	}
+
 	// pub async seek
 
 	// region:    --- TWork
@@ -273,6 +322,29 @@ WHERE orig_msg_id IN (
 		Ok(())
 	}
 
+	/// Cancel the work of the conversation.
+	/// - The unfinished steps are marked as cancelled (`run_tcancel`), so they will not be resolved or ran.
+	/// - Their orig msgs get closed with a cancelled agent answer.
+	/// - `ConvEvent::ConvWorkCancel` is published for the in-flight run to abort.
+	pub async fn cancel_work(mm: &ModelManager, conv_id: Id) -> Result<()> {
+		let conv = Self::get(mm, conv_id).await?;
+		// Note: No cfile yet means no work yet.
+		if conv.cfile_id.is_none() {
+			return Ok(());
+		}
+		let cfile_db = Self::getc_cfile_db(mm, &conv).await?;
+
+		let orig_msg_ids = StackStepBmc::cancel_unfinished_for_conv(&cfile_db, &conv.uid).await?;
+		for orig_msg_id in orig_msg_ids {
+			MsgBmc::create_agent_cancelled(&cfile_db, orig_msg_id).await?;
+		}
+
+		mm.hub().publish(ConvEvent::ConvWorkCancel { conv_id }).await;
+		Self::touch_work_tdone(mm, conv_id).await?;
+
+		Ok(())
+	}
+
 	pub async fn touch_work_both(mm: &ModelManager, conv_id: Id) -> Result<()> {
 		let now = now();
 
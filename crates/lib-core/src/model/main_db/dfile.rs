@@ -89,6 +89,45 @@ impl DFileBmc {
 		Ok(db)
 	}
 
+	/// Returns the DFiles of the dsources of the space drives (through `space_drive` -> `dsource` -> `dfile`).
+	pub async fn list_for_space(mm: &ModelManager, space_id: Id) -> Result<Vec<DFile>> {
+		let columns: Vec<String> = DFile::field_names().iter().map(|n| format!(r#""dfile"."{n}""#)).collect();
+		let columns = columns.join(",");
+		let sql = format!(
+			r#"
+SELECT DISTINCT {columns}
+FROM     dfile 
+JOIN     dsource     ON dsource.id          = dfile.main_dsource_id 
+JOIN     space_drive ON space_drive.drive_id = dsource.drive_id 
+WHERE    space_drive.space_id = :space_id
+ORDER BY dfile.id;
+"#
+		);
+
+		let entities: Vec<DFile> = mm.main_db().fetch_all(&sql, &[(":space_id", &*space_id)])?;
+
+		Ok(entities)
+	}
+
+	/// Returns the DFiles of the drive dsources (through `dsource` -> `dfile`).
+	pub async fn list_for_drive(mm: &ModelManager, drive_id: Id) -> Result<Vec<DFile>> {
+		let columns: Vec<String> = DFile::field_names().iter().map(|n| format!(r#""dfile"."{n}""#)).collect();
+		let columns = columns.join(",");
+		let sql = format!(
+			r#"
+SELECT DISTINCT {columns}
+FROM     dfile 
+JOIN     dsource ON dsource.id = dfile.main_dsource_id 
+WHERE    dsource.drive_id = :drive_id
+ORDER BY dfile.id;
+"#
+		);
+
+		let entities: Vec<DFile> = mm.main_db().fetch_all(&sql, &[(":drive_id", &*drive_id)])?;
+
+		Ok(entities)
+	}
+
 	/// Returns the list of distinct DFiles for a given dsource.
 	pub async fn list_dfiles_for_dsource(mm: &ModelManager, dsource_id: Id) -> Result<Vec<DFile>> {
 		// -- Build query
@@ -48,6 +48,8 @@ pub struct DItem {
 	pub file_ext: Option<String>,
 
 	pub proc_time: Option<UnixTimeUs>,
+	/// The sha256 (b64u) of the file content when processed (to skip the unchanged files).
+	pub content_hash: Option<String>,
 	pub dfile_id: Option<Id>,
 
 	pub ctime: UnixTimeUs,
@@ -87,6 +89,8 @@ pub struct DItemForUpdate {
 	pub file_path: Option<String>,
 	pub file_mtime: Option<UnixTimeUs>,
 	pub file_size: Option<i64>,
+	pub proc_time: Option<UnixTimeUs>,
+	pub content_hash: Option<String>,
 }
 
 #[derive(FilterNodes, Default, Deserialize)]
@@ -199,6 +203,47 @@ impl DItemBmc {
 		Ok(entities)
 	}
 
+	/// List the DItems of the dsources of the drives attached to a space (ordered by file_path).
+	pub async fn list_for_space(mm: &ModelManager, space_id: Id) -> Result<Vec<DItem>> {
+		let columns: Vec<String> = DItem::field_names().iter().map(|n| format!(r#""ditem"."{n}""#)).collect();
+		let columns = columns.join(",");
+		let sql = format!(
+			r#"
+SELECT DISTINCT {columns}
+FROM     ditem 
+JOIN     ditem_dsource ON ditem.id          = ditem_dsource.ditem_id 
+JOIN     dsource       ON dsource.id        = ditem_dsource.dsource_id 
+JOIN     space_drive   ON space_drive.drive_id = dsource.drive_id 
+WHERE    space_drive.space_id = :space_id
+ORDER BY ditem.file_path;
+"#
+		);
+
+		let entities: Vec<DItem> = mm.main_db().fetch_all(&sql, &[(":space_id", &*space_id)])?;
+
+		Ok(entities)
+	}
+
+	/// List the DItems of the dsources of a drive (ordered by file_path).
+	pub async fn list_for_drive(mm: &ModelManager, drive_id: Id) -> Result<Vec<DItem>> {
+		let columns: Vec<String> = DItem::field_names().iter().map(|n| format!(r#""ditem"."{n}""#)).collect();
+		let columns = columns.join(",");
+		let sql = format!(
+			r#"
+SELECT DISTINCT {columns}
+FROM     ditem 
+JOIN     ditem_dsource ON ditem.id   = ditem_dsource.ditem_id 
+JOIN     dsource       ON dsource.id = ditem_dsource.dsource_id 
+WHERE    dsource.drive_id = :drive_id
+ORDER BY ditem.file_path;
+"#
+		);
+
+		let entities: Vec<DItem> = mm.main_db().fetch_all(&sql, &[(":drive_id", &*drive_id)])?;
+
+		Ok(entities)
+	}
+
 	// pub async fn list_d
 	pub async fn list_ditems_to_proc_for_dsource(mm: &ModelManager, dsource_id: Id) -> Result<Vec<DItem>> {
 		let columns: Vec<String> = DItem::field_names().iter().map(|n| format!(r#""ditem"."{n}""#)).collect();
@@ -2,6 +2,7 @@ use crate::model::agent::{Agent, AgentBmc};
 use crate::model::conv::{Conv, ConvBmc, ConvFilter, ConvForCreate};
 use crate::model::drive::{Drive, DriveBmc, DriveForCreate};
 use crate::model::space_drive::{SpaceDrive, SpaceDriveBmc, SpaceDriveFilter, SpaceDriveForCreateRec};
+use crate::model::stack_step::{StepUsage, UsageRange};
 use crate::model::support::prelude::*;
 use modql::filter::{OpValBool, OpValInt64};
 
@@ -168,6 +169,26 @@ impl SpaceBmc {
 		Ok(drive)
 	}
 
+	/// Returns the run usage of all the conversations of this space, per conversation, agent and model.
+	pub async fn usage_report(mm: &ModelManager, space_id: Id, range: &UsageRange) -> Result<Vec<StepUsage>> {
+		let convs = ConvBmc::list(
+			mm,
+			Some(vec![ConvFilter {
+				space_id: Some(space_id.as_i64().into()),
+				..Default::default()
+			}]),
+			None,
+		)
+		.await?;
+
+		let mut usages = Vec::new();
+		for conv in convs {
+			usages.extend(ConvBmc::usage_report(mm, conv.id, range).await?);
+		}
+
+		Ok(usages)
+	}
+
 	// region:    --- Agent Related
 
 	pub async fn set_agent(mm: &ModelManager, space_id: Id, agent_id: Id) -> Result<()> {
@@ -1,5 +1,6 @@
 use crate::event::Hub;
 use crate::event::{ModelEvent, Publisher};
+use crate::model::agent::AgentChainCheck;
 use crate::model::store::db_sqlite::{self, init_db, SlDb};
 use crate::model::{DbType, Result};
 use std::collections::HashMap;
@@ -21,6 +22,8 @@ pub struct ModelManager {
 	main_db: SlDb,
 
 	sec_dbs: Arc<Mutex<HashMap<SecondaryDbKey, SlDb>>>,
+
+	agent_chain_check: Option<AgentChainCheck>,
 }
 
 impl ModelManager {
@@ -33,9 +36,16 @@ impl ModelManager {
 			hub,
 			main_db,
 			sec_dbs: Default::default(),
+			agent_chain_check: None,
 		})
 	}
 
+	/// Set the check of the agent chains, run by `AgentBmc::update` (see `AgentChainCheck`).
+	pub fn with_agent_chain_check(mut self, check: AgentChainCheck) -> Self {
+		self.agent_chain_check = Some(check);
+		self
+	}
+
 	pub fn hub(&self) -> &Hub {
 		&self.hub
 	}
@@ -44,6 +54,10 @@ impl ModelManager {
 		&self.main_db
 	}
 
+	pub fn agent_chain_check(&self) -> Option<&AgentChainCheck> {
+		self.agent_chain_check.as_ref()
+	}
+
 	pub(in crate::model) async fn dfile_db(&self, uid: &str) -> Result<SlDb> {
 		let db = self.sec_db(DbType::DFile, uid).await?;
 		Ok(db)
@@ -47,6 +47,10 @@ CREATE TABLE IF NOT EXISTS stack_step (
   first_step_id  INTEGER,                    -- The initial step (will point to self.id if it is the first step)
   prev_step_id   INTEGER,                    -- The previous step (if not first step)
   closer         INTEGER NOT NULL DEFAULT 0, -- Is the closing step (empty stack, no output)
+
+  -- Retry (a retry step is a clone of the failed step, which stays in the history)
+  retry_of_step_id INTEGER,                  -- The failed step this step is a retry of
+  attempt          INTEGER NOT NULL DEFAULT 1,
   
   resolve_tstart INTEGER, -- When it has been takend to be resolved
   resolve_tend   INTEGER, -- When the resolve was completed
@@ -55,9 +59,16 @@ CREATE TABLE IF NOT EXISTS stack_step (
   run_agent_uid  TEXT,    -- The agent that it was run with (should match the call stack, just for record)
   run_agent_name TEXT,    -- #cache# the name of the agent (to avoid join)
 
+  run_tafter     INTEGER, -- If set, the step should not be ran before (e.g., retry backoff)
   run_tstart     INTEGER, -- When the run started
   run_tend       INTEGER, -- When the run completed
   run_terr       INTEGER, -- If there is an error
+  run_tcancel    INTEGER, -- If the run was cancelled (before or while running)
+  run_model      TEXT,    -- The provider model the run was made with (e.g., "ollama::mixtral")
+
+  -- Usage (as returned by the provider, null if not reported)
+  usage_prompt_tokens     INTEGER,
+  usage_completion_tokens INTEGER,
 
   -- Call Ctx
   call_stack    TEXT,   -- JSON for the chain call stack for this step (e.g., items [{cursor, agent_uuid}, ...] )
@@ -70,3 +81,27 @@ CREATE TABLE IF NOT EXISTS stack_step (
 
   FOREIGN KEY (orig_msg_id) REFERENCES msg(id) ON DELETE CASCADE
 ) STRICT;
+
+-- The tool calls of the agent model while running a step (in the call order)
+CREATE TABLE IF NOT EXISTS step_tool_call (
+  id             INTEGER PRIMARY KEY AUTOINCREMENT,
+
+  step_id        INTEGER NOT NULL,
+
+  call_id        TEXT,          -- The provider call id (matching the tool result to the call)
+  fn_name        TEXT NOT NULL,
+  fn_arguments   TEXT,          -- JSON, as generated by the model
+
+  result         TEXT,          -- JSON, the tool result (if success)
+  err            TEXT,          -- The tool error (if fail)
+
+  tstart         INTEGER,
+  tend           INTEGER,
+
+  -- timestamps
+  ctime          INTEGER,
+  mtime          INTEGER,
+
+  FOREIGN KEY (step_id) REFERENCES stack_step(id) ON DELETE CASCADE
+) STRICT;
+CREATE INDEX IF NOT EXISTS idx_step_tool_call_step_id ON step_tool_call(step_id);
@@ -35,6 +35,49 @@ CREATE TABLE IF NOT EXISTS part (
 ) STRICT;
 
 
+-- The consecutive parts (lines) of a heading group, bounded in size (see `lib_splitters::chunk_parts`)
+CREATE TABLE IF NOT EXISTS chunk (
+  id           INTEGER PRIMARY KEY AUTOINCREMENT,
+
+  ditem_ref_id INTEGER NOT NULL,
+
+  "group"      INTEGER NOT NULL DEFAULT 0,
+  line_start   INTEGER NOT NULL,
+  line_end     INTEGER NOT NULL,
+  -- The heading titles, outer first, joined with ` > ` (e.g., `Intro > Setup`)
+  breadcrumb   TEXT NOT NULL DEFAULT '',
+  content      TEXT,
+
+  -- timestamps
+  ctime     INTEGER,
+  mtime     INTEGER,
+
+  FOREIGN KEY (ditem_ref_id) REFERENCES ditem_ref(id) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX IF NOT EXISTS idx_chunk_ditem_ref_id ON chunk(ditem_ref_id);
+
+-- The embedding vector of the parts, per chunk (see `chunk`), for a given embed model
+-- Note: `vec` is the `dim` f32 values in little endian.
+CREATE TABLE IF NOT EXISTS part_vec (
+  id           INTEGER PRIMARY KEY AUTOINCREMENT,
+
+  chunk_id     INTEGER NOT NULL,
+
+  model        TEXT NOT NULL,
+  dim          INTEGER NOT NULL,
+  vec          BLOB NOT NULL,
+
+  -- timestamps
+  ctime     INTEGER,
+  mtime     INTEGER,
+
+  FOREIGN KEY (chunk_id) REFERENCES chunk(id) ON DELETE CASCADE,
+  UNIQUE (chunk_id, model)
+) STRICT;
+
+CREATE INDEX IF NOT EXISTS idx_part_vec_model ON part_vec(model);
+
 -- `content='part'` option in fts5 means the content is from the part table (safe sapce)
 -- Note: here the `fts5` option name `content` is the same as the column name, but just coincidence. 
 CREATE VIRTUAL TABLE IF NOT EXISTS part_fts USING fts5(content, content='part');
@@ -46,12 +89,12 @@ END;
 
 -- -- Trigger to update data in FTS table when updating the main table
 CREATE TRIGGER part_au AFTER UPDATE ON part BEGIN
-  INSERT INTO part_fts(part_fts, rowid, content, uid) VALUES('delete', old.id, old.content);
-  INSERT INTO part_fts(rowid, content, uid) VALUES (new.id, new.content);
+  INSERT INTO part_fts(part_fts, rowid, content) VALUES('delete', old.id, old.content);
+  INSERT INTO part_fts(rowid, content) VALUES (new.id, new.content);
 END;
 
 -- Trigger to delete data from FTS table when deleting from the main table
 CREATE TRIGGER part_ad AFTER DELETE ON part BEGIN
-  INSERT INTO part_fts(part_fts, rowid, content, uid) VALUES('delete', old.id, old.content);
+  INSERT INTO part_fts(part_fts, rowid, content) VALUES('delete', old.id, old.content);
 END;
 
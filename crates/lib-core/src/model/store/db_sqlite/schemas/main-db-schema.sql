@@ -17,6 +17,10 @@ CREATE TABLE IF NOT EXISTS agent (
   prompt_tmpl      TEXT,
   chain            TEXT, -- json, might become blob for jsonb
   out_format       TEXT, -- "Text" | "Json"
+  history_window   INTEGER, -- Number of previous conv messages sent with the request (null/0 for none)
+  retry_policy     TEXT, -- json, e.g., {"max_attempts": 3, "backoff_ms": 1000, "retry_on": ["network"]}
+  tools            TEXT, -- json, the names of the tools the model can call, e.g., ["drive_search"]
+  retrieval        TEXT, -- json, the drive content retrieval for the prompt, e.g., {"limit": 20, "token_budget": 1000}
 
   -- Logic Props
   logic_tool       TEXT, -- e.g. "list_files"
@@ -156,6 +160,7 @@ CREATE TABLE IF NOT EXISTS ditem (
 
   -- DFile props
   proc_time         INTEGER, -- When ditem was profile. Nothing to do !NULL or > file_mtime
+  content_hash      TEXT,    -- The sha256 (b64u) of the file content when processed
   dfile_id          INTEGER,
 
   -- timestamps (unix_utc_us)
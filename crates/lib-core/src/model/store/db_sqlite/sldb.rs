@@ -3,7 +3,7 @@ use crate::model::store::{Error, Result};
 use crate::model::ModelPublisher;
 use modql::FromSqliteRow;
 use rusqlite::types::FromSql;
-use rusqlite::{Connection, OptionalExtension, Params};
+use rusqlite::{Connection, OptionalExtension, Params, Transaction};
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
@@ -118,6 +118,36 @@ impl SlDb {
 	}
 }
 
+// Public transaction api
+impl SlDb {
+	/// Run `f` in a transaction, committed if `f` returns Ok, and rolled back otherwise.
+	///
+	/// Note: The connection is locked for the whole transaction, so the other execs of this SlDb
+	///       wait for it to end (and `f` is sync, so it cannot await while holding the lock).
+	pub fn exec_tx<T, E>(
+		&self,
+		f: impl FnOnce(&Transaction) -> core::result::Result<T, E>,
+	) -> core::result::Result<T, E>
+	where
+		E: From<crate::model::Error>,
+	{
+		self.assert_can_write("BEGIN").map_err(model_err)?;
+
+		let mut conn_g = self.conn.lock().map_err(model_err)?;
+		let tx = conn_g.transaction().map_err(model_err)?;
+
+		// Note: The transaction is rolled back when dropped without commit (i.e., on `f` error).
+		let res = f(&tx)?;
+		tx.commit().map_err(model_err)?;
+
+		Ok(res)
+	}
+}
+
+fn model_err(err: impl Into<Error>) -> crate::model::Error {
+	crate::model::Error::from(err.into())
+}
+
 // Public publish event
 impl SlDb {
 	pub async fn publish(&self, evt: ModelEvent) {
@@ -161,3 +191,48 @@ mod test_support {
 		}
 	}
 }
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use crate::model::ModelManager;
+	use std::time::Duration;
+
+	#[tokio::test]
+	async fn test_sldb_exec_tx_rollback_isolated() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let db = mm.main_db().clone();
+		db.exec("CREATE TABLE tx_test (val INTEGER)", [])?;
+
+		// -- Exec
+		// Note: The transaction inserts, holds, and fails (so, rolled back), while another thread counts.
+		let (count, tx_res) = std::thread::scope(|scope| {
+			let tx_db = db.clone();
+			let tx_thread = scope.spawn(move || {
+				tx_db.exec_tx(|tx| -> core::result::Result<(), crate::model::Error> {
+					tx.execute("INSERT INTO tx_test (val) VALUES (1)", [])
+						.map_err(super::Error::from)?;
+					std::thread::sleep(Duration::from_millis(200));
+					Err(crate::model::Error::from("fail"))
+				})
+			});
+			std::thread::sleep(Duration::from_millis(50));
+			let count = db.exec_returning_num("SELECT COUNT(*) FROM tx_test", []);
+			let tx_res = tx_thread.join();
+			(count, tx_res)
+		});
+
+		// -- Check
+		assert!(matches!(tx_res, Ok(Err(_))), "Should have the transaction failed");
+		assert_eq!(count?, 0, "Should not see the uncommitted insert");
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
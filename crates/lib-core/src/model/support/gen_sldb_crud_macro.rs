@@ -18,6 +18,11 @@ macro_rules! generate_sldb_crud_fns {
                 pub async fn create(db: &SlDb, entity_c: $for_create) -> Result<Id> {
                     base::create::<Self, _>(db, entity_c).await
                 }
+
+                /// Create in the transaction of a `SlDb::exec_tx` (without the create event).
+                pub fn create_tx(tx: &rusqlite::Transaction, entity_c: $for_create) -> Result<Id> {
+                    base::create_tx::<Self, _>(tx, entity_c)
+                }
             )?
             $(
                 pub async fn get(db: &SlDb, id: Id) -> Result<$for_get> {
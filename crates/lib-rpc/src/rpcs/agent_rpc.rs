@@ -1,5 +1,10 @@
 use crate::rpcs::prelude::*;
+use lib_ais::runner::{simulate_agent_chain, ChainSimOutputs, ChainSimTrace};
+use lib_ais::{validate_agent_chain, ChainDiagnostic};
 use lib_core::model::agent::{Agent, AgentBmc, AgentFilter, AgentForCreate, AgentForUpdate, AgentLite};
+use lib_core::model::Id;
+use rpc_router::RpcParams;
+use serde::{Deserialize, Serialize};
 
 pub fn router_builder() -> RouterBuilder {
 	router_builder!(
@@ -9,9 +14,13 @@ pub fn router_builder() -> RouterBuilder {
 		agent_list,
 		agent_update,
 		agent_delete,
+		// -- Customs
+		agent_validate_chain,
+		agent_simulate_chain,
 	)
 }
 
+// Note: The chain gets validated by `AgentBmc::update` (see `lib_ais::agent_chain_check`).
 gen_rpc_crud_fns!(
 	Bmc: AgentBmc,
 	Entity: Agent,
@@ -21,3 +30,36 @@ gen_rpc_crud_fns!(
 	Filter: AgentFilter,
 	Suffix: agent
 );
+
+#[derive(Serialize, Deserialize, RpcParams)]
+pub struct ParamsValidateChain {
+	/// None for a new agent.
+	agent_id: Option<i64>,
+	chain: String,
+}
+
+/// Returns the chain diagnostics (empty if the chain is valid), without saving anything.
+async fn agent_validate_chain(
+	mm: ModelManager,
+	params: ParamsValidateChain,
+) -> Result<DataRpcResult<Vec<ChainDiagnostic>>> {
+	let agent_id = params.agent_id.map(Id::from);
+	let diagnostics = validate_agent_chain(&mm, agent_id, &params.chain).await?;
+	Ok(diagnostics.into())
+}
+
+#[derive(Serialize, Deserialize, RpcParams)]
+pub struct ParamsSimulateChain {
+	agent_id: i64,
+	input: String,
+	/// The canned outputs by agent name (see `ChainSimOutputs`).
+	#[serde(default)]
+	outputs: ChainSimOutputs,
+}
+
+/// Walks the agent chain with canned (or mocked) agent outputs, and returns the trace
+/// of the visited agents, cursors, and matched branch arms, without running or saving anything.
+async fn agent_simulate_chain(mm: ModelManager, params: ParamsSimulateChain) -> Result<DataRpcResult<ChainSimTrace>> {
+	let trace = simulate_agent_chain(&mm, params.agent_id.into(), params.input, &params.outputs).await?;
+	Ok(trace.into())
+}
@@ -2,7 +2,7 @@ use crate::rpcs::prelude::*;
 
 use lib_core::model::conv::{Conv, ConvBmc, ConvFilter, ConvForCreate, ConvForUpdate, ConvMsg};
 use lib_core::model::msg::Msg;
-use lib_core::model::stack_step::{StackStep, StackStepLite};
+use lib_core::model::stack_step::{StackStep, StackStepLite, StepUsage, UsageRange};
 use lib_core::model::Id;
 use rpc_router::RpcParams;
 use serde::{Deserialize, Serialize};
@@ -19,6 +19,9 @@ pub fn router_builder() -> RouterBuilder {
 		conv_list_steps,
 		conv_get_step,
 		conv_clear_all,
+		conv_cancel,
+		conv_retry_step,
+		conv_usage,
 	)
 }
 
@@ -44,6 +47,13 @@ async fn conv_clear_all(mm: ModelManager, conv_id: ParamsIded) -> Result<DataRpc
 	Ok(().into())
 }
 
+/// Cancel the in-flight work of the conversation (see `ConvBmc::cancel_work`).
+async fn conv_cancel(mm: ModelManager, conv_id: ParamsIded) -> Result<DataRpcResult<()>> {
+	let conv_id: Id = conv_id.id.into();
+	ConvBmc::cancel_work(&mm, conv_id).await?;
+	Ok(().into())
+}
+
 #[derive(Serialize, Deserialize, RpcParams)]
 pub struct ParamsListSteps {
 	conv_id: i64,
@@ -65,3 +75,33 @@ async fn conv_get_step(mm: ModelManager, params: ParamsGetStep) -> Result<DataRp
 	let step = ConvBmc::get_step(&mm, params.conv_id.into(), params.step_id.into()).await?;
 	Ok(step.into())
 }
+
+#[derive(Serialize, Deserialize, RpcParams)]
+pub struct ParamsRetryStep {
+	conv_id: i64,
+	step_id: i64,
+}
+
+/// Retry a failed step (see `ConvBmc::retry_step`), returns the new step.
+async fn conv_retry_step(mm: ModelManager, params: ParamsRetryStep) -> Result<DataRpcResult<StackStep>> {
+	let conv_id: Id = params.conv_id.into();
+	let retry_step_id = ConvBmc::retry_step(&mm, conv_id, params.step_id.into()).await?;
+	let step = ConvBmc::get_step(&mm, conv_id, retry_step_id).await?;
+	Ok(step.into())
+}
+
+#[derive(Serialize, Deserialize, RpcParams)]
+pub struct ParamsConvUsage {
+	conv_id: i64,
+	from: Option<i64>,
+	to: Option<i64>,
+}
+
+async fn conv_usage(mm: ModelManager, params: ParamsConvUsage) -> Result<DataRpcResult<Vec<StepUsage>>> {
+	let range = UsageRange {
+		from: params.from.map(Into::into),
+		to: params.to.map(Into::into),
+	};
+	let usages = ConvBmc::usage_report(&mm, params.conv_id.into(), &range).await?;
+	Ok(usages.into())
+}
@@ -1,7 +1,11 @@
 use crate::rpcs::prelude::*;
 
+use lib_ais::search::{drive_search as search_drive, DriveSearchHit, DriveSearchMode, DRIVE_SEARCH_LIMIT_DEFAULT};
+use lib_ais::AiManager;
 use lib_core::model::drive::{Drive, DriveBmc, DriveFilter, DriveForCreate, DriveForUpdate};
 use lib_core::model::dsource::{DSource, DSourceBmc, DSourceForCreate};
+use rpc_router::RpcParams;
+use serde::{Deserialize, Serialize};
 
 pub fn router_builder() -> RouterBuilder {
 	router_builder!(
@@ -12,6 +16,7 @@ pub fn router_builder() -> RouterBuilder {
 		drive_delete,
 		// -- Customs
 		drive_add_dsource,
+		drive_search,
 	)
 }
 
@@ -33,3 +38,24 @@ async fn drive_add_dsource(
 	let dsource = DSourceBmc::get(&mm, dsource_id).await?;
 	Ok(dsource.into())
 }
+
+#[derive(Serialize, Deserialize, RpcParams)]
+pub struct ParamsDriveSearch {
+	drive_id: i64,
+	query: String,
+	/// `fts`, `similar`, or `hybrid` (the default).
+	#[serde(default)]
+	mode: DriveSearchMode,
+	limit: Option<usize>,
+}
+
+/// Search the parts of the drive files, across all of the drive dfile dbs (see `DriveSearchMode`).
+async fn drive_search(
+	mm: ModelManager,
+	aim: AiManager,
+	params: ParamsDriveSearch,
+) -> Result<DataRpcResult<Vec<DriveSearchHit>>> {
+	let limit = params.limit.unwrap_or(DRIVE_SEARCH_LIMIT_DEFAULT);
+	let hits = search_drive(&mm, &aim, params.drive_id.into(), &params.query, params.mode, limit).await?;
+	Ok(hits.into())
+}
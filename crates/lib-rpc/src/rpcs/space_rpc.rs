@@ -3,6 +3,9 @@ use lib_core::model::agent::Agent;
 use lib_core::model::conv::Conv;
 use lib_core::model::drive::Drive;
 use lib_core::model::space::{Space, SpaceBmc, SpaceFilter, SpaceForCreate, SpaceForUpdate};
+use lib_core::model::stack_step::{StepUsage, UsageRange};
+use rpc_router::RpcParams;
+use serde::{Deserialize, Serialize};
 
 pub fn router_builder() -> RouterBuilder {
 	router_builder!(
@@ -16,7 +19,8 @@ pub fn router_builder() -> RouterBuilder {
 		space_get_latest,
 		space_get_default_drive,
 		space_get_latest_conv,
-		space_seek_agent
+		space_seek_agent,
+		space_usage
 	)
 }
 
@@ -50,3 +54,19 @@ async fn space_get_default_drive(mm: ModelManager, spaced: ParamsIded) -> Result
 
 	Ok(drive.into())
 }
+
+#[derive(Serialize, Deserialize, RpcParams)]
+pub struct ParamsSpaceUsage {
+	space_id: i64,
+	from: Option<i64>,
+	to: Option<i64>,
+}
+
+async fn space_usage(mm: ModelManager, params: ParamsSpaceUsage) -> Result<DataRpcResult<Vec<StepUsage>>> {
+	let range = UsageRange {
+		from: params.from.map(Into::into),
+		to: params.to.map(Into::into),
+	};
+	let usages = SpaceBmc::usage_report(&mm, params.space_id.into(), &range).await?;
+	Ok(usages.into())
+}
@@ -0,0 +1,216 @@
+use crate::SplitPart;
+
+/// The approximate number of chars per token, to bound the chunks in tokens.
+const CHARS_PER_TOKEN: usize = 4;
+
+// region:    --- Types
+
+#[derive(Debug, Clone)]
+pub struct ChunkerConfig {
+	/// The max number of tokens of a chunk (approximated at 4 chars per token).
+	/// Note: A single line over the limit is a chunk on its own (lines are never split).
+	pub max_tokens: usize,
+	/// The number of tokens of the last lines of a chunk repeated at the start of the next chunk
+	/// of the same group (whole lines only, never across headings).
+	pub overlap_tokens: usize,
+}
+
+impl Default for ChunkerConfig {
+	fn default() -> Self {
+		Self {
+			max_tokens: 256,
+			overlap_tokens: 32,
+		}
+	}
+}
+
+/// Consecutive lines of the same heading `group`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+	pub group: i64,
+	pub line_start: i64,
+	pub line_end: i64,
+	/// The titles of the headings of the chunk, outer first, without the `#` (e.g., `["Intro", "Setup"]`).
+	pub breadcrumb: Vec<String>,
+	pub content: String,
+}
+
+// endregion: --- Types
+
+/// Merge the consecutive parts of the same heading `group` into chunks bounded by `config.max_tokens`,
+/// with `config.overlap_tokens` of overlap between the chunks of a group.
+///
+/// Note: The blank lines at the start and end of a chunk are skipped, and the chunks without lines
+///       other than their overlap ones are dropped.
+pub fn chunk_parts(parts: &[SplitPart], config: &ChunkerConfig) -> Vec<Chunk> {
+	let max_chars = config.max_tokens * CHARS_PER_TOKEN;
+	let overlap_chars = config.overlap_tokens * CHARS_PER_TOKEN;
+
+	let mut chunks = Vec::new();
+	let mut headings: Vec<(i64, String)> = Vec::new();
+	let mut current: Option<ChunkBuilder> = None;
+
+	for part in parts {
+		// -- Heading boundary
+		if part.is_title {
+			headings.retain(|(level, _)| *level < part.level);
+			headings.push((part.level, part.content.trim_start_matches('#').trim().to_string()));
+		}
+		if current.as_ref().is_some_and(|c| c.group != part.group) {
+			chunks.extend(current.take().and_then(ChunkBuilder::build));
+		}
+
+		let builder = current.get_or_insert_with(|| ChunkBuilder::new(part.group, &headings));
+		if builder.lines.is_empty() && part.content.trim().is_empty() {
+			continue;
+		}
+
+		// -- Size boundary (the next chunk starts with the overlap lines)
+		if !builder.lines.is_empty() && builder.chars + line_chars(&part.content) > max_chars {
+			let overlap = builder.overlap_lines(overlap_chars);
+			let mut next = ChunkBuilder::new(part.group, &headings);
+			next.overlap_count = overlap.len();
+			for (line_num, content) in overlap {
+				next.push(line_num, content);
+			}
+			chunks.extend(current.replace(next).and_then(ChunkBuilder::build));
+		}
+
+		if let Some(builder) = current.as_mut() {
+			builder.push(part.line_num, part.content.to_string());
+		}
+	}
+	chunks.extend(current.and_then(ChunkBuilder::build));
+
+	chunks
+}
+
+// region:    --- Support
+
+struct ChunkBuilder {
+	group: i64,
+	breadcrumb: Vec<String>,
+	lines: Vec<(i64, String)>,
+	/// The number of first lines from the previous chunk.
+	overlap_count: usize,
+	chars: usize,
+}
+
+impl ChunkBuilder {
+	fn new(group: i64, headings: &[(i64, String)]) -> Self {
+		Self {
+			group,
+			breadcrumb: headings.iter().map(|(_, title)| title.to_string()).collect(),
+			lines: Vec::new(),
+			overlap_count: 0,
+			chars: 0,
+		}
+	}
+
+	fn push(&mut self, line_num: i64, content: String) {
+		self.chars += line_chars(&content);
+		self.lines.push((line_num, content));
+	}
+
+	/// Returns the last lines fitting in `overlap_chars` (never all of the lines, so the chunks progress).
+	fn overlap_lines(&self, overlap_chars: usize) -> Vec<(i64, String)> {
+		let mut chars = 0;
+		let mut lines: Vec<(i64, String)> = self
+			.lines
+			.iter()
+			.skip(1)
+			.rev()
+			.take_while(|(_, content)| {
+				chars += line_chars(content);
+				chars <= overlap_chars
+			})
+			.cloned()
+			.collect();
+		lines.reverse();
+		lines
+	}
+
+	fn build(mut self) -> Option<Chunk> {
+		while self.lines.last().is_some_and(|(_, content)| content.trim().is_empty()) {
+			self.lines.pop();
+		}
+		if self.lines.len() <= self.overlap_count {
+			return None;
+		}
+
+		let (line_start, line_end) = match (self.lines.first(), self.lines.last()) {
+			(Some((first, _)), Some((last, _))) => (*first, *last),
+			_ => return None,
+		};
+		let content = self
+			.lines
+			.into_iter()
+			.map(|(_, content)| content)
+			.collect::<Vec<_>>()
+			.join("\n");
+
+		Some(Chunk {
+			group: self.group,
+			line_start,
+			line_end,
+			breadcrumb: self.breadcrumb,
+			content,
+		})
+	}
+}
+
+/// The chars of a line, with its new line.
+fn line_chars(content: &str) -> usize {
+	content.chars().count() + 1
+}
+
+// endregion: --- Support
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::{get_splitter_parts, SplitterKind};
+
+	#[test]
+	fn test_chunker_chunk_parts() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_md = "\
+# Intro
+aaaa bbbb
+## Setup
+cccc dddd
+eeee ffff
+gggg hhhh
+
+# Usage
+iiii jjjj";
+		let parts = get_splitter_parts(SplitterKind::Md, fx_md.as_bytes())?.collect::<crate::Result<Vec<_>>>()?;
+		// Note: 5 tokens is 20 chars, so 2 lines of 9 chars (+ new line) per chunk, with 1 line of overlap.
+		let fx_config = ChunkerConfig {
+			max_tokens: 5,
+			overlap_tokens: 3,
+		};
+
+		// -- Exec
+		let chunks = chunk_parts(&parts, &fx_config);
+
+		// -- Check
+		let ranges: Vec<(i64, i64)> = chunks.iter().map(|c| (c.line_start, c.line_end)).collect();
+		// Note: The (6, 7) chunk is dropped, as only its overlap line and a blank line.
+		assert_eq!(ranges, [(1, 2), (3, 4), (4, 5), (5, 6), (8, 9)]);
+		assert_eq!(chunks[0].breadcrumb, ["Intro"]);
+		assert_eq!(chunks[2].breadcrumb, ["Intro", "Setup"]);
+		assert_eq!(chunks[2].content, "cccc dddd\neeee ffff");
+		assert_eq!(chunks[4].breadcrumb, ["Usage"]);
+		assert_eq!(chunks[4].content, "# Usage\niiii jjjj");
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
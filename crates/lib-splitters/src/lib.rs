@@ -3,9 +3,11 @@
 mod error;
 pub use error::{Error, Result};
 
+mod chunker;
 mod splitter;
 
 // -- Flatten
+pub use chunker::*;
 pub use splitter::*;
 use std::io::BufRead;
 
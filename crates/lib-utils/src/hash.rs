@@ -0,0 +1,8 @@
+use data_encoding::BASE64URL_NOPAD;
+use sha2::{Digest, Sha256};
+
+/// Returns the sha256 of the content, base64url encoded (e.g., for the content hash of a file).
+pub fn sha256_b64u(content: impl AsRef<[u8]>) -> String {
+	let digest = Sha256::digest(content.as_ref());
+	BASE64URL_NOPAD.encode(&digest)
+}
@@ -9,6 +9,7 @@
 pub mod b64;
 pub mod derive_commons;
 pub mod envs;
+pub mod hash;
 pub mod hbs;
 pub mod time;
 pub mod trace;
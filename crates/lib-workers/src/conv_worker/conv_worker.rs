@@ -1,23 +1,87 @@
 use crate::conv_worker::Result;
-use lib_ais::runner::RunStepStatus;
+use lib_ais::runner::{RunLimiter, RunStepStatus};
 use lib_ais::{runner, AiManager};
 use lib_core::event::{ConvEvent, Subscriber};
 use lib_core::model::conv::ConvBmc;
-use lib_core::model::stack_step::StackStepBmc;
-use lib_core::model::ModelManager;
-use tracing::{debug, error, info};
+use lib_core::model::stack_step::{OrphanStepPolicy, StackStepBmc};
+use lib_core::model::{Id, ModelManager};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info, warn};
+
+// region:    --- ConvWorkerConfig
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConvWorkerConfig {
+	/// What to do on start with the steps left half-processed by the previous app session.
+	#[serde(default)]
+	pub orphan_step_policy: OrphanStepPolicy,
+
+	/// Max number of convs worked on in parallel (the steps of a conv are always worked in order).
+	#[serde(default = "default_max_concurrent_convs")]
+	pub max_concurrent_convs: usize,
+
+	/// Max number of concurrent model runs per provider, map node item runs included (e.g., `{"openai": 4}`).
+	#[serde(default)]
+	pub provider_limits: HashMap<String, usize>,
+
+	/// Max number of concurrent model runs per `provider::model` (e.g., `{"ollama::mixtral": 1}`).
+	#[serde(default)]
+	pub model_limits: HashMap<String, usize>,
+}
+
+fn default_max_concurrent_convs() -> usize {
+	8
+}
+
+impl Default for ConvWorkerConfig {
+	fn default() -> Self {
+		Self {
+			orphan_step_policy: OrphanStepPolicy::default(),
+			max_concurrent_convs: default_max_concurrent_convs(),
+			provider_limits: HashMap::new(),
+			model_limits: HashMap::new(),
+		}
+	}
+}
+
+// endregion: --- ConvWorkerConfig
+
+// region:    --- ConvWorker
 
 pub struct ConvWorker {
 	mm: ModelManager,
 	aim: AiManager,
+	orphan_step_policy: OrphanStepPolicy,
+
+	/// The convs (by id) being worked on, with their "has new work" flag (set when new work comes in the meantime).
+	active_convs: Mutex<HashMap<i64, bool>>,
+	conv_semaphore: Arc<Semaphore>,
 }
 
 impl ConvWorker {
 	pub fn start(mm: ModelManager, aim: AiManager) -> Result<()> {
-		let conv_worker = ConvWorker { mm, aim };
+		Self::start_with_config(mm, aim, ConvWorkerConfig::default())
+	}
+
+	pub fn start_with_config(mm: ModelManager, aim: AiManager, config: ConvWorkerConfig) -> Result<()> {
+		// Note: The limits are on the aim, so that the map node item runs are limited as well.
+		let aim = aim.with_run_limiter(RunLimiter::new(config.provider_limits, config.model_limits));
+		let conv_worker = Arc::new(ConvWorker {
+			mm,
+			aim,
+			orphan_step_policy: config.orphan_step_policy,
+			active_convs: Default::default(),
+			conv_semaphore: Arc::new(Semaphore::new(config.max_concurrent_convs.max(1))),
+		});
+
+		// Note: Subscribed before the spawn, so that the events published right after the start are received.
+		let sub: Subscriber<ConvEvent> = conv_worker.mm.hub().subscriber()?;
 
 		tokio::spawn(async move {
-			let res = conv_worker.start_worker().await;
+			let res = conv_worker.start_worker(sub).await;
 			match res {
 				Ok(_) => println!("ConvWorker ends OK"),
 				Err(_) => println!("ConvWorker ends OK"),
@@ -27,9 +91,13 @@ impl ConvWorker {
 		Ok(())
 	}
 
-	async fn start_worker(&self) -> Result<()> {
+	async fn start_worker(self: Arc<Self>, mut sub: Subscriber<ConvEvent>) -> Result<()> {
 		debug!("STARTING");
-		let mut sub: Subscriber<ConvEvent> = self.mm.hub().subscriber()?;
+
+		// Note: After the subscribe, so that the work events of the recovered convs are received.
+		if let Err(err) = self.recover_orphan_steps().await {
+			error!("Orphan steps recovery fail. Cause: {err}");
+		}
 
 		while let Ok(evt) = sub.next().await {
 			if let Err(err) = self.exec_evt(evt).await {
@@ -42,30 +110,22 @@ impl ConvWorker {
 		Ok(())
 	}
 
-	async fn exec_evt(&self, evt: ConvEvent) -> Result<()> {
-		debug!("EVT: {evt:?}");
-		match evt {
-			ConvEvent::ConvWorkNew { conv_id } => {
-				debug!("ConvWorkNew for conv_id: {conv_id} - START");
-				let conv = ConvBmc::get(&self.mm, conv_id).await?;
-				let cfile_db = ConvBmc::getc_cfile_db(&self.mm, &conv).await?;
+	async fn recover_orphan_steps(&self) -> Result<()> {
+		let policy = self.orphan_step_policy;
+		let conv_ids = ConvBmc::recover_orphan_steps(&self.mm, policy).await?;
 
-				if let Some(step_to_resolve) = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid).await?
-				{
-					runner::resolve_stack_step(&self.mm, &cfile_db, step_to_resolve.id).await?;
-				}
+		for conv_id in conv_ids {
+			info!("Recovered orphan steps ({policy:?}) for conv_id: {conv_id}");
+			ConvBmc::touch_work_tnew(&self.mm, conv_id).await?;
+		}
 
-				if let Some(step_to_run) = StackStepBmc::seek_next_to_run_for_conv(&cfile_db, &conv.uid).await? {
-					let run_status = runner::run_stack_step(&self.aim, &self.mm, &cfile_db, step_to_run.id).await?;
-					if !matches!(run_status, RunStepStatus::Ended) {
-						// we have more work to do
-						ConvBmc::touch_work_tnew(&self.mm, conv_id).await?;
-					} else {
-						ConvBmc::touch_work_tdone(&self.mm, conv_id).await?;
-					}
-				}
-				debug!("ConvWorkNew for conv_id: {conv_id} - END");
-			}
+		Ok(())
+	}
+
+	async fn exec_evt(self: &Arc<Self>, evt: ConvEvent) -> Result<()> {
+		debug!("EVT: {evt:?}");
+		match evt {
+			ConvEvent::ConvWorkNew { conv_id } => self.schedule_conv_work(conv_id),
 			ConvEvent::ConvWorkDone { conv_id } => {
 				let conv = ConvBmc::get(&self.mm, conv_id).await?;
 				let cfile_db = ConvBmc::getc_cfile_db(&self.mm, &conv).await?;
@@ -80,12 +140,106 @@ impl ConvWorker {
 					ConvBmc::touch_work_tnew(&self.mm, conv_id).await?;
 				}
 			}
+			// Note: The in-flight run listens to the cancel itself (see `runner::run_stack_step`).
+			ConvEvent::ConvWorkCancel { .. } => (),
+			// Note: For the UI only.
+			ConvEvent::ConvMsgChunk { .. } => (),
 		}
 
 		Ok(())
 	}
 }
 
+// region:    --- Conv Work
+
+impl ConvWorker {
+	/// Spawn the work task of the conv, or flag the running one to do another pass,
+	/// so that the steps of a conv are never worked in parallel.
+	fn schedule_conv_work(self: &Arc<Self>, conv_id: Id) {
+		{
+			let mut active_convs = self.active_convs.lock().unwrap_or_else(|poison| poison.into_inner());
+			if let Some(has_new_work) = active_convs.get_mut(&conv_id.as_i64()) {
+				*has_new_work = true;
+				return;
+			}
+			active_convs.insert(conv_id.as_i64(), false);
+		}
+
+		let worker = self.clone();
+		tokio::spawn(async move {
+			// Note: The semaphore is never closed, so acquire cannot fail.
+			let _conv_permit = worker.conv_semaphore.clone().acquire_owned().await.ok();
+
+			loop {
+				if let Err(err) = worker.exec_conv_work(conv_id).await {
+					error!("Conv work fail for conv_id: {conv_id}. Cause: {err}");
+				}
+
+				let mut active_convs = worker.active_convs.lock().unwrap_or_else(|poison| poison.into_inner());
+				match active_convs.get_mut(&conv_id.as_i64()) {
+					Some(has_new_work) if *has_new_work => *has_new_work = false,
+					_ => {
+						active_convs.remove(&conv_id.as_i64());
+						break;
+					}
+				}
+			}
+		});
+	}
+
+	async fn exec_conv_work(&self, conv_id: Id) -> Result<()> {
+		debug!("ConvWorkNew for conv_id: {conv_id} - START");
+		let conv = ConvBmc::get(&self.mm, conv_id).await?;
+		let cfile_db = ConvBmc::getc_cfile_db(&self.mm, &conv).await?;
+
+		if let Some(step_to_resolve) = StackStepBmc::seek_next_to_resolve_for_conv(&cfile_db, &conv.uid).await? {
+			runner::resolve_stack_step(&self.mm, &cfile_db, step_to_resolve.id).await?;
+		}
+
+		if let Some(step_to_run) = StackStepBmc::seek_next_to_run_for_conv(&cfile_db, &conv.uid).await? {
+			// -- Wait for the provider and model run limits
+			let target = match step_to_run.resolve_model.as_deref() {
+				Some(model) if !step_to_run.closer => match self.aim.resolve_model(None, model).await {
+					Ok(target) => Some(target),
+					// Note: The run will fail with the proper error.
+					Err(err) => {
+						warn!("Cannot resolve model '{model}' for run limits. Cause: {err}");
+						None
+					}
+				},
+				_ => None,
+			};
+			let _run_permits = self.aim.run_limiter().acquire(target.as_ref()).await;
+
+			let run_status = runner::run_stack_step(&self.aim, &self.mm, &cfile_db, step_to_run.id).await?;
+			match run_status {
+				// we have more work to do
+				RunStepStatus::Ongoing => ConvBmc::touch_work_tnew(&self.mm, conv_id).await?,
+				RunStepStatus::Ended => ConvBmc::touch_work_tdone(&self.mm, conv_id).await?,
+				// Note: `ConvBmc::cancel_work` already marked the work as done.
+				RunStepStatus::Cancelled => debug!("ConvWorkNew for conv_id: {conv_id} - CANCELLED"),
+				// the retry step can only run after the delay
+				RunStepStatus::Retrying { delay, .. } => {
+					let mm = self.mm.clone();
+					tokio::spawn(async move {
+						tokio::time::sleep(delay).await;
+						if let Err(err) = ConvBmc::touch_work_tnew(&mm, conv_id).await {
+							error!("Fail to touch work for retry of conv_id: {conv_id}. Cause: {err}");
+						}
+					});
+				}
+			}
+		}
+		debug!("ConvWorkNew for conv_id: {conv_id} - END");
+
+		Ok(())
+	}
+}
+
+// endregion: --- Conv Work
+
+// endregion: --- ConvWorker
+
 // region:    --- Tests
 
 #[cfg(test)]
@@ -94,12 +248,37 @@ mod tests {
 	type Result<T> = core::result::Result<T, Error>; // For tests.
 
 	use super::*;
-	use lib_ais::_test_support::seed_all_for_test_runner;
-	use lib_core::model::msg::{AuthorKind, MsgBmc};
-	use lib_test_utils::sleep_ms;
+	use lib_ais::_test_support::{mock_echo_agent_c, seed_all_for_test_runner, MockHttpServer, MockRoute};
+	use lib_ais::{OpenaiClient, OpenaiConfig};
+	use lib_core::_test_support::seed_space;
+	use lib_core::model::agent::{AgentBmc, AgentForCreate};
+	use lib_core::model::cfile::CFileBmc;
+	use lib_core::model::msg::{AuthorKind, Msg, MsgBmc};
+	use lib_core::model::space::SpaceBmc;
+	use lib_core::model::support::prelude::SlDb;
 	use lib_utils::trace::init_trace;
 	use std::time::Duration;
-	use tokio::time::sleep;
+	use tokio::time::{sleep, timeout};
+
+	/// Wait for the done answer msg of the orig msg (polled, as the worker runs in its own tasks).
+	async fn wait_for_answer(cfile_db: &SlDb, orig_msg_id: Id, max_wait: Duration) -> Result<Msg> {
+		let wait = async {
+			loop {
+				let answer = MsgBmc::list(cfile_db, None, None)
+					.await?
+					.into_iter()
+					.find(|m| m.orig_msg_id == Some(orig_msg_id) && m.done_time.is_some());
+				if let Some(answer) = answer {
+					return Ok(answer);
+				}
+				sleep(Duration::from_millis(5)).await;
+			}
+		};
+
+		timeout(max_wait, wait)
+			.await
+			.map_err(|_| format!("Should have answer of msg {orig_msg_id} within {max_wait:?}"))?
+	}
 
 	#[tokio::test]
 	async fn test_conv_work_simple() -> Result<()> {
@@ -110,20 +289,14 @@ mod tests {
 		let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
 		ConvWorker::start(mm.clone(), aim.clone())?;
 
-		// Note: Somehow we have to pause here, otherwise, the events are not received.
-		//       We should investigate the reason.
-		//       In prod code, not an issue, as the queues are started at start of the app.
-		//       So there are an normal pause there.
-		sleep_ms(1).await;
-
 		// -- Exec
 		// Create the original message
 		let first_input = "Hello world";
 		// Note: this will create the first task_step
-		let _orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, first_input.into()).await?;
+		let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, first_input.into()).await?;
 
-		// we pause to let the worker work
-		sleep_ms(10).await;
+		// wait for the worker to work
+		wait_for_answer(&cfile_db, orig_msg_id, Duration::from_secs(5)).await?;
 
 		// for debug
 		cfile_db.print_table("msg")?;
@@ -156,6 +329,99 @@ mod tests {
 
 		Ok(())
 	}
+
+	#[tokio::test]
+	async fn test_conv_work_recover_orphan_reset() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let aim = AiManager::default();
+		let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
+		// Note: No worker yet, so the first step stays unresolved.
+		let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "Hello world".into()).await?;
+		let fx_step = StackStepBmc::list(&cfile_db, None, None)
+			.await?
+			.into_iter()
+			.next()
+			.ok_or("Should have first step")?;
+		// simulate an app quit in the middle of the resolve
+		StackStepBmc::set_resolve_tstart(&cfile_db, fx_step.id).await?;
+
+		// -- Exec
+		ConvWorker::start(mm.clone(), aim.clone())?;
+		let answer = wait_for_answer(&cfile_db, orig_msg_id, Duration::from_secs(5)).await?;
+
+		// -- Check
+		assert_eq!(answer.content.ok_or("should have content")?, "Final Agent response");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_conv_work_slow_conv_not_blocking() -> Result<()> {
+		// -- Setup & Fixtures
+		let fx_sse = concat!(
+			"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Slow response\"}}]}\n\n",
+			"data: [DONE]\n\n"
+		);
+		let server = MockHttpServer::start(vec![
+			MockRoute::sse("POST", "/chat/completions", fx_sse).with_delay(Duration::from_millis(1500))
+		])
+		.await?;
+		let aim =
+			AiManager::default().with_openai_client(OpenaiClient::new(OpenaiConfig::new(server.base_url(), "fx-key")));
+		let mm = ModelManager::new().await?;
+		// the slow conv (single agent, on the delayed mock server)
+		let slow_agent_id = AgentBmc::create(
+			&mm,
+			AgentForCreate {
+				model: Some("openai::gpt-mock-slow".to_string()),
+				..mock_echo_agent_c("Slow Agent", "Slow Agent inst")
+			},
+		)
+		.await?;
+		let slow_space_id = seed_space(&mm, "Space Slow Conv").await?;
+		SpaceBmc::set_agent(&mm, slow_space_id, slow_agent_id).await?;
+		let slow_conv = SpaceBmc::get_latest_conv(&mm, slow_space_id).await?;
+		let slow_cfile_db = CFileBmc::getc_cfile_db_for_conv(&mm, &slow_conv).await?;
+		// the fast conv (multi steps, on the mock echo model)
+		let (cfile_db, conv) = seed_all_for_test_runner(&mm).await?;
+		ConvWorker::start(mm.clone(), aim.clone())?;
+
+		// -- Exec
+		let slow_orig_msg_id = ConvBmc::add_conv_msg(&mm, slow_conv.id, "Hello slow".into()).await?;
+		let orig_msg_id = ConvBmc::add_conv_msg(&mm, conv.id, "Hello world".into()).await?;
+		let answer = wait_for_answer(&cfile_db, orig_msg_id, Duration::from_secs(1)).await?;
+
+		// -- Check
+		// the fast conv is answered while the slow conv is still running
+		assert_eq!(answer.content.ok_or("should have content")?, "Final Agent response");
+		let slow_answer = MsgBmc::list(&slow_cfile_db, None, None)
+			.await?
+			.into_iter()
+			.find(|m| m.orig_msg_id == Some(slow_orig_msg_id) && m.done_time.is_some());
+		assert!(slow_answer.is_none(), "Should not have the slow answer yet");
+		// the steps of the fast conv still run in order (one after the other)
+		let steps = StackStepBmc::list(&cfile_db, None, None).await?;
+		let mut run_times = Vec::new();
+		for step in steps {
+			let step = StackStepBmc::get(&cfile_db, step.id).await?;
+			if let (Some(run_tstart), Some(run_tend)) = (step.run_tstart, step.run_tend) {
+				run_times.push((run_tstart, run_tend));
+			}
+		}
+		assert!(run_times.len() > 1, "Should have more than one run step");
+		for pair in run_times.windows(2) {
+			assert!(
+				pair[1].0 >= pair[0].1,
+				"Should not have overlapping step runs: {pair:?}"
+			);
+		}
+		// the slow conv ends eventually
+		let slow_answer = wait_for_answer(&slow_cfile_db, slow_orig_msg_id, Duration::from_secs(5)).await?;
+		assert_eq!(slow_answer.content.ok_or("should have content")?, "Slow response");
+
+		Ok(())
+	}
 }
 
 // endregion: --- Tests
@@ -1,16 +1,29 @@
-use crate::dsource_worker::processors::{proc_dfiles_refreshed, proc_ditems_refreshed, proc_dsource_added};
+use crate::dsource_worker::processors::{
+	proc_dfile_dbs_refreshed, proc_dfiles_refreshed, proc_ditems_refreshed, proc_dsource_added,
+};
 use crate::dsource_worker::Result;
+use lib_ais::AiManager;
 use lib_core::event::{DSourceEvent, Subscriber};
-use lib_core::model::ModelManager;
-use tracing::debug;
+use lib_core::model::{Id, ModelManager};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{debug, error};
 
 pub struct DSourceWorker {
 	mm: ModelManager,
+	aim: AiManager,
+
+	/// Held by the running embed task (see `spawn_embed`).
+	embed_lock: Arc<Mutex<()>>,
 }
 
 impl DSourceWorker {
-	pub fn start(mm: ModelManager) -> Result<()> {
-		let ditemizer = DSourceWorker { mm };
+	pub fn start(mm: ModelManager, aim: AiManager) -> Result<()> {
+		let ditemizer = DSourceWorker {
+			mm,
+			aim,
+			embed_lock: Default::default(),
+		};
 
 		tokio::spawn(async move {
 			let res = ditemizer.start_worker().await;
@@ -32,13 +45,28 @@ impl DSourceWorker {
 				DSourceEvent::DSourceAdded { dsource_id } => proc_dsource_added(&self.mm, dsource_id).await?,
 				DSourceEvent::DItemsRefreshed { dsource_id } => proc_ditems_refreshed(&self.mm, dsource_id).await?,
 				DSourceEvent::DFilesRefreshed { dsource_id } => proc_dfiles_refreshed(&self.mm, dsource_id).await?,
-				other => debug!("DSourceWorker event '{other:?}' not implemented yet"),
+				DSourceEvent::DFileDbsRefreshed { dsource_id } => self.spawn_embed(dsource_id),
 			}
 		}
 
 		debug!("ENDING");
 		Ok(())
 	}
+
+	/// Embed the dsource parts in its own task, so that the (long) embed calls do not hold the dsource events.
+	/// Note: The embed tasks run one at a time, so that the same parts are not embedded twice.
+	fn spawn_embed(&self, dsource_id: Id) {
+		let mm = self.mm.clone();
+		let aim = self.aim.clone();
+		let embed_lock = self.embed_lock.clone();
+
+		tokio::spawn(async move {
+			let _embed_guard = embed_lock.lock().await;
+			if let Err(err) = proc_dfile_dbs_refreshed(&mm, &aim, dsource_id).await {
+				error!("Embed fail for dsource_id: {dsource_id}. Cause: {err}");
+			}
+		});
+	}
 }
 
 // region:    --- Tests
@@ -64,7 +92,7 @@ mod tests {
 		// -- Setup & Fixtures
 		// lib_utils::trace::init_trace();
 		let mm = ModelManager::new().await?;
-		DSourceWorker::start(mm.clone())?;
+		DSourceWorker::start(mm.clone(), AiManager::default())?;
 		let _fx_drive_name = "test_create_ok - drive 01";
 		let fx_drive_id = seed_drive(&mm, "test_create_dsource_ok - drive 01").await?;
 		let fx_dsource_rref = Path::new("../../test-data").canonicalize().map(SPath::from_path)??;
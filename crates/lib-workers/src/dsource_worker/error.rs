@@ -14,6 +14,12 @@ pub enum Error {
 		ditem_id: Id,
 	},
 
+	// -- dfiles_refreshed
+	DItemFileCantRead {
+		file_path: String,
+		cause: String,
+	},
+
 	// -- Libs
 	#[from]
 	Model(model::Error),
@@ -21,6 +27,8 @@ pub enum Error {
 	Event(event::Error),
 	#[from]
 	Splitters(lib_splitters::Error),
+	#[from]
+	Ais(lib_ais::Error),
 
 	// -- Externals
 	#[from]
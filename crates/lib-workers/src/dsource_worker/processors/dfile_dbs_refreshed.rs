@@ -0,0 +1,105 @@
+use crate::dsource_worker::Result;
+use lib_ais::AiManager;
+use lib_core::model::dfile::DFileBmc;
+use lib_core::model::dfile_db::part_vec::{PartVecBmc, PartVecForCreate};
+use lib_core::model::support::prelude::SlDb;
+use lib_core::model::{Id, ModelManager};
+use tracing::{debug, warn};
+
+/// The number of chunks embedded per embed call.
+const EMBED_BATCH_SIZE: i64 = 32;
+
+/// Create the missing chunk vectors of the dsource dfile dbs, with the `AiManager::embed_model`
+/// (does nothing when no embed model).
+///
+/// Note: A dfile db failing to embed is skipped with a warning (e.g., the model provider is not up),
+///       and its missing vectors are created at the next refresh.
+pub async fn proc_dfile_dbs_refreshed(mm: &ModelManager, aim: &AiManager, dsource_id: Id) -> Result<()> {
+	let Some(embed_model) = aim.embed_model() else {
+		debug!("proc_dfile_dbs_refreshed - no embed model, skip dsource {dsource_id}");
+		return Ok(());
+	};
+
+	for dfile in DFileBmc::list_dfiles_for_dsource(mm, dsource_id).await? {
+		let dfile_db = DFileBmc::get_dfile_db(mm, &dfile).await?;
+		if let Err(err) = embed_dfile_db_chunks(aim, &dfile_db, embed_model).await {
+			warn!(
+				"proc_dfile_dbs_refreshed - dfile {} fail to embed chunks. Cause: {err}",
+				dfile.id
+			);
+		}
+	}
+
+	Ok(())
+}
+
+// region:    --- Internal
+
+async fn embed_dfile_db_chunks(aim: &AiManager, dfile_db: &SlDb, embed_model: &str) -> Result<()> {
+	loop {
+		let chunks = PartVecBmc::list_chunks_without_vec(dfile_db, embed_model, EMBED_BATCH_SIZE).await?;
+		if chunks.is_empty() {
+			break;
+		}
+
+		let inputs = chunks.iter().map(|chunk| chunk.embed_input()).collect();
+		let vecs = aim.embed(embed_model, inputs).await?;
+
+		for (chunk, vec) in chunks.iter().zip(vecs) {
+			PartVecBmc::create(dfile_db, PartVecForCreate::new(*chunk.id, embed_model, &vec)).await?;
+		}
+	}
+
+	Ok(())
+}
+
+// endregion: --- Internal
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::dsource_worker::processors::{proc_dfiles_refreshed, proc_ditems_refreshed, proc_dsource_added};
+	use lib_core::_test_support::{seed_drive, seed_dsource};
+
+	#[tokio::test]
+	async fn test_proc_dfile_dbs_refreshed() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let aim = AiManager::default().with_embed_model("fc::fc-mock-embed");
+		let fx_drive_id = seed_drive(&mm, "test_proc_dfile_dbs_refreshed - drive 01").await?;
+		let fx_dsource_id = seed_dsource(&mm, fx_drive_id, "../../test-data").await?;
+		proc_dsource_added(&mm, fx_dsource_id).await?;
+		proc_ditems_refreshed(&mm, fx_dsource_id).await?;
+		proc_dfiles_refreshed(&mm, fx_dsource_id).await?;
+
+		// -- Exec
+		proc_dfile_dbs_refreshed(&mm, &aim, fx_dsource_id).await?;
+		// Note: A second run should not embed the chunks again.
+		proc_dfile_dbs_refreshed(&mm, &aim, fx_dsource_id).await?;
+
+		// -- Check
+		let dfile = DFileBmc::list_dfiles_for_dsource(&mm, fx_dsource_id)
+			.await?
+			.into_iter()
+			.next()
+			.ok_or("Should have a dfile")?;
+		let dfile_db = DFileBmc::get_dfile_db(&mm, &dfile).await?;
+		let chunks_count = dfile_db.exec_returning_num("select COUNT(*) as count from chunk", [])?;
+		let vecs_count = dfile_db.exec_returning_num("select COUNT(*) as count from part_vec", [])?;
+		assert!(chunks_count > 0);
+		assert_eq!(vecs_count, chunks_count);
+		let part_vec = PartVecBmc::get(&dfile_db, 1.into()).await?;
+		assert_eq!(part_vec.model, "fc::fc-mock-embed");
+		assert_eq!(part_vec.dim, 64);
+		assert_eq!(part_vec.values().len(), 64);
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
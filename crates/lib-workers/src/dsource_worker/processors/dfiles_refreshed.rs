@@ -1,12 +1,16 @@
-use crate::dsource_worker::Result;
+use crate::dsource_worker::{Error, Result};
 use lib_core::event::DSourceEvent;
 use lib_core::model::dfile::{DFile, DFileBmc};
+use lib_core::model::dfile_db::chunk::{ChunkBmc, ChunkForCreate, BREADCRUMB_SEP};
 use lib_core::model::dfile_db::ditem_ref::DItemRefBmc;
 use lib_core::model::dfile_db::part::{PartBmc, PartForCreate};
-use lib_core::model::ditem::{DItem, DItemBmc};
+use lib_core::model::ditem::{DItem, DItemBmc, DItemForUpdate};
+use lib_core::model::support::prelude::{DbBmc, SlDb};
 use lib_core::model::{Id, ModelManager};
-use lib_splitters::SplitterKind;
-use simple_fs::{get_buf_reader, SFile};
+use lib_splitters::{chunk_parts, ChunkerConfig, SplitterKind};
+use lib_utils::hash::sha256_b64u;
+use lib_utils::time::now_unix_time_us;
+use simple_fs::SFile;
 use std::collections::HashMap;
 
 pub async fn proc_dfiles_refreshed(mm: &ModelManager, dsource_id: Id) -> Result<()> {
@@ -62,31 +66,94 @@ async fn refresh_dsource_dfile_dbs(mm: &ModelManager, dsource_id: Id) -> Result<
 	Ok(())
 }
 
+/// Re-index the parts and chunks of the ditem in its dfile db (replacing the previous ones),
+/// in one dfile db transaction, and set the ditem `proc_time` and `content_hash`.
+///
+/// Note: The unchanged files (same `content_hash`) are not re-indexed (only their `proc_time` is set).
+/// Note: The content is hashed as bytes, and its invalid UTF-8 sequences are indexed as `U+FFFD`.
 async fn update_db_file_parts(mm: &ModelManager, ditem: &DItem, dfile: &DFile) -> Result<()> {
-	// -- Get the dfile db
-	let dfile_db = DFileBmc::get_dfile_db(mm, dfile).await?;
+	// -- Read the content
+	let full_path = SFile::new(&ditem.file_path)?;
+	let bytes = std::fs::read(full_path.path()).map_err(|err| Error::DItemFileCantRead {
+		file_path: ditem.file_path.to_string(),
+		cause: err.to_string(),
+	})?;
+	let content_hash = sha256_b64u(&bytes);
+	let content = String::from_utf8_lossy(&bytes).into_owned();
+
+	// -- Re-index the parts (if changed)
+	if ditem.content_hash.as_deref() != Some(content_hash.as_str()) {
+		let dfile_db = DFileBmc::get_dfile_db(mm, dfile).await?;
+		replace_ditem_parts(&dfile_db, &ditem.uid, &content).await?;
+	}
+
+	// -- Update the ditem
+	let ditem_u = DItemForUpdate {
+		proc_time: Some(now_unix_time_us().into()),
+		content_hash: Some(content_hash),
+		..Default::default()
+	};
+	DItemBmc::update(mm, ditem.id, ditem_u).await?;
 
+	Ok(())
+}
+
+/// Delete the parts and chunks of the ditem `ditem_uid`, and create the ones of its `content`,
+/// in one dfile db transaction (with the create events published once committed).
+async fn replace_ditem_parts(dfile_db: &SlDb, ditem_uid: &str, content: &str) -> Result<()> {
 	// -- Create the `ditem_ref` row if not present
-	let ditem_ref_id = DItemRefBmc::get_or_create_for_ditem_uid(&dfile_db, &ditem.uid).await?.id;
+	let ditem_ref_id = DItemRefBmc::get_or_create_for_ditem_uid(dfile_db, ditem_uid).await?.id;
 	let ditem_ref_id = *ditem_ref_id;
 
-	// -- Create the parts
-	let full_path = SFile::new(&ditem.file_path)?;
-	let reader = get_buf_reader(full_path.path())?;
-
+	// -- Split the parts and chunks
 	let splitter_kind = SplitterKind::Md;
-	let mut splitter_parts = lib_splitters::get_splitter_parts(splitter_kind, reader)?;
-
+	let mut splitter_parts = lib_splitters::get_splitter_parts(splitter_kind, content.as_bytes())?;
+	let mut s_parts = Vec::new();
 	while let Some(Ok(s_part)) = splitter_parts.next() {
-		let part_c = PartForCreate {
-			ditem_ref_id,
-			is_title: s_part.is_title,
-			level: s_part.level,
-			group: s_part.group,
-			line_num: s_part.line_num,
-			content: s_part.content,
-		};
-		PartBmc::create(&dfile_db, part_c).await?;
+		s_parts.push(s_part);
+	}
+	let chunks = chunk_parts(&s_parts, &ChunkerConfig::default());
+
+	// -- Replace the parts and chunks
+	let (part_ids, chunk_ids) = dfile_db.exec_tx(|tx| -> Result<(Vec<Id>, Vec<Id>)> {
+		PartBmc::delete_for_ditem_ref_tx(tx, ditem_ref_id)?;
+		ChunkBmc::delete_for_ditem_ref_tx(tx, ditem_ref_id)?;
+
+		let mut part_ids = Vec::with_capacity(s_parts.len());
+		for s_part in s_parts.iter() {
+			let part_c = PartForCreate {
+				ditem_ref_id,
+				is_title: s_part.is_title,
+				level: s_part.level,
+				group: s_part.group,
+				line_num: s_part.line_num,
+				content: s_part.content.to_string(),
+			};
+			part_ids.push(PartBmc::create_tx(tx, part_c)?);
+		}
+
+		let mut chunk_ids = Vec::with_capacity(chunks.len());
+		for chunk in chunks {
+			let chunk_c = ChunkForCreate {
+				ditem_ref_id,
+				group: chunk.group,
+				line_start: chunk.line_start,
+				line_end: chunk.line_end,
+				breadcrumb: chunk.breadcrumb.join(BREADCRUMB_SEP),
+				content: chunk.content,
+			};
+			chunk_ids.push(ChunkBmc::create_tx(tx, chunk_c)?);
+		}
+
+		Ok((part_ids, chunk_ids))
+	})?;
+
+	// -- Publish the create events
+	for part_id in part_ids {
+		PartBmc::publish_create_event(dfile_db, part_id).await;
+	}
+	for chunk_id in chunk_ids {
+		ChunkBmc::publish_create_event(dfile_db, chunk_id).await;
 	}
 
 	Ok(())
@@ -128,6 +195,95 @@ mod tests {
 		let distinct_ditem_ref_ids_count =
 			dfile_db.exec_returning_num("select COUNT(distinct(ditem_ref_id)) as count from part", [])?;
 		assert_eq!(distinct_ditem_ref_ids_count, 3);
+		let chunks_ditem_ref_ids_count =
+			dfile_db.exec_returning_num("select COUNT(distinct(ditem_ref_id)) as count from chunk", [])?;
+		assert_eq!(chunks_ditem_ref_ids_count, 3);
+		let chunks = ChunkBmc::list_for_ditem_ref(&dfile_db, 1).await?;
+		let chunk = chunks.first().ok_or("Should have a chunk")?;
+		assert!(chunk.line_start <= chunk.line_end);
+		assert!(!chunk.content.trim().is_empty());
+		// the fts hits expanded to their chunk
+		let hits = ChunkBmc::content_search_ranked(&dfile_db, "rayleigh", 10).await?;
+		let hit = hits.first().ok_or("Should have a chunk hit")?;
+		assert_eq!(hit.breadcrumb, "Morning");
+		assert!(hit.line_start < hit.line_end, "Should have more than the matching line");
+		assert!(hit.content.contains("Rayleigh scattering"));
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_proc_dfiles_refreshed_reindex() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let fx_drive_id = seed_drive(&mm, "test_proc_dfiles_refreshed_reindex - drive 01").await?;
+		let fx_dsource_id = seed_dsource(&mm, fx_drive_id, "../../test-data").await?;
+		proc_dsource_added(&mm, fx_dsource_id).await?;
+		proc_ditems_refreshed(&mm, fx_dsource_id).await?;
+		proc_dfiles_refreshed(&mm, fx_dsource_id).await?;
+		let dfile = DFileBmc::list_dfiles_for_dsource(&mm, fx_dsource_id)
+			.await?
+			.into_iter()
+			.next()
+			.ok_or("Should have a dfile")?;
+		let dfile_db = DFileBmc::get_dfile_db(&mm, &dfile).await?;
+		let fx_parts_count = dfile_db.exec_returning_num("select COUNT(*) as count from part", [])?;
+		let fx_chunks_count = dfile_db.exec_returning_num("select COUNT(*) as count from chunk", [])?;
+
+		// -- Exec
+		// Note: Without `proc_time`, the ditems are processed again (same content, so not re-indexed).
+		mm.main_db().exec("UPDATE ditem SET proc_time = NULL", [])?;
+		proc_dfiles_refreshed(&mm, fx_dsource_id).await?;
+		let unchanged_part_max_id = dfile_db.exec_returning_num("select MAX(id) as count from part", [])?;
+		// Note: Without `content_hash` either, the ditems are re-indexed.
+		mm.main_db()
+			.exec("UPDATE ditem SET proc_time = NULL, content_hash = NULL", [])?;
+		proc_dfiles_refreshed(&mm, fx_dsource_id).await?;
+
+		// -- Check
+		let ditems = DItemBmc::list_ditems_to_proc_for_dsource(&mm, fx_dsource_id).await?;
+		assert!(ditems.is_empty(), "Should have all ditems processed");
+		let parts_count = dfile_db.exec_returning_num("select COUNT(*) as count from part", [])?;
+		let parts_fts_count = dfile_db.exec_returning_num("select COUNT(*) as count from part_fts", [])?;
+		let chunks_count = dfile_db.exec_returning_num("select COUNT(*) as count from chunk", [])?;
+		let part_max_id = dfile_db.exec_returning_num("select MAX(id) as count from part", [])?;
+		assert_eq!(parts_count, fx_parts_count);
+		assert_eq!(parts_fts_count, fx_parts_count);
+		assert_eq!(chunks_count, fx_chunks_count);
+		assert_eq!(unchanged_part_max_id, fx_parts_count);
+		assert!(part_max_id > unchanged_part_max_id, "Should have re-indexed the parts");
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_proc_dfiles_refreshed_non_utf8() -> Result<()> {
+		// -- Setup & Fixtures
+		let mm = ModelManager::new().await?;
+		let fx_dir = std::env::temp_dir().join("fc-test-proc-dfiles-refreshed-non-utf8");
+		std::fs::create_dir_all(&fx_dir)?;
+		// Note: Latin-1 `é` (0xE9), not valid UTF-8.
+		std::fs::write(fx_dir.join("latin1.md"), b"# Caf\xe9\nThe caf\xe9 is open\n")?;
+		let fx_drive_id = seed_drive(&mm, "test_proc_dfiles_refreshed_non_utf8 - drive 01").await?;
+		let fx_dsource_id = seed_dsource(&mm, fx_drive_id, &fx_dir.to_string_lossy()).await?;
+		proc_dsource_added(&mm, fx_dsource_id).await?;
+		proc_ditems_refreshed(&mm, fx_dsource_id).await?;
+
+		// -- Exec
+		proc_dfiles_refreshed(&mm, fx_dsource_id).await?;
+
+		// -- Check
+		let ditems = DItemBmc::list_ditems_to_proc_for_dsource(&mm, fx_dsource_id).await?;
+		assert!(ditems.is_empty(), "Should have all ditems processed");
+		let dfile = DFileBmc::list_dfiles_for_dsource(&mm, fx_dsource_id)
+			.await?
+			.into_iter()
+			.next()
+			.ok_or("Should have a dfile")?;
+		let dfile_db = DFileBmc::get_dfile_db(&mm, &dfile).await?;
+		let parts = PartBmc::list_for_ditem_ref(&dfile_db, 1).await?;
+		let contents: Vec<&str> = parts.iter().map(|part| part.content.as_str()).collect();
+		assert_eq!(contents, ["# Caf\u{FFFD}", "The caf\u{FFFD} is open"]);
 
 		Ok(())
 	}
@@ -1,10 +1,12 @@
 // region:    --- Modules
 
+mod dfile_dbs_refreshed;
 mod dfiles_refreshed;
 mod ditems_refreshed;
 mod dsource_added;
 
 // -- Flatten
+pub use dfile_dbs_refreshed::*;
 pub use dfiles_refreshed::*;
 pub use ditems_refreshed::*;
 pub use dsource_added::*;
@@ -24,15 +24,20 @@ export type DSourceKind = "File" | "Folder" | "GhRepo" | "GgXls" | "GgDoc" | "Ms
 export interface Agent {
 	chain?: string | null;
 	desc?: string | null;
+	history_window?: number | null;
 	id: Id;
 	inst?: string | null;
 	kind: AgentKind;
+	logic_tool?: string | null;
 	model?: string | null;
 	name: string;
 	out_format?: OutFormat | null;
 	prompt_tmpl?: string | null;
 	provider?: string | null;
+	retrieval?: string | null;
+	retry_policy?: string | null;
 	space_default: boolean;
+	tools?: string | null;
 	uid: string;
 }
 
@@ -40,6 +45,7 @@ export interface AgentForCreate {
 	desc?: string | null;
 	inst?: string | null;
 	kind?: AgentKind | null;
+	logic_tool?: string | null;
 	model?: string | null;
 	name: string;
 	out_format?: OutFormat | null;
@@ -56,13 +62,18 @@ export interface AgentLite {
 export interface AgentForUpdate {
 	chain?: string | null;
 	desc?: string | null;
+	history_window?: number | null;
 	inst?: string | null;
+	logic_tool?: string | null;
 	model?: string | null;
 	name?: string | null;
 	out_format?: OutFormat | null;
 	prompt_tmpl?: string | null;
 	provider?: string | null;
+	retrieval?: string | null;
+	retry_policy?: string | null;
 	space_default?: boolean | null;
+	tools?: string | null;
 }
 
 export interface Conv {
@@ -62,6 +62,16 @@ class AgentFmc extends BaseFmc<types.Agent, types.AgentForCreate, types.AgentFor
     // Note: for now, we just add a 's' for list, might might get rid of plurals
     return invoke_rpc(`${this.cmd_suffix}_list`, {}).then(res => res.data);
   }
+
+  /** Returns the chain diagnostics ({path, kind, message}), empty if valid. `agent_id` null for a new agent. */
+  async validate_chain(agent_id: number | null, chain: string): Promise<any[]> {
+    return invoke_rpc(`${this.cmd_suffix}_validate_chain`, { agent_id, chain }).then(res => res.data);
+  }
+
+  /** Returns the chain simulation trace ({steps, output, truncated}), with the canned `outputs` by agent name. */
+  async simulate_chain(agent_id: number, input: string, outputs?: Record<string, string | string[]>): Promise<any> {
+    return invoke_rpc(`${this.cmd_suffix}_simulate_chain`, { agent_id, input, outputs }).then(res => res.data);
+  }
 }
 export const agentFmc = new AgentFmc();
 
@@ -133,6 +143,14 @@ class ConvFmc extends BaseFmc<types.Drive, void, types.DriveForUpdate> {
   async clear_all(conv_id: number): Promise<void> {
     return invoke_rpc(`${this.cmd_suffix}_clear_all`, { id: conv_id }).then(res => res.data);
   }
+
+  async retry_step(conv_id: number, step_id: number): Promise<any> {
+    return invoke_rpc(`${this.cmd_suffix}_retry_step`, { conv_id, step_id }).then(res => res.data);
+  }
+
+  async cancel(conv_id: number): Promise<void> {
+    return invoke_rpc(`${this.cmd_suffix}_cancel`, { id: conv_id }).then(res => res.data);
+  }
 }
 
 export const convFmc = new ConvFmc();
@@ -162,6 +180,11 @@ class DriveFmc extends BaseFmc<types.Drive, types.DriveForCreate, types.DriveFor
     return res.data;
   }
 
+  /** mode: "fts" | "similar" | "hybrid" (default) */
+  async search(drive_id: number, query: string, mode?: string, limit?: number): Promise<any[]> {
+    return invoke_rpc(`${this.cmd_suffix}_search`, { drive_id, query, mode, limit }).then(res => res.data);
+  }
+
 }
 
 export const driveFmc = new DriveFmc();